@@ -0,0 +1,231 @@
+// Updates the user decided to skip and images they snoozed, so they aren't reported again until something newer comes out
+
+use once_cell::sync::OnceCell;
+use redb::{Database, ReadableTable, TableDefinition, TableError};
+
+use crate::{
+    data_store,
+    structs::{acknowledgement::Acknowledgement, update::Update},
+    utils::time::timestamp,
+    Context,
+};
+
+/// Acknowledgements indexed by the key generated by `key`. Values are JSON-serialized `Acknowledgement`s.
+const ACKNOWLEDGEMENTS: TableDefinition<&str, &str> = TableDefinition::new("acknowledgements");
+
+static STORE: OnceCell<Option<Acknowledgements>> = OnceCell::new();
+
+pub struct Acknowledgements {
+    db: Database,
+    ctx: Context,
+}
+
+impl Acknowledgements {
+    /// Returns the store, opening it the first time. Returns `None` if the database can't be opened, in which case all updates are reported.
+    pub fn get(ctx: &Context) -> Option<&'static Self> {
+        STORE.get_or_init(|| Self::open(ctx)).as_ref()
+    }
+
+    fn open(ctx: &Context) -> Option<Self> {
+        let db = data_store::open(
+            "acknowledgements.redb",
+            "acknowledged updates will be reported",
+            ctx,
+        )?;
+        Some(Self {
+            db,
+            ctx: ctx.clone(),
+        })
+    }
+
+    /// Stops reporting the update to `version` (a tag or digest). Newer versions are reported again. Returns whether the acknowledgement was saved.
+    pub fn acknowledge(&self, server: Option<&str>, reference: &str, version: &str) -> bool {
+        self.saved(self.modify(server, reference, |acknowledgement| {
+            acknowledgement.version = Some(version.to_string())
+        }))
+    }
+
+    /// Stops reporting any update for the image until `until` (a Unix timestamp in milliseconds). Returns whether the snooze was saved.
+    pub fn snooze(&self, server: Option<&str>, reference: &str, until: u64) -> bool {
+        self.saved(self.modify(server, reference, |acknowledgement| {
+            acknowledgement.until = Some(until)
+        }))
+    }
+
+    /// Reports the image's updates again. Returns whether the acknowledgement was removed.
+    pub fn remove(&self, server: Option<&str>, reference: &str) -> bool {
+        self.saved(self.try_remove(server, reference))
+    }
+
+    fn saved(&self, result: Result<(), redb::Error>) -> bool {
+        if let Err(e) = &result {
+            self.ctx
+                .logger
+                .warn(format!("Failed to save acknowledgement: {}", e));
+        }
+        result.is_ok()
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn try_remove(&self, server: Option<&str>, reference: &str) -> Result<(), redb::Error> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(ACKNOWLEDGEMENTS)?;
+            table.remove(key(server, reference).as_str())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn modify(
+        &self,
+        server: Option<&str>,
+        reference: &str,
+        f: impl FnOnce(&mut Acknowledgement),
+    ) -> Result<(), redb::Error> {
+        let key = key(server, reference);
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(ACKNOWLEDGEMENTS)?;
+            let mut acknowledgement = table
+                .get(key.as_str())?
+                .and_then(|value| serde_json::from_str(value.value()).ok())
+                .unwrap_or_default();
+            f(&mut acknowledgement);
+            table.insert(
+                key.as_str(),
+                serde_json::to_string(&acknowledgement).unwrap().as_str(),
+            )?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Marks the updates which were acknowledged and forgets acknowledgements that no longer apply, so a newer version is reported even if it's later acknowledged too.
+    /// Updates from remote servers keep their own acknowledgements, unless they were acknowledged here.
+    pub fn apply(&self, updates: &mut [Update]) {
+        if let Err(e) = self.try_apply(updates) {
+            self.ctx
+                .logger
+                .warn(format!("Failed to read acknowledged updates: {}", e));
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn try_apply(&self, updates: &mut [Update]) -> Result<(), redb::Error> {
+        let now = timestamp();
+        let mut stale = Vec::new();
+        {
+            let txn = self.db.begin_read()?;
+            let table = match txn.open_table(ACKNOWLEDGEMENTS) {
+                Ok(table) => Some(table),
+                Err(TableError::TableDoesNotExist(_)) => None,
+                Err(e) => return Err(e.into()),
+            };
+            for update in updates.iter_mut() {
+                let key = key(update.server.as_deref(), &update.reference);
+                let stored = match &table {
+                    Some(table) => table.get(key.as_str())?.and_then(|value| {
+                        serde_json::from_str::<Acknowledgement>(value.value()).ok()
+                    }),
+                    None => None,
+                };
+                let acknowledgement = match stored {
+                    Some(acknowledgement) if acknowledgement.applies_to(update, now) => {
+                        Some(acknowledgement)
+                    }
+                    // Images which couldn't be checked keep their acknowledgement for the next check
+                    Some(_) => {
+                        if update.result.has_update.is_some() {
+                            stale.push(key);
+                        }
+                        None
+                    }
+                    None if !update.is_remote() => None,
+                    None => update.acknowledgement.clone(),
+                };
+                update.set_acknowledgement(acknowledgement);
+            }
+        }
+        if !stale.is_empty() {
+            let txn = self.db.begin_write()?;
+            {
+                let mut table = txn.open_table(ACKNOWLEDGEMENTS)?;
+                for key in &stale {
+                    table.remove(key.as_str())?;
+                }
+            }
+            txn.commit()?;
+        }
+        Ok(())
+    }
+}
+
+/// Generates a key that uniquely identifies an image, including the server it's on
+fn key(server: Option<&str>, reference: &str) -> String {
+    format!("{}\0{}", server.unwrap_or(""), reference)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        logging::Logger,
+        structs::{status::Status, update::fixtures::create_digest_update},
+    };
+
+    use super::*;
+
+    /// Test that acknowledged updates are marked until a newer version appears, that snoozes cover every version and that remote servers' acknowledgements are kept
+    #[test]
+    fn acknowledgements() {
+        let directory =
+            std::env::temp_dir().join(format!("cup-acknowledgements-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let mut config = Config::new();
+        config.data_directory = Some(directory.to_str().unwrap().to_string());
+        let ctx = Context {
+            config,
+            logger: Logger::new(false, true),
+        };
+        let acknowledgements = Acknowledgements::open(&ctx).unwrap();
+        let statuses = |updates: &mut [Update]| {
+            acknowledgements.apply(updates);
+            updates
+                .iter()
+                .map(|update| update.get_status())
+                .collect::<Vec<Status>>()
+        };
+
+        let mut remote = create_digest_update("busybox", "sha256:1");
+        remote.server = Some(String::from("remote"));
+        remote.acknowledgement = Some(Acknowledgement {
+            version: Some(String::from("sha256:1")),
+            until: None,
+        });
+        let mut updates = vec![create_digest_update("alpine", "sha256:1"), remote];
+        assert!(acknowledgements.acknowledge(None, "alpine", "sha256:1"));
+        assert_eq!(
+            statuses(&mut updates),
+            vec![Status::Acknowledged, Status::Acknowledged]
+        );
+
+        // A newer version is reported and the acknowledgement is forgotten, so going back doesn't hide it again
+        let mut newer = vec![create_digest_update("alpine", "sha256:2")];
+        assert_eq!(statuses(&mut newer), vec![Status::UpdateAvailable]);
+        assert_eq!(statuses(&mut updates[..1]), vec![Status::UpdateAvailable]);
+
+        assert!(acknowledgements.snooze(None, "alpine", timestamp() + 60_000));
+        assert_eq!(statuses(&mut newer), vec![Status::Acknowledged]);
+        assert!(acknowledgements.snooze(None, "alpine", timestamp() - 60_000));
+        assert_eq!(statuses(&mut newer), vec![Status::UpdateAvailable]);
+
+        assert!(acknowledgements.acknowledge(None, "alpine", "sha256:2"));
+        assert!(acknowledgements.remove(None, "alpine"));
+        assert_eq!(statuses(&mut newer), vec![Status::UpdateAvailable]);
+
+        drop(acknowledgements);
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,547 @@
+// Applies updates by pulling the new image and recreating the containers that use it
+
+use std::{collections::hash_map::Entry, time::Duration};
+
+use bollard::{
+    container::{
+        Config, CreateContainerOptions, NetworkingConfig, RemoveContainerOptions,
+        RenameContainerOptions, StartContainerOptions, StopContainerOptions,
+    },
+    models::{ContainerInspectResponse, EndpointSettings, HealthStatusEnum, ImageConfig},
+    network::ConnectNetworkOptions,
+    Docker,
+};
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+
+use crate::{
+    runtime::{
+        docker::{get_containers_using, pull_image, DockerRuntime},
+        docker_client,
+    },
+    structs::update::{Update, UpdateInfo},
+    utils::{
+        reference::with_tag,
+        time::{elapsed, now},
+    },
+    Context,
+};
+
+#[derive(Serialize)]
+pub struct AppliedUpdate {
+    pub reference: String,
+    /// The Docker host the image is on, if it isn't the local one
+    pub host: Option<String>,
+    pub new_reference: String,
+    /// Set if the update couldn't be applied at all (e.g. pulling failed)
+    pub error: Option<String>,
+    pub containers: Vec<ContainerUpdate>,
+}
+
+#[derive(Serialize)]
+pub struct ContainerUpdate {
+    pub name: String,
+    pub result: ContainerUpdateResult,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerUpdateResult {
+    Updated,
+    RolledBack,
+    Failed,
+}
+
+impl ContainerUpdate {
+    fn new(name: &str, result: ContainerUpdateResult, error: Option<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            result,
+            error,
+        }
+    }
+}
+
+impl AppliedUpdate {
+    fn new(update: &Update) -> Self {
+        Self {
+            reference: update.reference.clone(),
+            host: update.host.clone(),
+            new_reference: new_reference(update),
+            error: None,
+            containers: Vec::new(),
+        }
+    }
+}
+
+/// Returns the updates that can be applied to the containers on this machine or its hosts. If `references` is Some, only updates to those images are returned.
+pub fn applicable<'a>(updates: &'a [Update], references: &Option<Vec<String>>) -> Vec<&'a Update> {
+    updates
+        .iter()
+        .filter(|update| {
+            !update.is_remote() // We can't update another server's containers
+                && update.get_status().to_option_bool() == Some(true) // Acknowledged updates are skipped
+                && match references {
+                    Some(refs) => refs.contains(&update.reference),
+                    None => true,
+                }
+        })
+        .collect()
+}
+
+/// Pulls the new version of each image and recreates the containers using it. Images are updated one at a time, so a bad update doesn't take down everything at once.
+pub async fn apply_updates(updates: &[&Update], ctx: &Context) -> Vec<AppliedUpdate> {
+    // Each host is only connected to once, no matter how many of its images are updated
+    let mut runtimes: FxHashMap<Option<&str>, Result<DockerRuntime, String>> = FxHashMap::default();
+    let mut applied = Vec::with_capacity(updates.len());
+    for update in updates {
+        let host = update.host.as_deref();
+        if let Entry::Vacant(entry) = runtimes.entry(host) {
+            entry.insert(connect(host, ctx).await);
+        }
+        applied.push(match &runtimes[&host] {
+            Ok(runtime) => apply_update(update, runtime.client(), ctx).await,
+            Err(e) => AppliedUpdate {
+                error: Some(e.clone()),
+                ..AppliedUpdate::new(update)
+            },
+        });
+    }
+    applied
+}
+
+/// Connects to the Docker host an image was found on, or to the local daemon if `host` is None
+async fn connect(host: Option<&str>, ctx: &Context) -> Result<DockerRuntime, String> {
+    match host {
+        Some(name) => match ctx.config.hosts.get(name) {
+            Some(config) => DockerRuntime::for_host(name, config)
+                .await
+                .map_err(|e| format!("Failed to connect to host {}: {}", name, e)),
+            None => Err(format!("Host {} isn't configured", name)),
+        },
+        None => docker_client(ctx).map(DockerRuntime::from),
+    }
+}
+
+/// The reference the image will have after updating
+fn new_reference(update: &Update) -> String {
+    match &update.result.info {
+        UpdateInfo::Version(info) => with_tag(&update.reference, &info.new_tag),
+        _ => update.reference.clone(),
+    }
+}
+
+async fn apply_update(update: &Update, client: &Docker, ctx: &Context) -> AppliedUpdate {
+    let mut applied = AppliedUpdate::new(update);
+    // This has to happen before pulling, since pulling a new digest moves the tag and containers would then show the old image's ID instead
+    let containers = match get_containers_using(client, &update.reference).await {
+        Ok(containers) => containers,
+        Err(e) => {
+            applied.error = Some(e);
+            return applied;
+        }
+    };
+    ctx.logger
+        .info(format!("Pulling {}", applied.new_reference));
+    if let Err(e) = pull_image(client, &applied.new_reference, ctx).await {
+        applied.error = Some(format!("Failed to pull {}: {}", applied.new_reference, e));
+        return applied;
+    }
+    for id in containers {
+        let container = recreate_container(client, &id, &applied.new_reference, ctx).await;
+        applied.containers.push(container);
+    }
+    applied
+}
+
+/// Replaces a container with one created from `image` and the same configuration. If the new container fails to start or doesn't become healthy, the old one is restored.
+async fn recreate_container(
+    client: &Docker,
+    id: &str,
+    image: &str,
+    ctx: &Context,
+) -> ContainerUpdate {
+    let inspect = match client.inspect_container(id, None).await {
+        Ok(inspect) => inspect,
+        Err(e) => {
+            return ContainerUpdate::new(
+                id,
+                ContainerUpdateResult::Failed,
+                Some(format!("Failed to inspect container: {}", e)),
+            )
+        }
+    };
+    let name = inspect
+        .name
+        .as_deref()
+        .unwrap_or(id)
+        .trim_start_matches('/')
+        .to_string();
+    let running = inspect
+        .state
+        .as_ref()
+        .and_then(|state| state.running)
+        .unwrap_or(false);
+    let image_config = match &inspect.image {
+        Some(old_image) => client
+            .inspect_image(old_image)
+            .await
+            .ok()
+            .and_then(|image| image.config),
+        None => None,
+    };
+    let (config, networks) = container_config(&inspect, image_config, image);
+    ctx.logger.info(format!("Recreating container {}", name));
+
+    if running {
+        if let Err(e) = client
+            .stop_container(id, None::<StopContainerOptions>)
+            .await
+        {
+            return ContainerUpdate::new(
+                &name,
+                ContainerUpdateResult::Failed,
+                Some(format!("Failed to stop container: {}", e)),
+            );
+        }
+    }
+    // The old container is kept around under a different name until we know the new one works
+    let backup_name = format!("{}-cup-old", name);
+    if let Err(e) = client
+        .rename_container(id, RenameContainerOptions { name: &backup_name })
+        .await
+    {
+        let reason = format!("Failed to rename container: {}", e);
+        return rolled_back(
+            &name,
+            reason,
+            rollback(client, None, id, &name, running, false).await,
+        );
+    }
+
+    let new_id = match client
+        .create_container(
+            Some(CreateContainerOptions {
+                name: name.as_str(),
+                platform: None,
+            }),
+            config,
+        )
+        .await
+    {
+        Ok(response) => response.id,
+        Err(e) => {
+            let reason = format!("Failed to create container: {}", e);
+            return rolled_back(
+                &name,
+                reason,
+                rollback(client, None, id, &name, running, true).await,
+            );
+        }
+    };
+    let result = start_container(client, &new_id, networks, running, ctx).await;
+    if let Err(reason) = result {
+        return rolled_back(
+            &name,
+            reason,
+            rollback(client, Some(&new_id), id, &name, running, true).await,
+        );
+    }
+
+    match client
+        .remove_container(id, None::<RemoveContainerOptions>)
+        .await
+    {
+        Ok(()) => ContainerUpdate::new(&name, ContainerUpdateResult::Updated, None),
+        Err(e) => ContainerUpdate::new(
+            &name,
+            ContainerUpdateResult::Updated,
+            Some(format!(
+                "Failed to remove old container {}: {}",
+                backup_name, e
+            )),
+        ),
+    }
+}
+
+/// Connects a newly created container to its networks and, if the container it replaces was running, starts it and waits for it to become healthy
+async fn start_container(
+    client: &Docker,
+    id: &str,
+    networks: Vec<(String, EndpointSettings)>,
+    running: bool,
+    ctx: &Context,
+) -> Result<(), String> {
+    for (network, endpoint_config) in networks {
+        client
+            .connect_network(
+                &network,
+                ConnectNetworkOptions {
+                    container: id,
+                    endpoint_config,
+                },
+            )
+            .await
+            .map_err(|e| format!("Failed to connect container to network {}: {}", network, e))?;
+    }
+    if !running {
+        return Ok(());
+    }
+    client
+        .start_container(id, None::<StartContainerOptions<String>>)
+        .await
+        .map_err(|e| format!("Failed to start container: {}", e))?;
+    wait_until_healthy(client, id, ctx.config.apply.healthcheck_timeout).await
+}
+
+/// Waits until a container's healthcheck passes. Containers without a healthcheck only need to be running.
+async fn wait_until_healthy(client: &Docker, id: &str, timeout: u64) -> Result<(), String> {
+    let start = now();
+    loop {
+        let state = client
+            .inspect_container(id, None)
+            .await
+            .map_err(|e| format!("Failed to inspect new container: {}", e))?
+            .state
+            .unwrap_or_default();
+        if state.running != Some(true) {
+            return Err(format!(
+                "New container exited with code {}",
+                state.exit_code.unwrap_or_default()
+            ));
+        }
+        match state.health.and_then(|health| health.status) {
+            Some(HealthStatusEnum::STARTING) => (),
+            Some(HealthStatusEnum::UNHEALTHY) => {
+                return Err(String::from("New container is unhealthy"))
+            }
+            _ => return Ok(()),
+        }
+        if elapsed(start) as u64 >= timeout * 1000 {
+            return Err(format!(
+                "New container didn't become healthy within {}s",
+                timeout
+            ));
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+/// Removes the new container (if it was created) and brings back the old one
+async fn rollback(
+    client: &Docker,
+    new_id: Option<&str>,
+    old_id: &str,
+    name: &str,
+    running: bool,
+    renamed: bool,
+) -> Result<(), String> {
+    if let Some(new_id) = new_id {
+        client
+            .remove_container(
+                new_id,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await
+            .map_err(|e| format!("Failed to remove new container: {}", e))?;
+    }
+    if renamed {
+        client
+            .rename_container(old_id, RenameContainerOptions { name })
+            .await
+            .map_err(|e| format!("Failed to rename old container: {}", e))?;
+    }
+    if running {
+        client
+            .start_container(old_id, None::<StartContainerOptions<String>>)
+            .await
+            .map_err(|e| format!("Failed to start old container: {}", e))?;
+    }
+    Ok(())
+}
+
+fn rolled_back(name: &str, reason: String, rollback: Result<(), String>) -> ContainerUpdate {
+    match rollback {
+        Ok(()) => ContainerUpdate::new(name, ContainerUpdateResult::RolledBack, Some(reason)),
+        Err(e) => ContainerUpdate::new(
+            name,
+            ContainerUpdateResult::Failed,
+            Some(format!("{}. Rollback failed: {}", reason, e)),
+        ),
+    }
+}
+
+/// Builds the config of the new container from the old one's. Returns it along with the networks the container should be connected to after it's created, since older Docker versions only accept one network on creation.
+fn container_config(
+    inspect: &ContainerInspectResponse,
+    image_config: Option<ImageConfig>,
+    image: &str,
+) -> (Config<String>, Vec<(String, EndpointSettings)>) {
+    let id = inspect.id.as_deref().unwrap_or_default();
+    let mut config: Config<String> = inspect.config.clone().unwrap_or_default().into();
+    config.image = Some(image.to_string());
+    // Docker uses the short ID as the hostname if one isn't set, so we should let it generate a new one
+    if config
+        .hostname
+        .as_ref()
+        .is_some_and(|hostname| id.starts_with(hostname.as_str()))
+    {
+        config.hostname = None;
+    }
+    if let Some(image_config) = image_config {
+        strip_image_defaults(&mut config, image_config);
+    }
+    config.host_config = inspect.host_config.clone();
+
+    let network_mode = inspect
+        .host_config
+        .as_ref()
+        .and_then(|host_config| host_config.network_mode.clone())
+        .unwrap_or_default();
+    let primary_network = match network_mode.as_str() {
+        "default" => "bridge",
+        network_mode => network_mode,
+    };
+    let mut networks = Vec::new();
+    let endpoints = inspect
+        .network_settings
+        .as_ref()
+        .and_then(|network_settings| network_settings.networks.clone())
+        .unwrap_or_default();
+    for (network, endpoint) in endpoints {
+        let endpoint = EndpointSettings {
+            ipam_config: endpoint.ipam_config,
+            links: endpoint.links,
+            // The container's old short ID is added as an alias automatically
+            aliases: endpoint.aliases.map(|aliases| {
+                aliases
+                    .into_iter()
+                    .filter(|alias| !id.starts_with(alias.as_str()))
+                    .collect()
+            }),
+            driver_opts: endpoint.driver_opts,
+            ..Default::default()
+        };
+        if network == primary_network {
+            config.networking_config = Some(NetworkingConfig {
+                endpoints_config: [(network, endpoint)].into_iter().collect(),
+            });
+        } else if !network_mode.starts_with("container:") && network_mode != "host" {
+            networks.push((network, endpoint));
+        }
+    }
+    (config, networks)
+}
+
+/// Removes settings the old container inherited from its image, so the new image's defaults are used instead. Otherwise, something like a `PG_VERSION` environment variable would stay behind after updating.
+fn strip_image_defaults(config: &mut Config<String>, image: ImageConfig) {
+    if let (Some(env), Some(image_env)) = (&mut config.env, &image.env) {
+        env.retain(|var| !image_env.contains(var));
+    }
+    if let (Some(labels), Some(image_labels)) = (&mut config.labels, &image.labels) {
+        labels.retain(|key, value| image_labels.get(key) != Some(value));
+    }
+    if let (Some(ports), Some(image_ports)) = (&mut config.exposed_ports, &image.exposed_ports) {
+        ports.retain(|port, _| !image_ports.contains_key(port));
+    }
+    if let (Some(volumes), Some(image_volumes)) = (&mut config.volumes, &image.volumes) {
+        volumes.retain(|volume, _| !image_volumes.contains_key(volume));
+    }
+    if config.cmd == image.cmd {
+        config.cmd = None;
+    }
+    if config.entrypoint == image.entrypoint {
+        config.entrypoint = None;
+    }
+    if config.working_dir == image.working_dir {
+        config.working_dir = None;
+    }
+    if config.user == image.user {
+        config.user = None;
+    }
+    if config.healthcheck == image.healthcheck {
+        config.healthcheck = None;
+    }
+    if config.stop_signal == image.stop_signal {
+        config.stop_signal = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bollard::models::{ContainerConfig, HostConfig, NetworkSettings};
+
+    use super::*;
+
+    /// Test that the new container keeps the old one's settings, but not the ones inherited from the old image or generated by Docker
+    #[test]
+    fn config() {
+        let inspect = ContainerInspectResponse {
+            id: Some(String::from("0123456789abcdef")),
+            config: Some(ContainerConfig {
+                hostname: Some(String::from("0123456789ab")),
+                image: Some(String::from("postgres:15")),
+                env: Some(vec![
+                    String::from("POSTGRES_PASSWORD=secret"),
+                    String::from("PG_VERSION=15.10"),
+                ]),
+                cmd: Some(vec![String::from("postgres")]),
+                labels: Some(HashMap::from([(
+                    String::from("com.example.backup"),
+                    String::from("true"),
+                )])),
+                ..Default::default()
+            }),
+            host_config: Some(HostConfig {
+                network_mode: Some(String::from("app")),
+                ..Default::default()
+            }),
+            network_settings: Some(NetworkSettings {
+                networks: Some(HashMap::from([
+                    (
+                        String::from("app"),
+                        EndpointSettings {
+                            aliases: Some(vec![String::from("db"), String::from("0123456789ab")]),
+                            ip_address: Some(String::from("172.18.0.2")),
+                            ..Default::default()
+                        },
+                    ),
+                    (String::from("monitoring"), EndpointSettings::default()),
+                ])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let image_config = ImageConfig {
+            env: Some(vec![String::from("PG_VERSION=15.10")]),
+            cmd: Some(vec![String::from("postgres")]),
+            ..Default::default()
+        };
+        let (config, networks) = container_config(&inspect, Some(image_config), "postgres:17");
+        assert_eq!(config.image.as_deref(), Some("postgres:17"));
+        assert_eq!(config.hostname, None);
+        assert_eq!(
+            config.env,
+            Some(vec![String::from("POSTGRES_PASSWORD=secret")])
+        );
+        assert_eq!(config.cmd, None);
+        assert_eq!(config.labels.unwrap().len(), 1);
+        assert_eq!(
+            config.networking_config.unwrap().endpoints_config["app"],
+            EndpointSettings {
+                aliases: Some(vec![String::from("db")]),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            networks,
+            vec![(String::from("monitoring"), EndpointSettings::default())]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+// On-disk cache of registry responses, so paginated tag lists and the details of updates don't have to be downloaded again on every check
+
+use once_cell::sync::OnceCell;
+use redb::{Database, TableDefinition};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+
+use crate::{data_store, structs::details::ImageDetails, utils::time::timestamp, Context};
+
+/// Cached responses indexed by the key generated by `key`. Values are JSON-serialized `CachedResponse`s.
+const RESPONSES: TableDefinition<&str, &str> = TableDefinition::new("responses");
+
+/// Details of remote images indexed by the digest of their manifest. Values are JSON-serialized `CachedDetails`. Manifests never change, so these don't need to be revalidated.
+const DETAILS: TableDefinition<&str, &str> = TableDefinition::new("details");
+
+/// Responses which haven't been used for this long are removed when the cache is opened
+const MAX_AGE_MS: u64 = 30 * 24 * 60 * 60 * 1000;
+
+static CACHE: OnceCell<Option<ResponseCache>> = OnceCell::new();
+
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct CachedResponse {
+    pub body: String,
+    /// The `Link` header, pointing to the next page of results
+    pub link: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Unix timestamp (in milliseconds) the response was received or last revalidated at
+    pub stored_at: u64,
+}
+
+impl CachedResponse {
+    /// Whether the response is younger than `ttl` (in seconds) and can be used without asking the registry
+    pub fn is_fresh(&self, ttl: u64) -> bool {
+        timestamp().saturating_sub(self.stored_at) < ttl * 1000
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedDetails {
+    details: ImageDetails,
+    /// Unix timestamp (in milliseconds) the details were fetched at
+    stored_at: u64,
+}
+
+pub struct ResponseCache {
+    db: Database,
+}
+
+impl ResponseCache {
+    /// Returns the cache, opening it the first time. Returns `None` if caching is disabled or the database can't be opened, in which case every request goes to the registry.
+    pub fn get(ctx: &Context) -> Option<&'static Self> {
+        CACHE.get_or_init(|| Self::open(ctx)).as_ref()
+    }
+
+    fn open(ctx: &Context) -> Option<Self> {
+        if !ctx.config.cache.enabled {
+            return None;
+        }
+        let cache = Self {
+            db: data_store::open("cache.redb", "registry responses will not be cached", ctx)?,
+        };
+        if let Err(e) = cache.remove_old() {
+            ctx.logger
+                .warn(format!("Failed to clean up response cache: {}", e));
+        }
+        Some(cache)
+    }
+
+    /// Returns the response stored for a key, if there is one
+    pub fn load(&self, key: &str) -> Option<CachedResponse> {
+        let txn = self.db.begin_read().ok()?;
+        let table = txn.open_table(RESPONSES).ok()?;
+        let value = table.get(key).ok()??;
+        serde_json::from_str(value.value()).ok()
+    }
+
+    /// Stores a response. Failing to do so isn't a problem, the response just won't be cached.
+    pub fn store(&self, key: &str, response: &CachedResponse) {
+        let _ = self.try_store(key, response);
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn try_store(&self, key: &str, response: &CachedResponse) -> Result<(), redb::Error> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(RESPONSES)?;
+            table.insert(key, serde_json::to_string(response).unwrap().as_str())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Returns the details stored for the image with the given manifest digest, if there are any
+    pub fn load_details(&self, digest: &str) -> Option<ImageDetails> {
+        let txn = self.db.begin_read().ok()?;
+        let table = txn.open_table(DETAILS).ok()?;
+        let value = table.get(digest).ok()??;
+        serde_json::from_str::<CachedDetails>(value.value())
+            .ok()
+            .map(|cached| cached.details)
+    }
+
+    /// Stores the details of the image with the given manifest digest. Like responses, they're fetched again if this fails.
+    pub fn store_details(&self, digest: &str, details: &ImageDetails) {
+        let _ = self.try_store_details(digest, details);
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn try_store_details(&self, digest: &str, details: &ImageDetails) -> Result<(), redb::Error> {
+        let cached = CachedDetails {
+            details: details.clone(),
+            stored_at: timestamp(),
+        };
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(DETAILS)?;
+            table.insert(digest, serde_json::to_string(&cached).unwrap().as_str())?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn remove_old(&self) -> Result<(), redb::Error> {
+        let cutoff = timestamp().saturating_sub(MAX_AGE_MS);
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(RESPONSES)?;
+            table.retain(|_, value| {
+                serde_json::from_str::<CachedResponse>(value)
+                    .is_ok_and(|response| response.stored_at >= cutoff)
+            })?;
+            let mut table = txn.open_table(DETAILS)?;
+            table.retain(|_, value| {
+                serde_json::from_str::<CachedDetails>(value)
+                    .is_ok_and(|cached| cached.stored_at >= cutoff)
+            })?;
+        }
+        txn.commit()?;
+        Ok(())
+    }
+}
+
+/// Generates the key a response is stored under. Responses may differ depending on who is asking, so the key includes the authentication scope: nothing for anonymous requests, the scheme for tokens (which are scoped to the repository in the URL and change every time) and a digest of the credentials for Basic authentication.
+pub fn key(url: &str, authorization: Option<&str>) -> String {
+    let scope = match authorization {
+        None => String::new(),
+        Some(authorization) => match authorization.split_once(' ') {
+            Some(("Basic", credentials)) => digest::digest(&digest::SHA256, credentials.as_bytes())
+                .as_ref()
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+            Some((scheme, _)) => scheme.to_lowercase(),
+            None => String::new(),
+        },
+    };
+    format!("{} {}", scope, url)
+}
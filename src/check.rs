@@ -3,12 +3,20 @@ use itertools::Itertools;
 use rustc_hash::{FxHashMap, FxHashSet};
 
 use crate::{
-    docker::{get_images_from_docker_daemon, get_in_use_images},
-    http::Client,
-    registry::{check_auth, get_token},
-    structs::{image::Image, update::Update},
+    acknowledgements::Acknowledgements,
+    error::Error,
+    http::{rate_limit, Client},
+    registry::{authorize, refresh_rate_limit},
+    releases::{get_releases, Repository},
+    runtime::{self, kubernetes::get_kubernetes_images, Runtime},
+    structs::{
+        details::ImageDetails,
+        image::{CheckKey, Image},
+        status::Status,
+        update::Update,
+    },
     utils::{
-        reference::split,
+        reference::{split, DEFAULT_REGISTRY},
         request::{get_response_body, parse_json},
     },
     Context,
@@ -49,7 +57,13 @@ async fn get_remote_updates(ctx: &Context, client: &Client, refresh: bool) -> Ve
                         ctx.logger.warn(format!("GET {}: Failed to fetch updates from server. Server returned invalid response code: {}", json_url, response.status()));
                         return Vec::new();
                     }
-                    let json = parse_json(&get_response_body(response).await);
+                    let json = match get_response_body(response).await.and_then(|body| parse_json(&body)) {
+                        Ok(json) => json,
+                        Err(e) => {
+                            ctx.logger.warn(format!("GET {}: Failed to fetch updates from server. {}", json_url, e));
+                            return Vec::new();
+                        }
+                    };
                     ctx.logger.debug(format!("JSON response for {}: {}", name, json));
                     if let Some(updates) = json["images"].as_array() {
                         let mut server_updates: Vec<Update> = updates
@@ -59,6 +73,8 @@ async fn get_remote_updates(ctx: &Context, client: &Client, refresh: bool) -> Ve
                         // Add server origin to each image
                         for update in &mut server_updates {
                             update.server = Some(name.clone());
+                            // The server's own hosts are only reachable from there
+                            update.host = None;
                             update.status = update.get_status();
                         }
                         ctx.logger.debug(format!("Updates for {}: {:#?}", name, server_updates));
@@ -82,6 +98,63 @@ async fn get_remote_updates(ctx: &Context, client: &Client, refresh: bool) -> Ve
     remote_images
 }
 
+/// Returns the images available in a runtime, along with which containers and Compose services use them
+async fn get_runtime_images(
+    runtime: &dyn Runtime,
+    ctx: &Context,
+    references: &Option<Vec<String>>,
+) -> Result<Vec<Image>, Error> {
+    let mut images = runtime.images(ctx, references).await?;
+    let in_use_images = runtime.in_use_images(ctx).await?;
+    ctx.logger
+        .debug(format!("Found {} images in use", in_use_images.len()));
+
+    // Complete in_use and compose fields
+    images.iter_mut().for_each(|image| {
+        if let Some(services) = in_use_images.get(&image.reference) {
+            image.in_use = true;
+            image.compose = services.clone();
+        }
+    });
+    Ok(images)
+}
+
+/// Fetches images from the configured Docker hosts. Hosts which can't be reached or fail to list their images are skipped.
+#[cfg(feature = "docker")]
+async fn get_host_images(ctx: &Context, references: &Option<Vec<String>>) -> Vec<Image> {
+    use crate::runtime::docker::DockerRuntime;
+
+    let handles = ctx.config.hosts.iter().map(|(name, host)| async move {
+        let runtime = match DockerRuntime::for_host(name, host).await {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                ctx.logger
+                    .warn(format!("Failed to connect to host {}: {}", name, e));
+                return Vec::new();
+            }
+        };
+        let mut images = match get_runtime_images(&runtime, ctx, references).await {
+            Ok(images) => images,
+            Err(e) => {
+                ctx.logger.warn(format!("Skipping host {}: {}", name, e));
+                return Vec::new();
+            }
+        };
+        for image in &mut images {
+            image.host = Some(name.clone());
+        }
+        images
+    });
+    join_all(handles).await.into_iter().flatten().collect()
+}
+
+#[cfg(not(feature = "docker"))]
+async fn get_host_images(ctx: &Context, _references: &Option<Vec<String>>) -> Vec<Image> {
+    ctx.logger
+        .warn("This build of Cup doesn't support the Docker API, so images on Docker hosts won't be checked");
+    Vec::new()
+}
+
 /// Returns a list of excluded tag prefixes for the given image.
 fn get_excluded_tags(image: &Image, ctx: &Context) -> Vec<String> {
     let image_name = image.reference.split(':').next().unwrap();
@@ -97,12 +170,46 @@ fn get_excluded_tags(image: &Image, ctx: &Context) -> Vec<String> {
         .collect()
 }
 
-/// Returns a list of updates for all images passed in.
+/// Adds the releases between the current and the latest version to images with a version update, from the forge hosting their source. Failing to get them doesn't affect the check.
+async fn add_releases(images: &mut [Image], ctx: &Context, client: &Client) {
+    let handles = images.iter_mut().filter_map(|image| {
+        if !matches!(
+            image.has_update(),
+            Status::UpdateMajor | Status::UpdateMinor | Status::UpdatePatch
+        ) {
+            return None;
+        }
+        let repository = Repository::from_source(image.source.as_deref()?, ctx)?;
+        let reference = &image.reference;
+        let version_info = image.version_info.as_mut()?;
+        Some(async move {
+            version_info.releases_url = Some(repository.releases_url());
+            match get_releases(
+                &repository,
+                &version_info.current_tag,
+                version_info.latest_remote_tag.as_ref().unwrap(),
+                ctx,
+                client,
+            )
+            .await
+            {
+                Ok(releases) => version_info.releases = releases,
+                Err(e) => ctx.logger.debug(format!(
+                    "Failed to get the releases of {}: {}",
+                    reference, e
+                )),
+            }
+        })
+    });
+    join_all(handles).await;
+}
+
+/// Returns a list of updates for all images passed in. Fails only if the local container runtime can't be read, errors with single images are reported in their results instead.
 pub async fn get_updates(
     references: &Option<Vec<String>>, // If a user requested _specific_ references to be checked, this will have a value
     refresh: bool,
     ctx: &Context,
-) -> Vec<Update> {
+) -> Result<Vec<Update>, Error> {
     let client = Client::new(ctx);
 
     // Merge references argument with references from config
@@ -118,21 +225,38 @@ pub async fn get_updates(
 
     // Get local images
     ctx.logger.debug("Retrieving images to be checked");
-    let mut images = get_images_from_docker_daemon(ctx, references).await;
-    let in_use_images = get_in_use_images(ctx).await;
-    ctx.logger
-        .debug(format!("Found {} images in use", in_use_images.len()));
-
-    // Complete in_use field
-    images.iter_mut().for_each(|image| {
-        if in_use_images.contains(&image.reference) {
-            image.in_use = true
+    let runtime = runtime::connect(ctx)?;
+    let mut images = match &runtime {
+        Some(runtime) => get_runtime_images(runtime.as_ref(), ctx, references).await?,
+        None => Vec::new(),
+    };
+    if ctx.config.kubernetes.enabled {
+        for image in get_kubernetes_images(ctx, references).await {
+            // The node's runtime may be the one running the pods
+            match images
+                .iter_mut()
+                .find(|local_image| local_image.reference == image.reference)
+            {
+                Some(local_image) => {
+                    local_image.in_use = true;
+                    local_image.workloads = image.workloads;
+                }
+                None => images.push(image),
+            }
         }
-    });
+    }
+    if !ctx.config.hosts.is_empty() {
+        ctx.logger.debug("Retrieving images from Docker hosts");
+        images.extend(get_host_images(ctx, references).await);
+    }
 
     // Add extra images from references
     if !all_references.is_empty() {
-        let image_refs: FxHashSet<&String> = images.iter().map(|image| &image.reference).collect();
+        let image_refs: FxHashSet<&String> = images
+            .iter()
+            .filter(|image| image.host.is_none())
+            .map(|image| &image.reference)
+            .collect();
         let extra = all_references
             .iter()
             .filter(|&reference| !image_refs.contains(reference))
@@ -179,37 +303,46 @@ pub async fn get_updates(
             .push(image);
     }
 
-    // Retrieve an authentication token (if required) for each registry.
-    let mut tokens: FxHashMap<&str, Option<String>> = FxHashMap::default();
+    // Retrieve an authentication token (if required) for each registry. The tokens are stored as the value of the Authorization header. If authentication fails, the error is reported for each of the registry's images.
+    let mut tokens: FxHashMap<&str, Result<Option<String>, Error>> = FxHashMap::default();
     for registry in registries.clone() {
-        let credentials = if let Some(registry_config) = ctx.config.registries.get(registry) {
-            &registry_config.authentication
-        } else {
-            &None
-        };
-        match check_auth(registry, ctx, &client).await {
-            Some(auth_url) => {
-                let token = get_token(
-                    image_map.get(registry).unwrap(),
-                    &auth_url,
-                    credentials,
-                    &client,
-                )
-                .await;
-                tokens.insert(registry, Some(token));
-            }
-            None => {
-                tokens.insert(registry, None);
-            }
-        }
+        let repositories: Vec<&str> = image_map
+            .get(registry)
+            .unwrap()
+            .iter()
+            .map(|image| image.parts.repository.as_str())
+            .collect();
+        let token = authorize(registry, &repositories, ctx, &client).await;
+        tokens.insert(registry, token);
     }
 
     ctx.logger.debug(format!("Tokens: {:?}", tokens));
 
+    // Registries with a rate limit are asked for their current quota first, so images whose check would exhaust it can be deferred to the next one. Docker Hub is always asked, since its limit is known even before it reports one.
+    for registry in &registries {
+        if rate_limit(registry).is_none() && registry.as_str() != DEFAULT_REGISTRY {
+            continue;
+        }
+        let registry_images = image_map.get(registry).unwrap();
+        let Ok(token) = tokens.get(registry.as_str()).unwrap() else {
+            continue;
+        };
+        refresh_rate_limit(registry_images[0], token.as_deref(), ctx, &client).await;
+        if let Some(limit) = rate_limit(registry) {
+            client.set_budget(registry, limit.budget());
+        }
+    }
+
     let mut handles = Vec::with_capacity(images.len());
+    let mut failed = Vec::new();
+    // Images with the same reference (e.g. on different hosts) only need to be checked once, unless the local images differ. The platform and the local image decide which manifest is compared and whether it changed.
+    let mut checked_keys: FxHashSet<CheckKey> = FxHashSet::default();
 
     // Loop through images check for updates
     for image in &images {
+        if !checked_keys.insert(image.check_key()) {
+            continue;
+        }
         let is_ignored = !registries.contains(&&image.parts.registry)
             || ctx
                 .config
@@ -218,15 +351,92 @@ pub async fn get_updates(
                 .iter()
                 .any(|item| image.reference.starts_with(item));
         if !is_ignored {
+            let token = match tokens.get(image.parts.registry.as_str()).unwrap() {
+                Ok(token) => token,
+                Err(error) => {
+                    failed.push(Image {
+                        error: Some(error.clone()),
+                        ..image.clone()
+                    });
+                    continue;
+                }
+            };
             let excluded_tags = get_excluded_tags(image, ctx);
-            let token = tokens.get(image.parts.registry.as_str()).unwrap();
             let future = image.check(token.as_deref(), ctx, &client, excluded_tags);
             handles.push(future);
         }
     }
     // Await all the futures
-    let images = join_all(handles).await;
-    let mut updates: Vec<Update> = images.iter().map(|image| image.to_update()).collect();
+    let mut checked_images = join_all(handles).await;
+    checked_images.extend(failed);
+    for registry in &registries {
+        let deferred = checked_images
+            .iter()
+            .filter(|image| image.deferred && &image.parts.registry == *registry)
+            .count();
+        if let (true, Some(limit)) = (deferred != 0, rate_limit(registry)) {
+            ctx.logger.warn(format!(
+                "The rate limit of {} is nearly exhausted ({} of {} requests remaining), {} of its images will be checked next time",
+                registry, limit.remaining, limit.limit, deferred
+            ));
+        }
+    }
+    if ctx.config.release_notes.enabled {
+        add_releases(&mut checked_images, ctx, &client).await;
+    }
+    // Describing a digest update needs the local image's contents too, which are only worth reading for the images that have one
+    let mut local_details: FxHashMap<&str, ImageDetails> = FxHashMap::default();
+    if let Some(runtime) = &runtime {
+        let references: Vec<&str> = checked_images
+            .iter()
+            .filter(|image| {
+                image
+                    .digest_info
+                    .as_ref()
+                    .is_some_and(|digest_info| digest_info.remote_details.is_some())
+            })
+            .map(|image| image.reference.as_str())
+            .collect();
+        let details = join_all(
+            references
+                .iter()
+                .map(|reference| runtime.details(reference)),
+        )
+        .await;
+        local_details.extend(
+            references
+                .into_iter()
+                .zip(details)
+                .filter_map(|(reference, details)| Some((reference, details?))),
+        );
+    }
+    let results: FxHashMap<CheckKey, &Image> = checked_images
+        .iter()
+        .map(|image| (image.check_key(), image))
+        .collect();
+    let mut updates: Vec<Update> = images
+        .iter()
+        .filter_map(|image| {
+            let result = results.get(&image.check_key())?;
+            // Only the registry's answer is shared, the rest depends on where the image was found
+            let mut image_result = (*result).clone();
+            image_result.in_use = image.in_use;
+            image_result.compose = image.compose.clone();
+            image_result.workloads = image.workloads.clone();
+            image_result.host = image.host.clone();
+            if let Some(digest_info) = &mut image_result.digest_info {
+                // Only the local runtime was asked
+                digest_info.local_details = match image.host {
+                    Some(_) => None,
+                    None => local_details.get(image.reference.as_str()).cloned(),
+                };
+            }
+            Some(image_result.to_update())
+        })
+        .collect();
     updates.extend_from_slice(&remote_updates);
-    updates
+    if let Some(acknowledgements) = Acknowledgements::get(ctx) {
+        acknowledgements.apply(&mut updates);
+    }
+    Ok(updates)
 }
@@ -0,0 +1,148 @@
+// Rewrites pinned image tags in Compose files when a new version is available
+
+use std::fs;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    structs::update::{Update, UpdateInfo},
+    utils::reference::{split, with_tag},
+    Context,
+};
+
+/// Matches `image:` keys in a Compose file. The value may be quoted and followed by a comment.
+static IMAGE_LINE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"^(\s*image:\s*)(["']?)([^"'\s#]+)(["']?)(.*)$"#).unwrap());
+
+/// Updates the image tags in the Compose files used by services with a version update available. Returns the files that were changed.
+pub fn write_compose_files(updates: &[Update], ctx: &Context) -> Vec<String> {
+    let mut changed: Vec<String> = new_tags(updates)
+        .into_iter()
+        .filter_map(|(file, tags)| {
+            let contents = match fs::read_to_string(file) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    ctx.logger
+                        .warn(format!("Failed to read Compose file {}: {}", file, e));
+                    return None;
+                }
+            };
+            let new_contents = rewrite(&contents, &tags)?;
+            match fs::write(file, new_contents) {
+                Ok(()) => Some(file.to_string()),
+                Err(e) => {
+                    ctx.logger
+                        .warn(format!("Failed to write Compose file {}: {}", file, e));
+                    None
+                }
+            }
+        })
+        .collect();
+    changed.sort_unstable();
+    changed
+}
+
+/// Groups the new tags of the version updates available by the Compose files of the services using them
+fn new_tags(updates: &[Update]) -> FxHashMap<&str, Vec<(&str, &str)>> {
+    // Each file is read and written only once, no matter how many of its images have updates
+    let mut files: FxHashMap<&str, Vec<(&str, &str)>> = FxHashMap::default();
+    // Compose files on hosts and other servers can't be edited from here, and acknowledged updates are skipped
+    for update in updates.iter().filter(|update| {
+        update.server.is_none() && update.get_status().to_option_bool() == Some(true)
+    }) {
+        if let UpdateInfo::Version(info) = &update.result.info {
+            for service in &update.compose {
+                for file in &service.config_files {
+                    files
+                        .entry(file)
+                        .or_default()
+                        .push((&update.reference, &info.new_tag));
+                }
+            }
+        }
+    }
+    files
+}
+
+/// Replaces the tag of every image in `contents` matching one of the references passed in with its new tag, keeping the rest of the file intact. Returns `None` if nothing changed.
+fn rewrite(contents: &str, tags: &[(&str, &str)]) -> Option<String> {
+    let mut changed = false;
+    let lines: Vec<String> = contents
+        .split('\n')
+        .map(|line| {
+            let captures = match IMAGE_LINE.captures(line) {
+                Some(captures) => captures,
+                None => return line.to_string(),
+            };
+            let image = &captures[3];
+            // Images with variables can't be resolved without the environment Compose was run with
+            if image.contains('$') {
+                return line.to_string();
+            }
+            match tags
+                .iter()
+                .find(|(reference, _)| split(reference) == split(image))
+            {
+                Some((_, tag)) => {
+                    changed = true;
+                    format!(
+                        "{}{}{}{}{}",
+                        &captures[1],
+                        &captures[2],
+                        with_tag(image, tag),
+                        &captures[4],
+                        &captures[5]
+                    )
+                }
+                None => line.to_string(),
+            }
+        })
+        .collect();
+    changed.then(|| lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{
+        acknowledgement::Acknowledgement, compose::ComposeService,
+        update::fixtures::create_version_update,
+    };
+
+    use super::*;
+
+    /// Test that only matching images are rewritten and that the file's formatting is preserved
+    #[test]
+    fn rewrite_tags() {
+        let contents = "services:\n  db:\n    image: \"docker.io/library/postgres:15\" # pinned\n  app:\n    image: ghcr.io/example/app:${TAG}\n  cache:\n    image: redis:7\n";
+        assert_eq!(
+            rewrite(contents, &[("postgres:15", "17")]).as_deref(),
+            Some("services:\n  db:\n    image: \"docker.io/library/postgres:17\" # pinned\n  app:\n    image: ghcr.io/example/app:${TAG}\n  cache:\n    image: redis:7\n")
+        );
+        assert_eq!(rewrite(contents, &[("mysql:8", "9")]), None);
+    }
+
+    /// Test that acknowledged updates don't have their tags rewritten
+    #[test]
+    fn acknowledged() {
+        let mut updates = vec![
+            create_version_update("postgres:15", "17"),
+            create_version_update("redis:7", "8"),
+        ];
+        for update in &mut updates {
+            update.compose = vec![ComposeService {
+                project: String::from("stack"),
+                service: update.reference.clone(),
+                config_files: vec![String::from("/stack/compose.yaml")],
+            }];
+        }
+        updates[1].set_acknowledgement(Some(Acknowledgement {
+            version: None,
+            until: Some(u64::MAX),
+        }));
+        let tags = new_tags(&updates);
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags["/stack/compose.yaml"], vec![("postgres:15", "17")]);
+    }
+}
@@ -1,3 +1,4 @@
+use regex::Regex;
 use rustc_hash::FxHashMap;
 use serde::Deserialize;
 use serde::Deserializer;
@@ -5,7 +6,10 @@ use std::env;
 use std::mem;
 use std::path::PathBuf;
 
-use crate::error;
+use crate::{
+    error,
+    structs::{scheme::CalverComponent, status::Status, version::Version},
+};
 
 // We can't assign `a` to `b` in the loop in `Config::load`, so we'll have to use swap. It looks ugly so now we have a macro for it.
 macro_rules! swap {
@@ -42,13 +46,278 @@ impl Default for UpdateType {
     }
 }
 
+/// The container runtime to get images from
+#[derive(Clone, Deserialize, Default, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeType {
+    #[default]
+    Auto,
+    Docker,
+    Podman,
+    Containerd,
+    /// Don't get local images, only check the references that are passed in or configured
+    None,
+}
+
+/// A cloud provider which issues short-lived credentials for its registries
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthProviderConfig {
+    Ecr(EcrConfig),
+    Gcp(GcpConfig),
+    Acr(AcrConfig),
+}
+
+/// Amazon ECR. Credentials are read from the standard `AWS_*` environment variables if not set here.
+#[derive(Clone, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct EcrConfig {
+    /// Defaults to the region in the registry's domain
+    pub region: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub session_token: Option<String>,
+    /// Overrides the URL of ECR's API
+    pub endpoint: Option<String>,
+}
+
+/// Google Artifact Registry and Container Registry
+#[derive(Clone, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct GcpConfig {
+    /// Path to a service account key. Defaults to `$GOOGLE_APPLICATION_CREDENTIALS`, or the metadata server if that isn't set either.
+    pub key_file: Option<String>,
+    /// Overrides the URL of the metadata server
+    pub endpoint: Option<String>,
+}
+
+/// Azure Container Registry, using a service principal
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AcrConfig {
+    pub tenant_id: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Overrides the URL of Microsoft Entra ID
+    #[serde(default)]
+    pub authority: Option<String>,
+    /// Overrides the URL the Entra ID token is exchanged at. Defaults to the registry.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+}
+
 #[derive(Clone, Deserialize, Default)]
 #[serde(deny_unknown_fields)]
 #[serde(default)]
 pub struct RegistryConfig {
     pub authentication: Option<String>,
+    pub provider: Option<AuthProviderConfig>,
     pub insecure: bool,
     pub ignore: bool,
+    /// How long (in seconds) cached tag lists are used without asking the registry whether they changed
+    pub cache_ttl: u64,
+    /// The maximum number of requests sent to the registry at once. 0 means no limit.
+    pub max_concurrent_requests: usize,
+    /// The maximum number of requests sent to the registry per second. 0 means no limit.
+    pub requests_per_second: f64,
+}
+
+/// Which versions an image may be updated to
+#[derive(Clone, Deserialize, Default, PartialEq, Debug)]
+#[serde(from = "String")]
+pub enum Channel {
+    /// Releases only
+    #[default]
+    Stable,
+    /// Releases and any pre-release
+    PreRelease,
+    /// Releases and pre-releases of a single channel (e.g. `beta`)
+    Named(String),
+}
+
+impl From<String> for Channel {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "stable" => Self::Stable,
+            "pre_release" => Self::PreRelease,
+            _ => Self::Named(value.to_lowercase()),
+        }
+    }
+}
+
+impl Channel {
+    pub fn allows(&self, version: &Version) -> bool {
+        match self {
+            _ if version.pre_release.is_none() => true,
+            Self::Stable => false,
+            Self::PreRelease => true,
+            Self::Named(name) => version.channel().as_ref() == Some(name),
+        }
+    }
+}
+
+/// The version scheme of an image's tags, used instead of the one detected from each tag
+#[derive(Clone, Deserialize, PartialEq, Debug)]
+#[serde(try_from = "String")]
+pub enum VersionScheme {
+    Semver,
+    /// Calendar versioning with the given pattern (e.g. `YYYY.0M.MICRO`), or a pattern detected from each tag if `None`
+    Calver(Option<Vec<CalverComponent>>),
+    Timestamp,
+    Build,
+}
+
+impl TryFrom<String> for VersionScheme {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "semver" => Ok(Self::Semver),
+            "calver" => Ok(Self::Calver(None)),
+            "timestamp" => Ok(Self::Timestamp),
+            "build" => Ok(Self::Build),
+            pattern => {
+                CalverComponent::parse_pattern(pattern).map(|pattern| Self::Calver(Some(pattern)))
+            }
+        }
+    }
+}
+
+/// A regex compiled when the config is loaded
+#[derive(Clone, Deserialize, Debug)]
+#[serde(try_from = "String")]
+pub struct ConfigRegex(pub Regex);
+
+impl TryFrom<String> for ConfigRegex {
+    type Error = regex::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Regex::new(&value).map(Self)
+    }
+}
+
+/// A glob (`*` matches any characters and `?` a single one) compiled to a regex matching the whole string
+#[derive(Clone, Deserialize, Debug)]
+#[serde(from = "String")]
+pub struct Glob(pub Regex);
+
+impl From<String> for Glob {
+    fn from(value: String) -> Self {
+        let pattern: String = value
+            .split('*')
+            .map(|part| {
+                part.split('?')
+                    .map(regex::escape)
+                    .collect::<Vec<String>>()
+                    .join(".")
+            })
+            .collect::<Vec<String>>()
+            .join(".*");
+        Self(Regex::new(&format!("^{}$", pattern)).unwrap())
+    }
+}
+
+/// Comma-separated comparisons a version has to satisfy, e.g. `>=14,<17`. Versions with fewer numbers match everything starting with them, so `<17` excludes all of 17.x.
+#[derive(Clone, Deserialize, Debug)]
+#[serde(try_from = "String")]
+pub struct VersionRange(Vec<(String, Version)>);
+
+impl TryFrom<String> for VersionRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value
+            .split(',')
+            .map(|comparison| {
+                let comparison = comparison.trim();
+                let version_start = comparison
+                    .find(|c: char| !matches!(c, '<' | '>' | '=' | '!'))
+                    .unwrap_or(comparison.len());
+                let (operator, version) = comparison.split_at(version_start);
+                if !["", "=", "!=", ">", ">=", "<", "<="].contains(&operator) {
+                    return Err(format!("Invalid operator {} in {}", operator, value));
+                }
+                match Version::from_tag(version.trim()) {
+                    Some((version, _)) => Ok((operator.to_string(), version)),
+                    None => Err(format!("Invalid version {} in {}", version, value)),
+                }
+            })
+            .collect::<Result<Vec<(String, Version)>, String>>()
+            .map(Self)
+    }
+}
+
+impl VersionRange {
+    pub fn matches(&self, version: &Version) -> bool {
+        self.0.iter().all(|(operator, bound)| {
+            let ordering = version.cmp(bound);
+            match operator.as_str() {
+                "" | "=" => ordering.is_eq(),
+                "!=" => ordering.is_ne(),
+                ">" => ordering.is_gt(),
+                ">=" => ordering.is_ge(),
+                "<" => ordering.is_lt(),
+                _ => ordering.is_le(),
+            }
+        })
+    }
+}
+
+/// Version update types an image policy can allow
+#[derive(Clone, Copy, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionUpdateType {
+    Major,
+    Minor,
+    Patch,
+}
+
+/// Rules for the versions an image can be updated to. All policies matching an image apply.
+#[derive(Clone, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct PolicyConfig {
+    /// Glob matched against the whole image reference
+    pub image: Option<Glob>,
+    /// Regex matched against the image reference. If neither this nor `image` is set, the policy applies to all images.
+    pub image_regex: Option<ConfigRegex>,
+    /// The range new versions have to be in
+    pub versions: Option<VersionRange>,
+    /// The update types which are reported. All of them are if empty.
+    pub update_types: Vec<VersionUpdateType>,
+    /// Defines the version in tags instead of the detected scheme, with `major`, `minor`, `patch` and `pre_release` named groups. Tags it doesn't match are ignored.
+    pub tag_regex: Option<ConfigRegex>,
+    /// Versions which are never updated to
+    pub ignore: Vec<String>,
+}
+
+impl PolicyConfig {
+    pub fn applies_to(&self, reference: &str) -> bool {
+        self.image
+            .as_ref()
+            .is_none_or(|glob| glob.0.is_match(reference))
+            && self
+                .image_regex
+                .as_ref()
+                .is_none_or(|regex| regex.0.is_match(reference))
+    }
+
+    /// Whether an image with version `base` may be updated to `version` according to the range and update types. Ignored versions are checked by `ImageConfig::allows`, since parsing them depends on the image.
+    pub fn allows(&self, version: &Version, base: &Version) -> bool {
+        let update_type = match version.to_status(base) {
+            Status::UpdateMajor => Some(VersionUpdateType::Major),
+            Status::UpdateMinor => Some(VersionUpdateType::Minor),
+            Status::UpdatePatch => Some(VersionUpdateType::Patch),
+            _ => None,
+        };
+        self.versions
+            .as_ref()
+            .is_none_or(|range| range.matches(version))
+            && (self.update_types.is_empty()
+                || update_type.is_none_or(|update_type| self.update_types.contains(&update_type)))
+    }
 }
 
 #[derive(Clone, Deserialize, Default)]
@@ -56,18 +325,338 @@ pub struct RegistryConfig {
 pub struct ImageConfig {
     pub extra: Vec<String>,
     pub exclude: Vec<String>,
+    /// Channels indexed by image reference prefix. Images which don't match any are only updated to releases.
+    pub channels: FxHashMap<String, Channel>,
+    /// Version schemes indexed by image reference prefix. Images which don't match any have their scheme detected from their tags.
+    pub schemes: FxHashMap<String, VersionScheme>,
+    pub policies: Vec<PolicyConfig>,
+}
+
+impl ImageConfig {
+    /// Returns the channel of the longest prefix matching the reference
+    pub fn channel(&self, reference: &str) -> &Channel {
+        static STABLE: Channel = Channel::Stable;
+        longest_prefix(&self.channels, reference).unwrap_or(&STABLE)
+    }
+
+    /// Returns the version scheme of the longest prefix matching the reference
+    pub fn scheme(&self, reference: &str) -> Option<&VersionScheme> {
+        longest_prefix(&self.schemes, reference)
+    }
+
+    /// Returns the policies which apply to the reference
+    pub fn policies<'a>(&'a self, reference: &'a str) -> impl Iterator<Item = &'a PolicyConfig> {
+        self.policies
+            .iter()
+            .filter(move |policy| policy.applies_to(reference))
+    }
+
+    /// Parses a tag of the image with the reference, using the tag regex of its policies or its configured scheme
+    pub fn parse_tag(&self, reference: &str, tag: &str) -> Option<(Version, String)> {
+        match self
+            .policies(reference)
+            .find_map(|policy| policy.tag_regex.as_ref())
+        {
+            Some(regex) => Version::from_regex(tag, &regex.0),
+            None => Version::parse(tag, self.scheme(reference)),
+        }
+    }
+
+    /// Whether the image with the reference and version `base` may be updated to `version` according to all of its policies.
+    /// Ignored versions are parsed like the image's tags, so they can be given as tags (e.g. `r12_4-slim`) or plain versions (e.g. `12.4`).
+    pub fn allows(&self, reference: &str, version: &Version, base: &Version) -> bool {
+        self.policies(reference).all(|policy| {
+            policy.allows(version, base)
+                && !policy.ignore.iter().any(|ignored| {
+                    self.parse_tag(reference, ignored)
+                        .or_else(|| Version::from_tag(ignored))
+                        .is_some_and(|(ignored, _)| ignored == *version)
+                })
+        })
+    }
+}
+
+fn longest_prefix<'a, T>(options: &'a FxHashMap<String, T>, reference: &str) -> Option<&'a T> {
+    options
+        .iter()
+        .filter(|(prefix, _)| reference.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, option)| option)
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct ApplyConfig {
+    pub endpoint: bool,
+    /// Secret that requests to the update and acknowledgement endpoints must send as a bearer token. They stay disabled without one.
+    pub token: Option<String>,
+    pub healthcheck_timeout: u64,
+}
+
+impl Default for ApplyConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: false,
+            token: None,
+            healthcheck_timeout: 60,
+        }
+    }
+}
+
+/// A Docker host to get images from besides the local one
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostConfig {
+    /// `unix://`, `tcp://` or `ssh://` URL of the Docker daemon
+    pub socket: String,
+    #[serde(default)]
+    pub tls: Option<HostTlsConfig>,
+}
+
+/// Paths of the certificates used to connect to a Docker daemon protected by TLS
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostTlsConfig {
+    pub ca: String,
+    pub cert: String,
+    pub key: String,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct HistoryConfig {
+    pub enabled: bool,
+    pub max_runs: usize,
+    pub max_age_days: u32,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_runs: 100,
+            max_age_days: 30,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct KubernetesConfig {
+    pub enabled: bool,
+    /// Path to a kubeconfig file. If not set, `$KUBECONFIG`, `~/.kube/config`, k3s' kubeconfig and the in-cluster service account are tried in that order.
+    pub kubeconfig: Option<String>,
+    /// Kubeconfig context to use instead of the current one
+    pub context: Option<String>,
+    /// Namespaces to get workloads from. All namespaces are checked if empty.
+    pub namespaces: Vec<String>,
+}
+
+/// The software a forge (a site hosting Git repositories) runs, which determines its API
+#[derive(Clone, Copy, Deserialize, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ForgeType {
+    Github,
+    Gitlab,
+    Gitea,
 }
 
 #[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ForgeConfig {
+    #[serde(rename = "type")]
+    pub forge_type: ForgeType,
+    /// Sent with API requests, e.g. to raise GitHub's rate limit or read private repositories
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
 #[serde(default)]
+pub struct ReleaseNotesConfig {
+    pub enabled: bool,
+    /// Forges indexed by domain. github.com, gitlab.com and codeberg.org are known, self-hosted ones have to be added here.
+    pub forges: FxHashMap<String, ForgeConfig>,
+}
+
+impl ReleaseNotesConfig {
+    /// Returns the forge configured for a base URL like `https://git.example.com`. Forges configured by their domain alone are only used over HTTPS, so their tokens are never sent in plain text.
+    pub fn forge(&self, base_url: &str) -> Option<&ForgeConfig> {
+        self.forges
+            .get(base_url)
+            .or_else(|| self.forges.get(base_url.strip_prefix("https://")?))
+    }
+}
+
+/// Update types a notification target can be limited to
+#[derive(Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationStatus {
+    Major,
+    Minor,
+    Patch,
+    Digest,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub headers: FxHashMap<String, String>,
+    #[serde(default)]
+    pub statuses: Vec<NotificationStatus>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NtfyConfig {
+    #[serde(default = "default_ntfy_url")]
+    pub url: String,
+    pub topic: String,
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub priority: Option<u8>,
+    #[serde(default)]
+    pub statuses: Vec<NotificationStatus>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GotifyConfig {
+    pub url: String,
+    pub token: String,
+    #[serde(default)]
+    pub priority: Option<u8>,
+    #[serde(default)]
+    pub statuses: Vec<NotificationStatus>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiscordConfig {
+    pub webhook_url: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub statuses: Vec<NotificationStatus>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SlackConfig {
+    pub webhook_url: String,
+    #[serde(default)]
+    pub statuses: Vec<NotificationStatus>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MatrixConfig {
+    pub homeserver: String,
+    pub room_id: String,
+    pub access_token: String,
+    #[serde(default)]
+    pub statuses: Vec<NotificationStatus>,
+}
+
+#[derive(Clone, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpSecurity {
+    Tls,
+    #[default]
+    Starttls,
+    None,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailConfig {
+    pub host: String,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub statuses: Vec<NotificationStatus>,
+}
+
+#[derive(Clone, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+#[serde(default)]
+pub struct NotificationConfig {
+    pub webhooks: FxHashMap<String, WebhookConfig>,
+    pub ntfy: FxHashMap<String, NtfyConfig>,
+    pub gotify: FxHashMap<String, GotifyConfig>,
+    pub discord: FxHashMap<String, DiscordConfig>,
+    pub slack: FxHashMap<String, SlackConfig>,
+    pub matrix: FxHashMap<String, MatrixConfig>,
+    pub email: FxHashMap<String, EmailConfig>,
+}
+
+impl NotificationConfig {
+    pub fn is_empty(&self) -> bool {
+        self.webhooks.is_empty()
+            && self.ntfy.is_empty()
+            && self.gotify.is_empty()
+            && self.discord.is_empty()
+            && self.slack.is_empty()
+            && self.matrix.is_empty()
+            && self.email.is_empty()
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
 pub struct Config {
     version: u8,
     pub agent: bool,
+    pub apply: ApplyConfig,
+    pub cache: CacheConfig,
+    #[serde(deserialize_with = "empty_as_none")]
+    pub data_directory: Option<String>,
+    /// Whether to fetch what changed for images with a digest update. Takes another request to the registry for each of them.
+    pub details: bool,
+    pub history: HistoryConfig,
+    pub hosts: FxHashMap<String, HostConfig>,
     pub ignore_update_type: UpdateType,
     pub images: ImageConfig,
+    pub kubernetes: KubernetesConfig,
+    /// The maximum number of requests sent at once, to all registries combined. 0 means no limit.
+    pub max_concurrent_requests: usize,
+    pub notifications: NotificationConfig,
     #[serde(deserialize_with = "empty_as_none")]
     pub refresh_interval: Option<String>,
     pub registries: FxHashMap<String, RegistryConfig>,
+    pub release_notes: ReleaseNotesConfig,
+    pub runtime: RuntimeType,
     pub servers: FxHashMap<String, String>,
     pub socket: Option<String>,
     pub theme: Theme,
@@ -78,10 +667,21 @@ impl Config {
         Self {
             version: 3,
             agent: false,
+            apply: ApplyConfig::default(),
+            cache: CacheConfig::default(),
+            data_directory: None,
+            details: false,
+            history: HistoryConfig::default(),
+            hosts: FxHashMap::default(),
             ignore_update_type: UpdateType::default(),
             images: ImageConfig::default(),
+            kubernetes: KubernetesConfig::default(),
+            max_concurrent_requests: 0,
+            notifications: NotificationConfig::default(),
             refresh_interval: None,
             registries: FxHashMap::default(),
+            release_notes: ReleaseNotesConfig::default(),
+            runtime: RuntimeType::default(),
             servers: FxHashMap::default(),
             socket: None,
             theme: Theme::Default,
@@ -103,9 +703,14 @@ impl Config {
                     match key.as_str() {
                         "CUP_AGENT" => config.agent = cfg.agent,
                         #[rustfmt::skip]
+                        "CUP_DATA_DIRECTORY" => swap!(config.data_directory, cfg.data_directory),
+                        #[rustfmt::skip]
                         "CUP_IGNORE_UPDATE_TYPE" => swap!(config.ignore_update_type, cfg.ignore_update_type),
                         #[rustfmt::skip]
+                        "CUP_MAX_CONCURRENT_REQUESTS" => config.max_concurrent_requests = cfg.max_concurrent_requests,
+                        #[rustfmt::skip]
                         "CUP_REFRESH_INTERVAL" => swap!(config.refresh_interval, cfg.refresh_interval),
+                        "CUP_RUNTIME" => swap!(config.runtime, cfg.runtime),
                         "CUP_SOCKET" => swap!(config.socket, cfg.socket),
                         "CUP_THEME" => swap!(config.theme, cfg.theme),
                         // The syntax for these is slightly more complicated, not sure if they should be enabled or not. Let's stick to simple types for now.
@@ -118,6 +723,12 @@ impl Config {
             }
         }
 
+        // Older configs turned off local images with this instead of the `runtime` option
+        if config.socket.as_deref() == Some("none") {
+            config.socket = None;
+            config.runtime = RuntimeType::None;
+        }
+
         config
     }
 
@@ -146,6 +757,20 @@ impl Config {
         }
         config
     }
+
+    /// Returns the directory Cup stores persistent data in. If one isn't configured, we follow the XDG base directory spec and fall back to `/data` (useful when running in the Docker image, where there is no home directory).
+    pub fn data_directory(&self) -> PathBuf {
+        match &self.data_directory {
+            Some(dir) => PathBuf::from(dir),
+            None => match env::var("XDG_DATA_HOME") {
+                Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("cup"),
+                _ => match env::var("HOME") {
+                    Ok(home) if !home.is_empty() => PathBuf::from(home).join(".local/share/cup"),
+                    _ => PathBuf::from("/data"),
+                },
+            },
+        }
+    }
 }
 
 impl Default for Config {
@@ -154,6 +779,10 @@ impl Default for Config {
     }
 }
 
+fn default_ntfy_url() -> String {
+    String::from("https://ntfy.sh")
+}
+
 fn empty_as_none<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
 where
     D: Deserializer<'de>,
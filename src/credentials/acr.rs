@@ -0,0 +1,142 @@
+// Azure Container Registry. A service principal's Microsoft Entra ID token is exchanged for a registry refresh token, which ACR accepts as a password.
+
+use std::time::{Duration, SystemTime};
+
+use futures::{future::BoxFuture, FutureExt};
+use serde::Deserialize;
+
+use crate::{config::AcrConfig, http::Client};
+
+use super::{form, token_credentials, Provider};
+
+const AUTHORITY: &str = "https://login.microsoftonline.com";
+const SCOPE: &str = "https://management.azure.com/.default";
+/// The user ACR expects refresh tokens to be sent with
+const TOKEN_USER: &str = "00000000-0000-0000-0000-000000000000";
+
+#[derive(Deserialize)]
+struct AccessTokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize)]
+struct ExchangeResponse {
+    refresh_token: String,
+}
+
+impl Provider for AcrConfig {
+    fn credentials<'a>(
+        &'a self,
+        registry: &'a str,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<(String, SystemTime), String>> {
+        async move {
+            let headers = [("Content-Type", Some("application/x-www-form-urlencoded"))];
+            let url = format!(
+                "{}/{}/oauth2/v2.0/token",
+                self.authority.as_deref().unwrap_or(AUTHORITY),
+                self.tenant_id
+            );
+            let response = client
+                .post(
+                    &url,
+                    &headers,
+                    form(&[
+                        ("grant_type", "client_credentials"),
+                        ("client_id", &self.client_id),
+                        ("client_secret", &self.client_secret),
+                        ("scope", SCOPE),
+                    ]),
+                )
+                .await?;
+            let access_token: AccessTokenResponse =
+                serde_json::from_str(&response.text().await.map_err(|e| e.to_string())?)
+                    .map_err(|e| e.to_string())?;
+
+            let url = format!(
+                "{}/oauth2/exchange",
+                match &self.endpoint {
+                    Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+                    None => format!("https://{}", registry),
+                }
+            );
+            let response = client
+                .post(
+                    &url,
+                    &headers,
+                    form(&[
+                        ("grant_type", "access_token"),
+                        ("service", registry),
+                        ("tenant", &self.tenant_id),
+                        ("access_token", &access_token.access_token),
+                    ]),
+                )
+                .await?;
+            let exchange: ExchangeResponse =
+                serde_json::from_str(&response.text().await.map_err(|e| e.to_string())?)
+                    .map_err(|e| e.to_string())?;
+            // Refresh tokens outlive the access token they were exchanged for, but ACR doesn't say by how much
+            Ok((
+                token_credentials(TOKEN_USER, &exchange.refresh_token),
+                SystemTime::now() + Duration::from_secs(access_token.expires_in),
+            ))
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    use crate::{
+        config::Config,
+        logging::Logger,
+        utils::test_server::{serve, Response},
+        Context,
+    };
+
+    use super::*;
+
+    /// Test that an Entra ID token from a mock authority is exchanged for a refresh token at a mock registry
+    #[tokio::test]
+    async fn acr_credentials() {
+        let (url, mut requests) = serve(|request| match request.path.as_str() {
+            "/tenant/oauth2/v2.0/token" => Response::new(
+                200,
+                r#"{"token_type":"Bearer","expires_in":3599,"access_token":"entra-token"}"#,
+            ),
+            "/oauth2/exchange" => Response::new(200, r#"{"refresh_token":"acr-refresh-token"}"#),
+            _ => Response::new(404, ""),
+        })
+        .await;
+        let config = AcrConfig {
+            tenant_id: String::from("tenant"),
+            client_id: String::from("client"),
+            client_secret: String::from("s3cret&more"),
+            authority: Some(url.clone()),
+            endpoint: Some(url),
+        };
+        let ctx = Context {
+            config: Config::new(),
+            logger: Logger::new(false, false),
+        };
+        let (credentials, _) = config
+            .credentials("example.azurecr.io", &Client::new(&ctx))
+            .await
+            .unwrap();
+        assert_eq!(
+            credentials,
+            STANDARD.encode(format!("{}:acr-refresh-token", TOKEN_USER))
+        );
+
+        let request = requests.recv().await.unwrap();
+        assert!(request.body.contains("client_secret=s3cret%26more"));
+        let request = requests.recv().await.unwrap();
+        assert_eq!(
+            request.body,
+            "grant_type=access_token&service=example.azurecr.io&tenant=tenant&access_token=entra-token"
+        );
+    }
+}
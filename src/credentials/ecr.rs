@@ -0,0 +1,254 @@
+// Amazon ECR. Registry passwords come from the `GetAuthorizationToken` API, which needs requests signed with AWS Signature Version 4.
+
+use std::{
+    env,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures::{future::BoxFuture, FutureExt};
+use ring::{digest, hmac};
+use serde::Deserialize;
+
+use crate::{config::EcrConfig, http::Client};
+
+use super::Provider;
+
+const TARGET: &str = "AmazonEC2ContainerRegistry_V20150921.GetAuthorizationToken";
+const CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AuthorizationResponse {
+    authorization_data: Vec<AuthorizationData>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AuthorizationData {
+    /// Base64 encoded `AWS:<password>`
+    authorization_token: String,
+    /// Unix timestamp, in seconds
+    expires_at: f64,
+}
+
+impl Provider for EcrConfig {
+    fn credentials<'a>(
+        &'a self,
+        registry: &'a str,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<(String, SystemTime), String>> {
+        async move {
+            let region = match &self.region {
+                Some(region) => region.clone(),
+                None => region_from_registry(registry)
+                    .ok_or("Couldn't find the registry's region, please set it in the config")?
+                    .to_string(),
+            };
+            let access_key_id = value_or_env(&self.access_key_id, "AWS_ACCESS_KEY_ID")
+                .ok_or("No AWS access key ID configured")?;
+            let secret_access_key = value_or_env(&self.secret_access_key, "AWS_SECRET_ACCESS_KEY")
+                .ok_or("No AWS secret access key configured")?;
+            let session_token = value_or_env(&self.session_token, "AWS_SESSION_TOKEN");
+            let url = match &self.endpoint {
+                Some(endpoint) => endpoint.clone(),
+                None => format!("https://api.ecr.{}.amazonaws.com/", region),
+            };
+            let host = url
+                .split("://")
+                .nth(1)
+                .and_then(|rest| rest.split('/').next())
+                .ok_or(format!("Invalid endpoint {}", url))?;
+
+            let body = "{}";
+            let (date, datetime) = amz_date(SystemTime::now());
+            let mut headers = vec![
+                ("content-type", CONTENT_TYPE.to_string()),
+                ("host", host.to_string()),
+                ("x-amz-date", datetime.clone()),
+            ];
+            if let Some(token) = &session_token {
+                headers.push(("x-amz-security-token", token.clone()));
+            }
+            headers.push(("x-amz-target", TARGET.to_string()));
+            let signed_headers = headers
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<&str>>()
+                .join(";");
+            let canonical_request = format!(
+                "POST\n/\n\n{}\n{}\n{}",
+                headers
+                    .iter()
+                    .map(|(name, value)| format!("{}:{}\n", name, value))
+                    .collect::<String>(),
+                signed_headers,
+                sha256_hex(body.as_bytes())
+            );
+            let scope = format!("{}/{}/ecr/aws4_request", date, region);
+            let string_to_sign = format!(
+                "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+                datetime,
+                scope,
+                sha256_hex(canonical_request.as_bytes())
+            );
+            let signature = hex(&sign(
+                &signing_key(&secret_access_key, &date, &region, "ecr"),
+                &string_to_sign,
+            ));
+            let authorization = format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                access_key_id, scope, signed_headers, signature
+            );
+
+            let mut request_headers: Vec<(&str, Option<&str>)> = headers
+                .iter()
+                // The client adds the Host header itself
+                .filter(|(name, _)| *name != "host")
+                .map(|(name, value)| (*name, Some(value.as_str())))
+                .collect();
+            request_headers.push(("Authorization", Some(&authorization)));
+            let response = client
+                .post(&url, &request_headers, body.to_string())
+                .await?;
+            let response: AuthorizationResponse =
+                serde_json::from_str(&response.text().await.map_err(|e| e.to_string())?)
+                    .map_err(|e| e.to_string())?;
+            let data = response
+                .authorization_data
+                .into_iter()
+                .next()
+                .ok_or("ECR didn't return an authorization token")?;
+            Ok((
+                data.authorization_token,
+                UNIX_EPOCH + Duration::from_secs_f64(data.expires_at),
+            ))
+        }
+        .boxed()
+    }
+}
+
+/// ECR registries look like `<account>.dkr.ecr.<region>.amazonaws.com`
+fn region_from_registry(registry: &str) -> Option<&str> {
+    let parts: Vec<&str> = registry.split('.').collect();
+    match parts.as_slice() {
+        [_, "dkr", "ecr", region, ..] => Some(region),
+        _ => None,
+    }
+}
+
+fn value_or_env(value: &Option<String>, var: &str) -> Option<String> {
+    value
+        .clone()
+        .or_else(|| env::var(var).ok().filter(|value| !value.is_empty()))
+}
+
+fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    [date, region, service, "aws4_request"].iter().fold(
+        format!("AWS4{}", secret_access_key).into_bytes(),
+        |key, data| sign(&key, data),
+    )
+}
+
+fn sign(key: &[u8], data: &str) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Formats a time as the date (`YYYYMMDD`) and date-time (`YYYYMMDDTHHMMSSZ`) used by AWS
+fn amz_date(time: SystemTime) -> (String, String) {
+    let seconds = time.duration_since(UNIX_EPOCH).unwrap().as_secs();
+    let (days, seconds_of_day) = ((seconds / 86400) as i64, seconds % 86400);
+    // Converts days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let datetime = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60
+    );
+    (date, datetime)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        logging::Logger,
+        utils::test_server::{serve, Response},
+        Context,
+    };
+
+    use super::*;
+
+    /// Test that requests to a mock ECR API are signed and that its token is used as is
+    #[tokio::test]
+    async fn ecr_credentials() {
+        assert_eq!(
+            amz_date(UNIX_EPOCH + Duration::from_secs(1440938160)),
+            (String::from("20150830"), String::from("20150830T123600Z"))
+        );
+        // From AWS' documentation
+        assert_eq!(
+            hex(&signing_key(
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+                "20120215",
+                "us-east-1",
+                "iam"
+            )),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+
+        let (url, mut requests) = serve(|_| {
+            Response::new(
+                200,
+                r#"{"authorizationData":[{"authorizationToken":"QVdTOnBhc3N3b3Jk","expiresAt":1.8E9,"proxyEndpoint":"https://123456789012.dkr.ecr.eu-west-1.amazonaws.com"}]}"#,
+            )
+        })
+        .await;
+        let config = EcrConfig {
+            access_key_id: Some(String::from("AKIDEXAMPLE")),
+            secret_access_key: Some(String::from("secret")),
+            endpoint: Some(format!("{}/", url)),
+            ..Default::default()
+        };
+        let ctx = Context {
+            config: Config::new(),
+            logger: Logger::new(false, false),
+        };
+        let (credentials, expires_at) = config
+            .credentials(
+                "123456789012.dkr.ecr.eu-west-1.amazonaws.com",
+                &Client::new(&ctx),
+            )
+            .await
+            .unwrap();
+        assert_eq!(credentials, "QVdTOnBhc3N3b3Jk");
+        assert_eq!(expires_at, UNIX_EPOCH + Duration::from_secs(1800000000));
+
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.headers["x-amz-target"], TARGET);
+        assert!(request.headers["authorization"]
+            .starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20"));
+        assert!(request.headers["authorization"]
+            .contains("/eu-west-1/ecr/aws4_request, SignedHeaders=content-type;host;x-amz-date;x-amz-target, Signature="));
+    }
+}
@@ -0,0 +1,180 @@
+// Google Artifact Registry and Container Registry accept OAuth access tokens, which we get from a service account key or the metadata server of the instance Cup is running on.
+
+use std::{
+    env, fs,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use futures::{future::BoxFuture, FutureExt};
+use ring::{
+    rand::SystemRandom,
+    signature::{RsaKeyPair, RSA_PKCS1_SHA256},
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{config::GcpConfig, http::Client};
+
+use super::{form, token_credentials, Provider};
+
+const METADATA_SERVER: &str = "http://metadata.google.internal";
+const SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+impl Provider for GcpConfig {
+    fn credentials<'a>(
+        &'a self,
+        _registry: &'a str,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<(String, SystemTime), String>> {
+        async move {
+            let key_file = self.key_file.clone().or_else(|| {
+                env::var("GOOGLE_APPLICATION_CREDENTIALS")
+                    .ok()
+                    .filter(|path| !path.is_empty())
+            });
+            let response = match key_file {
+                Some(path) => {
+                    let key: ServiceAccountKey = serde_json::from_str(
+                        &fs::read_to_string(&path)
+                            .map_err(|e| format!("Failed to read {}: {}", path, e))?,
+                    )
+                    .map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+                    let assertion = jwt(&key)?;
+                    client
+                        .post(
+                            &key.token_uri,
+                            &[("Content-Type", Some("application/x-www-form-urlencoded"))],
+                            form(&[
+                                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                                ("assertion", &assertion),
+                            ]),
+                        )
+                        .await?
+                }
+                None => {
+                    let url = format!(
+                        "{}/computeMetadata/v1/instance/service-accounts/default/token",
+                        self.endpoint.as_deref().unwrap_or(METADATA_SERVER)
+                    );
+                    client
+                        .get(&url, &[("Metadata-Flavor", Some("Google"))], false)
+                        .await?
+                }
+            };
+            let token: TokenResponse =
+                serde_json::from_str(&response.text().await.map_err(|e| e.to_string())?)
+                    .map_err(|e| e.to_string())?;
+            Ok((
+                token_credentials("oauth2accesstoken", &token.access_token),
+                SystemTime::now() + Duration::from_secs(token.expires_in),
+            ))
+        }
+        .boxed()
+    }
+}
+
+/// Creates a JWT signed with the service account's key, which Google exchanges for an access token
+fn jwt(key: &ServiceAccountKey) -> Result<String, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let header = URL_SAFE_NO_PAD.encode(json!({"alg": "RS256", "typ": "JWT"}).to_string());
+    let claims = URL_SAFE_NO_PAD.encode(
+        json!({
+            "iss": key.client_email,
+            "scope": SCOPE,
+            "aud": key.token_uri,
+            "iat": now,
+            "exp": now + 3600
+        })
+        .to_string(),
+    );
+    let message = format!("{}.{}", header, claims);
+    // The key is a PEM encoded PKCS#8 key
+    let der = STANDARD
+        .decode(
+            key.private_key
+                .lines()
+                .filter(|line| !line.starts_with("-----"))
+                .collect::<String>(),
+        )
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let key_pair =
+        RsaKeyPair::from_pkcs8(&der).map_err(|e| format!("Invalid private key: {}", e))?;
+    let mut signature = vec![0; key_pair.public().modulus_len()];
+    key_pair
+        .sign(
+            &RSA_PKCS1_SHA256,
+            &SystemRandom::new(),
+            message.as_bytes(),
+            &mut signature,
+        )
+        .map_err(|e| format!("Failed to sign JWT: {}", e))?;
+    Ok(format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        logging::Logger,
+        utils::test_server::{serve, Response},
+        Context,
+    };
+
+    use super::*;
+
+    /// Test that the access token from a mock metadata server is used as the password
+    #[tokio::test]
+    async fn gcp_credentials() {
+        let (url, mut requests) = serve(|_| {
+            Response::new(
+                200,
+                r#"{"access_token":"ya29.c.token","expires_in":3599,"token_type":"Bearer"}"#,
+            )
+        })
+        .await;
+        let config = GcpConfig {
+            key_file: None,
+            endpoint: Some(url),
+        };
+        let ctx = Context {
+            config: Config::new(),
+            logger: Logger::new(false, false),
+        };
+        let (credentials, expires_at) = config
+            .credentials("europe-docker.pkg.dev", &Client::new(&ctx))
+            .await
+            .unwrap();
+        assert_eq!(
+            credentials,
+            STANDARD.encode("oauth2accesstoken:ya29.c.token")
+        );
+        assert!(expires_at > SystemTime::now() + Duration::from_secs(3500));
+
+        let request = requests.recv().await.unwrap();
+        assert_eq!(
+            request.path,
+            "/computeMetadata/v1/instance/service-accounts/default/token"
+        );
+        assert_eq!(request.headers["metadata-flavor"], "Google");
+    }
+}
@@ -0,0 +1,329 @@
+// Registry credentials, either minted by a cloud provider or saved by `docker login` (read from Docker's config file and credential helpers)
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    process::Stdio,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::future::BoxFuture;
+use once_cell::sync::Lazy;
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{
+    config::AuthProviderConfig, http::Client, utils::reference::DEFAULT_REGISTRY, Context,
+};
+
+pub mod acr;
+pub mod ecr;
+pub mod gcp;
+
+/// Credentials are refreshed this long before they expire, so they don't expire in the middle of a check
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// Credentials minted by providers, with the time they expire at, indexed by registry
+static PROVIDER_CREDENTIALS: Lazy<Mutex<FxHashMap<String, (String, SystemTime)>>> =
+    Lazy::new(|| Mutex::new(FxHashMap::default()));
+
+/// A cloud provider which mints short-lived registry credentials. Implemented by the config of each provider.
+pub trait Provider: Sync {
+    /// Fetches new credentials for a registry. Returns them (as base64 encoded `user:pass`) along with the time they expire at.
+    fn credentials<'a>(
+        &'a self,
+        registry: &'a str,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<(String, SystemTime), String>>;
+}
+
+impl AuthProviderConfig {
+    fn provider(&self) -> &dyn Provider {
+        match self {
+            Self::Ecr(config) => config,
+            Self::Gcp(config) => config,
+            Self::Acr(config) => config,
+        }
+    }
+}
+
+/// The address Docker Hub credentials are saved under
+const DOCKER_HUB_ADDRESS: &str = "https://index.docker.io/v1/";
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct DockerConfig {
+    #[serde(default)]
+    auths: FxHashMap<String, AuthEntry>,
+    creds_store: Option<String>,
+    #[serde(default)]
+    cred_helpers: FxHashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct AuthEntry {
+    auth: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HelperOutput {
+    username: String,
+    secret: String,
+}
+
+#[cfg_attr(test, derive(Debug, PartialEq))]
+enum Source<'a> {
+    /// A `docker-credential-*` program
+    Helper(&'a str),
+    /// Base64 encoded `user:pass`, stored in the config file itself
+    Auth(&'a str),
+}
+
+/// Returns the credentials for a registry as a base64 encoded `user:pass` string (the format of `RegistryConfig.authentication`). A configured provider is used first, then credentials saved by `docker login`, falling back to the ones in the config.
+pub async fn get_credentials(registry: &str, ctx: &Context, client: &Client) -> Option<String> {
+    let config = ctx.config.registries.get(registry);
+    if let Some(provider) = config.and_then(|config| config.provider.as_ref()) {
+        if let Some(credentials) = get_provider_credentials(registry, provider, ctx, client).await {
+            return Some(credentials);
+        }
+    }
+    match get_docker_credentials(registry, ctx).await {
+        Some(credentials) => Some(credentials),
+        None => config.and_then(|config| config.authentication.clone()),
+    }
+}
+
+/// Returns the provider's cached credentials for the registry, or mints new ones if they're about to expire
+async fn get_provider_credentials(
+    registry: &str,
+    provider: &AuthProviderConfig,
+    ctx: &Context,
+    client: &Client,
+) -> Option<String> {
+    if let Some((credentials, expires_at)) = PROVIDER_CREDENTIALS.lock().unwrap().get(registry) {
+        if SystemTime::now() + REFRESH_MARGIN < *expires_at {
+            return Some(credentials.clone());
+        }
+    }
+    match provider.provider().credentials(registry, client).await {
+        Ok((credentials, expires_at)) => {
+            ctx.logger
+                .debug(format!("Refreshed credentials for {}", registry));
+            PROVIDER_CREDENTIALS
+                .lock()
+                .unwrap()
+                .insert(registry.to_string(), (credentials.clone(), expires_at));
+            Some(credentials)
+        }
+        Err(e) => {
+            ctx.logger
+                .warn(format!("Failed to get credentials for {}: {}", registry, e));
+            None
+        }
+    }
+}
+
+/// Encodes the parameters of an `application/x-www-form-urlencoded` request body
+fn form(params: &[(&str, &str)]) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(params)
+        .finish()
+}
+
+/// Converts an access token into credentials for registries which accept one as the password of a fixed user
+fn token_credentials(username: &str, token: &str) -> String {
+    STANDARD.encode(format!("{}:{}", username, token))
+}
+
+async fn get_docker_credentials(registry: &str, ctx: &Context) -> Option<String> {
+    let path = docker_config_path()?;
+    // Not everyone uses Docker's CLI, so a missing file isn't worth mentioning
+    let contents = fs::read_to_string(&path).ok()?;
+    let config: DockerConfig = match serde_json::from_str(&contents) {
+        Ok(config) => config,
+        Err(e) => {
+            ctx.logger
+                .warn(format!("Failed to parse {}: {}", path.display(), e));
+            return None;
+        }
+    };
+    match find_source(&config, registry)? {
+        Source::Auth(auth) => {
+            ctx.logger.debug(format!(
+                "Using credentials for {} from {}",
+                registry,
+                path.display()
+            ));
+            Some(auth.to_string())
+        }
+        Source::Helper(helper) => match run_helper(helper, &server_address(registry)).await {
+            Ok(credentials) => {
+                ctx.logger.debug(format!(
+                    "Using credentials for {} from docker-credential-{}",
+                    registry, helper
+                ));
+                credentials
+            }
+            Err(e) => {
+                ctx.logger.warn(format!(
+                    "Failed to get credentials for {} from docker-credential-{}: {}",
+                    registry, helper, e
+                ));
+                None
+            }
+        },
+    }
+}
+
+/// Figures out where Docker keeps the credentials for a registry, in the same order Docker looks for them
+fn find_source<'a>(config: &'a DockerConfig, registry: &str) -> Option<Source<'a>> {
+    if let Some(helper) = config
+        .cred_helpers
+        .iter()
+        .find(|(address, _)| matches_registry(address, registry))
+        .map(|(_, helper)| helper)
+    {
+        return Some(Source::Helper(helper));
+    }
+    if let Some(store) = &config.creds_store {
+        return Some(Source::Helper(store));
+    }
+    config
+        .auths
+        .iter()
+        .find(|(address, _)| matches_registry(address, registry))
+        .and_then(|(_, entry)| entry.auth.as_deref())
+        .filter(|auth| !auth.is_empty())
+        .map(Source::Auth)
+}
+
+/// Checks if an address in Docker's config (e.g. `https://ghcr.io` or `ghcr.io`) refers to the registry passed in
+fn matches_registry(address: &str, registry: &str) -> bool {
+    let host = address
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    match registry {
+        DEFAULT_REGISTRY => matches!(
+            host,
+            "index.docker.io" | "docker.io" | "registry-1.docker.io"
+        ),
+        _ => host == registry,
+    }
+}
+
+fn server_address(registry: &str) -> String {
+    match registry {
+        DEFAULT_REGISTRY => DOCKER_HUB_ADDRESS.to_string(),
+        _ => registry.to_string(),
+    }
+}
+
+/// Asks a credential helper for the credentials of a server. Returns `None` if it doesn't have any.
+async fn run_helper(helper: &str, server: &str) -> Result<Option<String>, String> {
+    let mut child = Command::new(format!("docker-credential-{}", helper))
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(server.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    let output = child.wait_with_output().await.map_err(|e| e.to_string())?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        // Helpers print this (to stdout!) when they don't know the server
+        if stdout.contains("credentials not found") {
+            return Ok(None);
+        }
+        return Err(format!(
+            "{}{}",
+            stdout.trim(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_helper_output(&stdout))
+}
+
+fn parse_helper_output(output: &str) -> Option<String> {
+    let output: HelperOutput = serde_json::from_str(output).ok()?;
+    // Identity tokens need a different flow, which registries supporting them also offer passwords for
+    if output.username == "<token>" || output.secret.is_empty() {
+        return None;
+    }
+    Some(STANDARD.encode(format!("{}:{}", output.username, output.secret)))
+}
+
+/// Docker's config file lives in `$DOCKER_CONFIG` or `~/.docker`
+fn docker_config_path() -> Option<PathBuf> {
+    match env::var("DOCKER_CONFIG") {
+        Ok(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("config.json")),
+        _ => env::var("HOME")
+            .ok()
+            .filter(|home| !home.is_empty())
+            .map(|home| PathBuf::from(home).join(".docker/config.json")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that credentials are looked up like Docker does
+    #[test]
+    fn credential_sources() {
+        let config: DockerConfig = serde_json::from_str(
+            r#"{
+                "auths": {
+                    "https://index.docker.io/v1/": {"auth": "dXNlcjpwYXNz"},
+                    "ghcr.io": {},
+                    "registry.example.com:5000": {"auth": "YWRtaW46aHVudGVyMg=="}
+                },
+                "credHelpers": {"ghcr.io": "gh"}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            find_source(&config, DEFAULT_REGISTRY),
+            Some(Source::Auth("dXNlcjpwYXNz"))
+        );
+        assert_eq!(find_source(&config, "ghcr.io"), Some(Source::Helper("gh")));
+        assert_eq!(
+            find_source(&config, "registry.example.com:5000"),
+            Some(Source::Auth("YWRtaW46aHVudGVyMg=="))
+        );
+        assert_eq!(find_source(&config, "quay.io"), None);
+
+        let config = DockerConfig {
+            creds_store: Some(String::from("desktop")),
+            ..config
+        };
+        assert_eq!(
+            find_source(&config, "quay.io"),
+            Some(Source::Helper("desktop"))
+        );
+
+        assert_eq!(
+            parse_helper_output(
+                r#"{"ServerURL":"https://index.docker.io/v1/","Username":"user","Secret":"pass"}"#
+            )
+            .as_deref(),
+            Some("dXNlcjpwYXNz")
+        );
+        assert_eq!(
+            parse_helper_output(r#"{"ServerURL":"gcr.io","Username":"<token>","Secret":"x"}"#),
+            None
+        );
+    }
+}
@@ -0,0 +1,35 @@
+// Databases in the data directory, which hold everything Cup remembers between checks. Each feature has its own file, so one of them breaking doesn't affect the others.
+// A database can only be opened once at a time, so stores used from several places in a process keep theirs in a static.
+
+use std::fs;
+
+use redb::Database;
+
+use crate::Context;
+
+/// Opens (or creates) the database `name` (e.g. `history.redb`) in the data directory. Cup works fine without any of them, so if that fails, a warning explaining what `consequence` that has is logged and `None` is returned.
+pub fn open(name: &str, consequence: &str, ctx: &Context) -> Option<Database> {
+    let directory = ctx.config.data_directory();
+    if let Err(e) = fs::create_dir_all(&directory) {
+        ctx.logger.warn(format!(
+            "Failed to create data directory {}, {}. {}",
+            directory.display(),
+            consequence,
+            e
+        ));
+        return None;
+    }
+    let path = directory.join(name);
+    match Database::create(&path) {
+        Ok(db) => Some(db),
+        Err(e) => {
+            ctx.logger.warn(format!(
+                "Failed to open database at {}, {}. {}",
+                path.display(),
+                consequence,
+                e
+            ));
+            None
+        }
+    }
+}
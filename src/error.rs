@@ -0,0 +1,174 @@
+// Errors which can happen while checking an image. Instead of stopping Cup, they're stored with the image (which then has an unknown status), so one broken image or registry doesn't prevent the others from being checked.
+
+use std::fmt::Display;
+
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The connection to the server failed. `request` is the method and URL of the request, e.g. `GET https://ghcr.io/v2/`.
+    ConnectionFailed {
+        request: String,
+    },
+    Timeout {
+        request: String,
+    },
+    TooManyRetries {
+        request: String,
+    },
+    /// Any other error while sending the request
+    RequestFailed {
+        request: String,
+        message: String,
+    },
+    NotFound {
+        request: String,
+    },
+    Unauthorized {
+        request: String,
+    },
+    Forbidden {
+        request: String,
+    },
+    /// The registry's rate limit was exceeded, even after retrying
+    TooManyRequests {
+        request: String,
+    },
+    /// Sending the request would use up the part of the registry's rate limit that's kept for pulling updates. Images are deferred instead of reporting this.
+    BudgetExhausted {
+        registry: String,
+    },
+    Unavailable {
+        request: String,
+        status: u16,
+    },
+    UnexpectedStatus {
+        request: String,
+        status: u16,
+    },
+    /// The server responded with something we don't understand
+    InvalidResponse {
+        message: String,
+    },
+    /// The registry needs authentication, but doesn't say how
+    MissingChallenge {
+        registry: String,
+    },
+    UnsupportedScheme {
+        scheme: String,
+    },
+    /// None of the registry's tags have the same format as the image's tag
+    NoVersionTags {
+        reference: String,
+        tag: String,
+    },
+    /// The image isn't available locally and its tag isn't a version, so there's nothing to compare with
+    UnsupportedTag {
+        reference: String,
+    },
+    /// The container runtime couldn't be reached or failed to list its images or containers
+    Runtime {
+        message: String,
+    },
+}
+
+impl Error {
+    /// Returns a machine-readable code for the error, included in the API next to the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ConnectionFailed { .. } => "connection_failed",
+            Self::Timeout { .. } => "timeout",
+            Self::TooManyRetries { .. } => "too_many_retries",
+            Self::RequestFailed { .. } => "request_failed",
+            Self::NotFound { .. } => "not_found",
+            Self::Unauthorized { .. } => "unauthorized",
+            Self::Forbidden { .. } => "forbidden",
+            Self::TooManyRequests { .. } => "too_many_requests",
+            Self::BudgetExhausted { .. } => "budget_exhausted",
+            Self::Unavailable { .. } => "registry_unavailable",
+            Self::UnexpectedStatus { .. } => "unexpected_status",
+            Self::InvalidResponse { .. } => "invalid_response",
+            Self::MissingChallenge { .. } => "missing_challenge",
+            Self::UnsupportedScheme { .. } => "unsupported_auth_scheme",
+            Self::NoVersionTags { .. } => "no_version_tags",
+            Self::UnsupportedTag { .. } => "unsupported_tag",
+            Self::Runtime { .. } => "runtime_error",
+        }
+    }
+
+    pub fn invalid_response(message: impl Display) -> Self {
+        Self::InvalidResponse {
+            message: message.to_string(),
+        }
+    }
+
+    pub fn runtime(message: impl Display) -> Self {
+        Self::Runtime {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ConnectionFailed { request } => write!(f, "{}: Connection failed!", request),
+            Self::Timeout { request } => write!(f, "{}: Connection timed out!", request),
+            Self::TooManyRetries { request } => {
+                write!(f, "{}: Connection failed after 3 retries!", request)
+            }
+            Self::RequestFailed { request, message } => {
+                write!(f, "{}: Unexpected error: {}", request, message)
+            }
+            Self::NotFound { request } => write!(f, "{}: Not found!", request),
+            Self::Unauthorized { request } => write!(f, "{}: Unauthorized! Please configure authentication for this registry or if you have already done so, please make sure it is correct.", request),
+            Self::Forbidden { request } => write!(f, "{}: Forbidden! If you've configured authentication for this registry, make sure it is correct. Otherwise there is a chance that the registry is down and a proxy is returning an error.", request),
+            Self::TooManyRequests { request } => write!(f, "{}: Too many requests! The registry's rate limit was exceeded, try again later.", request),
+            Self::BudgetExhausted { registry } => write!(
+                f,
+                "The rate limit of {} is nearly exhausted, the image will be checked next time",
+                registry
+            ),
+            Self::Unavailable { request, status } => write!(
+                f,
+                "{}: The registry is currently unavailabile (returned status code {}).",
+                request, status
+            ),
+            Self::UnexpectedStatus { request, status } => write!(
+                f,
+                "{}: Unexpected error: Recieved status code {}",
+                request, status
+            ),
+            Self::InvalidResponse { message } => {
+                write!(f, "Server returned invalid response! {}", message)
+            }
+            Self::MissingChallenge { registry } => write!(
+                f,
+                "Unauthorized to access registry {} and no way to authenticate was provided",
+                registry
+            ),
+            Self::UnsupportedScheme { scheme } => {
+                write!(f, "Unsupported authentication scheme {}", scheme)
+            }
+            Self::NoVersionTags { reference, tag } => write!(
+                f,
+                "Image {} has no remote version tags! Local tag: {}",
+                reference, tag
+            ),
+            Self::UnsupportedTag { reference } => write!(
+                f,
+                "Image {} is not available locally and does not have a recognizable tag format!",
+                reference
+            ),
+            Self::Runtime { message } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Lets functions which report errors as strings (e.g. notifications and auth providers) use `?` on requests
+impl From<Error> for String {
+    fn from(error: Error) -> Self {
+        error.to_string()
+    }
+}
@@ -1,16 +1,21 @@
 pub mod spinner;
 
+use std::collections::BTreeMap;
+
 use rustc_hash::FxHashMap;
 
 use crate::{
+    http::rate_limits,
     structs::{
+        details::UpdateDetails,
         status::Status,
         update::{Update, UpdateInfo},
     },
     utils::{json::to_simple_json, sort_update_vec::sort_update_vec},
 };
 
-pub fn print_updates(updates: &[Update], icons: &bool) {
+/// Prints tables of updates. If `details` is set, what changed is listed for each digest update.
+pub fn print_updates(updates: &[Update], icons: &bool, details: &bool) {
     let sorted_updates = sort_update_vec(updates);
     let updates_by_server = {
         let mut servers: FxHashMap<&str, Vec<&Update>> = FxHashMap::default();
@@ -26,143 +31,249 @@ pub fn print_updates(updates: &[Update], icons: &bool) {
         servers
     };
     for (server, updates) in updates_by_server {
-        if server.is_empty() {
-            println!("\x1b[90;1m~ Local images\x1b[0m")
+        let title = if server.is_empty() {
+            "Local images"
         } else {
-            println!("\x1b[90;1m~ {}\x1b[0m", server)
+            server
+        };
+        // Images used by Compose services are listed under their project instead, once for each service using them
+        let mut standalone: Vec<(&str, &Update)> = Vec::new();
+        let mut projects: BTreeMap<&str, Vec<(&str, &Update)>> = BTreeMap::new();
+        for update in updates {
+            if update.compose.is_empty() {
+                standalone.push(("", update));
+            }
+            for service in &update.compose {
+                projects
+                    .entry(&service.project)
+                    .or_default()
+                    .push((&service.service, update));
+            }
+        }
+        if !standalone.is_empty() {
+            println!("\x1b[90;1m~ {}\x1b[0m", title);
+            print_table(&standalone, icons);
         }
-        let (reference_width, status_width, time_width) =
-            updates.iter().fold((9, 6, 9), |acc, update| {
-                let reference_length = update.reference.len();
-                let status_length = update.get_status().to_string().len()
-                    + match &update.result.info {
-                        UpdateInfo::Version(info) => {
-                            info.current_version.len() + info.new_version.len() + 6
-                        }
-                        _ => 0,
-                    };
-                let time_length = update.time.to_string().len();
-                (
-                    if reference_length > acc.0 {
-                        reference_length
-                    } else {
-                        acc.0
-                    },
-                    if status_length > acc.1 {
-                        status_length
-                    } else {
-                        acc.1
-                    },
-                    if time_length > acc.2 {
-                        time_length
-                    } else {
-                        acc.2
-                    },
-                )
-            });
+        for (project, services) in projects {
+            println!("\x1b[90;1m~ {} / {}\x1b[0m", title, project);
+            print_table(&services, icons);
+        }
+    }
+    if *details {
+        print_details(&sorted_updates);
+    }
+    let mut limits: Vec<_> = rate_limits().into_iter().collect();
+    limits.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    for (registry, limit) in limits {
+        println!(
+            "\x1b[90m{} rate limit: {} of {} requests remaining\x1b[0m",
+            registry, limit.remaining, limit.limit
+        );
+    }
+}
+
+/// Prints a table of updates. Each update is paired with the Compose service using it, and a service column is added if any of them is non-empty.
+fn print_table(rows: &[(&str, &Update)], icons: &bool) {
+    let service_width = rows
+        .iter()
+        .map(|(service, _)| service.len())
+        .max()
+        .filter(|width| *width != 0)
+        .map(|width| width.max(7));
+    let (reference_width, status_width, time_width) =
+        rows.iter().fold((9, 6, 9), |acc, (_, update)| {
+            let reference_length = update.reference.len();
+            let status_length = update.get_status().to_string().len()
+                + match &update.result.info {
+                    UpdateInfo::Version(info) => {
+                        info.current_version.len() + info.new_version.len() + 6
+                    }
+                    _ => 0,
+                };
+            let time_length = update.time.to_string().len();
+            (
+                if reference_length > acc.0 {
+                    reference_length
+                } else {
+                    acc.0
+                },
+                if status_length > acc.1 {
+                    status_length
+                } else {
+                    acc.1
+                },
+                if time_length > acc.2 {
+                    time_length
+                } else {
+                    acc.2
+                },
+            )
+        });
+    let status_column_width = status_width + if *icons { 2 } else { 0 };
+    // Prints a horizontal border using the corner and junction characters passed in
+    let border = |left: &str, middle: &str, right: &str| {
+        let service = match service_width {
+            Some(width) => format!("{:─<width$}{}", "", middle, width = width),
+            None => String::new(),
+        };
         println!(
-            " \x1b[90;1m╭{:─<rw$}┬{:─<sw$}┬{:─<tw$}╮\x1b[0m",
+            " \x1b[90;1m{}{}{:─<rw$}{}{:─<sw$}{}{:─<tw$}{}\x1b[0m",
+            left,
+            service,
             "",
+            middle,
             "",
+            middle,
             "",
+            right,
             rw = reference_width,
-            sw = status_width + {
-                if *icons {
-                    2
-                } else {
-                    0
-                }
-            },
+            sw = status_column_width,
             tw = time_width
         );
-        println!(
-            " \x1b[90;1m│\x1b[36;1m{:<rw$}\x1b[90;1m│\x1b[36;1m{:<sw$}\x1b[90;1m│\x1b[36;1m{:<tw$}\x1b[90;1m│\x1b[0m",
-            "Reference",
-            "Status",
-            "Time (ms)",
-            rw = reference_width,
-            sw = status_width + {
-                if *icons {
-                    2
-                } else {
-                    0
+    };
+    let service_cell = |contents: &str, color: &str| match service_width {
+        Some(width) => format!("{}{:<width$}\x1b[90;1m│", color, contents, width = width),
+        None => String::new(),
+    };
+    border("╭", "┬", "╮");
+    println!(
+        " \x1b[90;1m│{}\x1b[36;1m{:<rw$}\x1b[90;1m│\x1b[36;1m{:<sw$}\x1b[90;1m│\x1b[36;1m{:<tw$}\x1b[90;1m│\x1b[0m",
+        service_cell("Service", "\x1b[36;1m"),
+        "Reference",
+        "Status",
+        "Time (ms)",
+        rw = reference_width,
+        sw = status_column_width,
+        tw = time_width
+    );
+    border("├", "┼", "┤");
+    for (service, update) in rows {
+        let status = update.get_status();
+        let icon = if *icons {
+            match status {
+                Status::UpToDate => "\u{f058} ",
+                Status::Deferred => "\u{f017} ",
+                Status::Acknowledged => "\u{f1f6} ",
+                Status::Unknown(_) => "\u{f059} ",
+                _ => "\u{f0aa} ",
+            }
+        } else {
+            ""
+        };
+        let color = match status {
+            Status::UpdateAvailable | Status::UpdatePatch => "\x1b[34m",
+            Status::UpdateMinor => "\x1b[33m",
+            Status::UpdateMajor => "\x1b[31m",
+            Status::UpToDate => "\x1b[32m",
+            Status::Acknowledged | Status::Deferred | Status::Unknown(_) => "\x1b[90m",
+        };
+        let description = format!(
+            "{}{}",
+            status,
+            match &update.result.info {
+                UpdateInfo::Version(info) => {
+                    format!(" ({} → {})", info.current_version, info.new_version)
                 }
-            },
-            tw = time_width
+                _ => String::new(),
+            }
         );
         println!(
-            " \x1b[90;1m├{:─<rw$}┼{:─<sw$}┼{:─<tw$}┤\x1b[0m",
-            "",
-            "",
-            "",
+            " \x1b[90;1m│{}\x1b[0m{:<rw$}\x1b[90;1m│\x1b[0m{}{}{:<sw$}\x1b[0m\x1b[90;1m│\x1b[0m{:<tw$}\x1b[90;1m│\x1b[0m",
+            service_cell(service, "\x1b[0m"),
+            update.reference,
+            color,
+            icon,
+            description,
+            update.time,
             rw = reference_width,
-            sw = status_width + {
-                if *icons {
-                    2
-                } else {
-                    0
-                }
-            },
+            sw = status_width,
             tw = time_width
         );
-        for update in updates {
-            let status = update.get_status();
-            let icon = if *icons {
-                match status {
-                    Status::UpToDate => "\u{f058} ",
-                    Status::Unknown(_) => "\u{f059} ",
-                    _ => "\u{f0aa} ",
-                }
-            } else {
-                ""
-            };
-            let color = match status {
-                Status::UpdateAvailable | Status::UpdatePatch => "\x1b[34m",
-                Status::UpdateMinor => "\x1b[33m",
-                Status::UpdateMajor => "\x1b[31m",
-                Status::UpToDate => "\x1b[32m",
-                Status::Unknown(_) => "\x1b[90m",
-            };
-            let description = format!(
-                "{}{}",
-                status,
-                match &update.result.info {
-                    UpdateInfo::Version(info) => {
-                        format!(" ({} → {})", info.current_version, info.new_version)
-                    }
-                    _ => String::new(),
-                }
-            );
+    }
+    border("╰", "┴", "╯");
+}
+
+/// Prints what changed for each digest update whose details are known
+fn print_details(updates: &[Update]) {
+    let rows: Vec<(&Update, &UpdateDetails)> = updates
+        .iter()
+        .filter(|update| update.get_status() == Status::UpdateAvailable)
+        .filter_map(|update| match &update.result.info {
+            UpdateInfo::Digest(info) => Some((update, info.details.as_ref()?)),
+            _ => None,
+        })
+        .collect();
+    if rows.is_empty() {
+        return;
+    }
+    println!("\x1b[90;1m~ Details\x1b[0m");
+    for (update, details) in rows {
+        match &update.server {
+            Some(server) => println!(
+                " \x1b[36;1m{}\x1b[0m \x1b[90m({})\x1b[0m",
+                update.reference, server
+            ),
+            None => println!(" \x1b[36;1m{}\x1b[0m", update.reference),
+        }
+        if let Some(created) = &details.remote_created {
+            match &details.local_created {
+                Some(local_created) => println!(
+                    "   Created: {} → {}",
+                    format_date(local_created),
+                    format_date(created)
+                ),
+                None => println!("   Created: {}", format_date(created)),
+            }
+        }
+        match (
+            details.local_layers,
+            details.new_layers,
+            details.download_size,
+        ) {
+            (Some(local_layers), Some(new_layers), Some(download_size)) => println!(
+                "   Layers: {} → {} ({} new, {} to download)",
+                local_layers,
+                details.remote_layers,
+                new_layers,
+                format_size(download_size)
+            ),
+            _ => println!(
+                "   Layers: {} ({})",
+                details.remote_layers,
+                format_size(details.size)
+            ),
+        }
+        for label in &details.labels {
             println!(
-                " \x1b[90;1m│\x1b[0m{:<rw$}\x1b[90;1m│\x1b[0m{}{}{:<sw$}\x1b[0m\x1b[90;1m│\x1b[0m{:<tw$}\x1b[90;1m│\x1b[0m",
-                update.reference,
-                color,
-                icon,
-                description,
-                update.time,
-                rw = reference_width,
-                sw = status_width,
-                tw = time_width
+                "   {}: {} → {}",
+                label.name,
+                label.old.as_deref().unwrap_or("(none)"),
+                label.new.as_deref().unwrap_or("(none)")
             );
         }
-        println!(
-            " \x1b[90;1m╰{:─<rw$}┴{:─<sw$}┴{:─<tw$}╯\x1b[0m",
-            "",
-            "",
-            "",
-            rw = reference_width,
-            sw = status_width + {
-                if *icons {
-                    2
-                } else {
-                    0
-                }
-            },
-            tw = time_width
-        );
     }
 }
 
+/// Shortens an RFC 3339 date to the second, e.g. `2025-01-01 12:00:00`
+fn format_date(date: &str) -> String {
+    date.get(..19).unwrap_or(date).replace('T', " ")
+}
+
+/// Formats a size in bytes with decimal units, e.g. `12.3 MB`
+fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "kB", "MB"] {
+        if size < 1000.0 {
+            return match unit {
+                "B" => format!("{} {}", bytes, unit),
+                _ => format!("{:.1} {}", size, unit),
+            };
+        }
+        size /= 1000.0;
+    }
+    format!("{:.1} GB", size)
+}
+
 pub fn print_raw_updates(updates: &[Update]) {
     println!("{}", to_simple_json(updates));
 }
@@ -0,0 +1,249 @@
+// Persistent storage for the results of previous checks
+
+use redb::{Database, ReadableTable, ReadableTableMetadata, TableDefinition, TableError};
+use rustc_hash::FxHashSet;
+use serde::{Deserialize, Serialize};
+
+use crate::{data_store, structs::update::Update, Context};
+
+/// Check runs indexed by the Unix timestamp (in milliseconds) they started at. Values are JSON-serialized `CheckRun`s.
+const RUNS: TableDefinition<u64, &str> = TableDefinition::new("runs");
+/// The timestamp each currently available update was first seen at. Keys are generated by `Update::update_key`.
+const FIRST_SEEN: TableDefinition<&str, u64> = TableDefinition::new("first_seen");
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// The results of a single check
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub struct CheckRun {
+    pub timestamp: u64,
+    pub duration: u32,
+    pub updates: Vec<Update>,
+}
+
+/// On-disk record of previous checks, stored in the data directory.
+pub struct History {
+    db: Database,
+    ctx: Context,
+}
+
+impl History {
+    /// Opens (or creates) the history database. Returns `None` if history is disabled or the database can't be opened, since Cup works fine without it.
+    pub fn open(ctx: &Context) -> Option<Self> {
+        if !ctx.config.history.enabled {
+            return None;
+        }
+        let db = data_store::open("history.redb", "history will not be saved", ctx)?;
+        Some(Self {
+            db,
+            ctx: ctx.clone(),
+        })
+    }
+
+    pub fn record(&self, run: &CheckRun) {
+        if let Err(e) = self.try_record(run) {
+            self.ctx
+                .logger
+                .warn(format!("Failed to save check results to history: {}", e));
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn try_record(&self, run: &CheckRun) -> Result<(), redb::Error> {
+        let config = &self.ctx.config.history;
+        let txn = self.db.begin_write()?;
+        {
+            let mut runs = txn.open_table(RUNS)?;
+            runs.insert(run.timestamp, serde_json::to_string(run).unwrap().as_str())?;
+            if config.max_runs != 0 {
+                while runs.len()? > config.max_runs as u64 {
+                    runs.pop_first()?;
+                }
+            }
+            if config.max_age_days != 0 {
+                let cutoff = run
+                    .timestamp
+                    .saturating_sub(config.max_age_days as u64 * DAY_MS);
+                runs.retain_in(..cutoff, |_, _| false)?;
+            }
+
+            // Updates that are no longer available are forgotten, so if they reappear they'll count as new. Images which couldn't be checked keep theirs.
+            let mut first_seen = txn.open_table(FIRST_SEEN)?;
+            let current: FxHashSet<String> =
+                run.updates.iter().filter_map(Update::update_key).collect();
+            let unchecked: Vec<String> = run
+                .updates
+                .iter()
+                .filter(|update| update.is_unchecked())
+                .map(Update::image_key)
+                .collect();
+            first_seen.retain(|key, _| {
+                current.contains(key) || unchecked.iter().any(|image| key.starts_with(image))
+            })?;
+            for key in &current {
+                if first_seen.get(key.as_str())?.is_none() {
+                    first_seen.insert(key.as_str(), run.timestamp)?;
+                }
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    /// Returns the most recent check run, if there is one.
+    pub fn latest(&self) -> Option<CheckRun> {
+        self.try_runs(true)
+            .unwrap_or_else(|e| {
+                self.ctx
+                    .logger
+                    .warn(format!("Failed to read history: {}", e));
+                Vec::new()
+            })
+            .pop()
+    }
+
+    /// Returns all stored check runs, oldest first.
+    pub fn runs(&self) -> Vec<CheckRun> {
+        self.try_runs(false).unwrap_or_else(|e| {
+            self.ctx
+                .logger
+                .warn(format!("Failed to read history: {}", e));
+            Vec::new()
+        })
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn try_runs(&self, latest_only: bool) -> Result<Vec<CheckRun>, redb::Error> {
+        let txn = self.db.begin_read()?;
+        let runs = match txn.open_table(RUNS) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let entries = if latest_only {
+            runs.last()?.into_iter().collect::<Vec<_>>()
+        } else {
+            runs.iter()?.collect::<Result<Vec<_>, _>>()?
+        };
+        Ok(entries
+            .iter()
+            .filter_map(|(_, value)| serde_json::from_str::<CheckRun>(value.value()).ok())
+            .map(|mut run| {
+                // Status isn't serialized, so we need to restore it
+                run.updates
+                    .iter_mut()
+                    .for_each(|update| update.status = update.get_status());
+                run
+            })
+            .collect())
+    }
+
+    /// Returns the timestamp each of the updates passed in was first seen at. Entries are `None` for images without an update.
+    pub fn first_seen(&self, updates: &[Update]) -> Vec<Option<u64>> {
+        self.try_first_seen(updates).unwrap_or_else(|e| {
+            self.ctx
+                .logger
+                .warn(format!("Failed to read history: {}", e));
+            vec![None; updates.len()]
+        })
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn try_first_seen(&self, updates: &[Update]) -> Result<Vec<Option<u64>>, redb::Error> {
+        let txn = self.db.begin_read()?;
+        let table = match txn.open_table(FIRST_SEEN) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(vec![None; updates.len()]),
+            Err(e) => return Err(e.into()),
+        };
+        updates
+            .iter()
+            .map(|update| match update.update_key() {
+                Some(key) => Ok(table.get(key.as_str())?.map(|value| value.value())),
+                None => Ok(None),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        logging::Logger,
+        structs::update::{fixtures::create_digest_update, UpdateResult},
+    };
+
+    use super::*;
+
+    /// Test that runs are stored and retrieved, that retention limits are applied and that the first time an update was seen is remembered
+    #[test]
+    fn history() {
+        let directory = std::env::temp_dir().join(format!("cup-history-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let mut config = Config::new();
+        config.data_directory = Some(directory.to_str().unwrap().to_string());
+        config.history.max_runs = 2;
+        let ctx = Context {
+            config,
+            logger: Logger::new(false, true),
+        };
+        let history = History::open(&ctx).unwrap();
+        assert_eq!(history.latest(), None);
+
+        let outdated = create_digest_update("alpine", "sha256:1");
+        let up_to_date = Update {
+            reference: String::from("busybox"),
+            result: UpdateResult {
+                has_update: Some(false),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let runs = [
+            create_run(1000, vec![outdated.clone(), up_to_date.clone()]),
+            create_run(2000, vec![outdated.clone(), up_to_date.clone()]),
+            create_run(3000, vec![create_digest_update("alpine", "sha256:2")]),
+        ];
+        history.record(&runs[0]);
+        history.record(&runs[1]);
+        assert_eq!(
+            history.first_seen(&[outdated.clone(), up_to_date]),
+            vec![Some(1000), None]
+        );
+
+        history.record(&runs[2]);
+        assert_eq!(history.latest().map(|run| run.timestamp), Some(3000));
+        assert_eq!(history.runs().len(), 2);
+        assert_eq!(
+            history.first_seen(&[outdated, runs[2].updates[0].clone()]),
+            vec![None, Some(3000)]
+        );
+
+        // Images which couldn't be checked keep their updates' first sighting
+        let unknown = Update {
+            reference: String::from("alpine"),
+            result: UpdateResult {
+                has_update: None,
+                error: Some(String::from("Connection failed!")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        history.record(&create_run(4000, vec![unknown]));
+        history.record(&create_run(5000, runs[2].updates.clone()));
+        assert_eq!(history.first_seen(&runs[2].updates), vec![Some(3000)]);
+
+        drop(history);
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    fn create_run(timestamp: u64, updates: Vec<Update>) -> CheckRun {
+        CheckRun {
+            timestamp,
+            duration: 0,
+            updates,
+        }
+    }
+}
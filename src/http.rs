@@ -1,14 +1,31 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
-use reqwest::Response;
+use once_cell::sync::Lazy;
+use reqwest::{Response, Url};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use tokio::sync::{Semaphore, SemaphorePermit};
 
-use crate::{error, Context};
+use crate::{
+    config::Config,
+    error::Error,
+    metrics,
+    utils::time::{elapsed, now},
+    Context,
+};
 
 pub enum RequestMethod {
     GET,
     HEAD,
+    POST,
+    PUT,
 }
 
 impl Display for RequestMethod {
@@ -16,13 +33,128 @@ impl Display for RequestMethod {
         f.write_str(match self {
             RequestMethod::GET => "GET",
             RequestMethod::HEAD => "HEAD",
+            RequestMethod::POST => "POST",
+            RequestMethod::PUT => "PUT",
         })
     }
 }
 
+/// A registry's pull quota, as reported by the `ratelimit-limit` and `ratelimit-remaining` headers (e.g. Docker Hub's)
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct RateLimit {
+    pub limit: u32,
+    pub remaining: u32,
+}
+
+impl RateLimit {
+    /// How many more requests can be made before the quota is nearly exhausted. The last 10% are kept for pulling updates.
+    pub fn budget(&self) -> u32 {
+        self.remaining.saturating_sub(self.limit / 10)
+    }
+}
+
+/// The last rate limit each registry reported, indexed by registry
+static RATE_LIMITS: Lazy<Mutex<FxHashMap<String, RateLimit>>> =
+    Lazy::new(|| Mutex::new(FxHashMap::default()));
+
+/// Returns the last rate limit a registry reported, if it has one
+pub fn rate_limit(registry: &str) -> Option<RateLimit> {
+    RATE_LIMITS.lock().unwrap().get(registry).copied()
+}
+
+/// Returns the last rate limit reported by each registry which has one
+pub fn rate_limits() -> FxHashMap<String, RateLimit> {
+    RATE_LIMITS.lock().unwrap().clone()
+}
+
+/// Parses a rate limit header, which looks like `100;w=21600` (the quota, followed by the window in seconds)
+fn parse_rate_limit_header(response: &Response, name: &str) -> Option<u32> {
+    response
+        .headers()
+        .get(name)?
+        .to_str()
+        .ok()?
+        .split(';')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Limits on how many requests are sent at once and how often, configured globally and for each registry
+#[derive(Default)]
+struct Limits {
+    global: Option<Semaphore>,
+    registries: FxHashMap<String, RegistryLimits>,
+}
+
+struct RegistryLimits {
+    concurrency: Option<Semaphore>,
+    /// The minimum time between the start of two requests
+    interval: Option<Duration>,
+    /// The time the next request may start at
+    next: Mutex<Instant>,
+}
+
+impl Limits {
+    fn new(config: &Config) -> Self {
+        let semaphore = |permits: usize| (permits != 0).then(|| Semaphore::new(permits));
+        Self {
+            global: semaphore(config.max_concurrent_requests),
+            registries: config
+                .registries
+                .iter()
+                .filter(|(_, registry_config)| {
+                    registry_config.max_concurrent_requests != 0
+                        || registry_config.requests_per_second > 0.0
+                })
+                .map(|(registry, registry_config)| {
+                    (
+                        registry.clone(),
+                        RegistryLimits {
+                            concurrency: semaphore(registry_config.max_concurrent_requests),
+                            interval: (registry_config.requests_per_second > 0.0).then(|| {
+                                Duration::from_secs_f64(1.0 / registry_config.requests_per_second)
+                            }),
+                            next: Mutex::new(Instant::now()),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Waits until a request to the registry is allowed. The returned permits must be held until the request is done.
+    async fn acquire(&self, registry: Option<&str>) -> Vec<SemaphorePermit<'_>> {
+        let mut permits = Vec::new();
+        // Wait for the registry first, so requests to other registries can use the global permits in the meantime
+        if let Some(limits) = registry.and_then(|registry| self.registries.get(registry)) {
+            if let Some(semaphore) = &limits.concurrency {
+                permits.push(semaphore.acquire().await.unwrap());
+            }
+            if let Some(interval) = limits.interval {
+                let slot = {
+                    let mut next = limits.next.lock().unwrap();
+                    let slot = (*next).max(Instant::now());
+                    *next = slot + interval;
+                    slot
+                };
+                tokio::time::sleep_until(slot.into()).await;
+            }
+        }
+        if let Some(semaphore) = &self.global {
+            permits.push(semaphore.acquire().await.unwrap());
+        }
+        permits
+    }
+}
+
 /// A struct for handling HTTP requests. Takes care of the repetitive work of checking for errors, etc and exposes a simple interface
 pub struct Client {
     inner: ClientWithMiddleware,
+    limits: Limits,
+    /// How many more requests counting towards its rate limit can be sent to each registry during this check. Registries which aren't listed are unlimited.
+    budgets: Mutex<FxHashMap<String, u32>>,
     ctx: Context,
 }
 
@@ -34,92 +166,150 @@ impl Client {
                     ExponentialBackoff::builder().build_with_max_retries(3),
                 ))
                 .build(),
+            limits: Limits::new(&ctx.config),
+            budgets: Mutex::new(FxHashMap::default()),
             ctx: ctx.clone(),
         }
     }
 
+    /// Limits how many requests counting towards the registry's rate limit may be sent
+    pub fn set_budget(&self, registry: &str, budget: u32) {
+        self.budgets
+            .lock()
+            .unwrap()
+            .insert(registry.to_string(), budget);
+    }
+
+    /// Takes a request from the registry's budget. Returns false if none are left.
+    pub fn spend(&self, registry: &str) -> bool {
+        match self.budgets.lock().unwrap().get_mut(registry) {
+            Some(0) => false,
+            Some(budget) => {
+                *budget -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+
     async fn request(
         &self,
         url: &str,
         method: RequestMethod,
         headers: &[(&str, Option<&str>)],
+        body: Option<String>,
         ignore_401: bool,
-    ) -> Result<Response, String> {
+    ) -> Result<Response, Error> {
         let mut request = match method {
             RequestMethod::GET => self.inner.get(url),
             RequestMethod::HEAD => self.inner.head(url),
+            RequestMethod::POST => self.inner.post(url),
+            RequestMethod::PUT => self.inner.put(url),
         };
         for (name, value) in headers {
             if let Some(v) = value {
                 request = request.header(*name, *v)
             }
         }
-        match request.send().await {
+        if let Some(body) = body {
+            request = request.body(body)
+        }
+        let parsed = Url::from_str(url).ok();
+        let registry = parsed.as_ref().and_then(|parsed| {
+            parsed.host_str().map(|host| match parsed.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_string(),
+            })
+        });
+        let start = now();
+        let permits = self.limits.acquire(registry.as_deref()).await;
+        let queued = elapsed(start);
+        if queued != 0 {
+            self.ctx.logger.debug(format!(
+                "{} {}: Waited {}ms for a free request slot",
+                method, url, queued
+            ));
+        }
+        let start = now();
+        let result = request.send().await;
+        drop(permits);
+        // Only registry API requests are interesting for metrics and rate limits, not auth servers or notification targets
+        if let Some(parsed) = parsed {
+            if parsed.path().starts_with("/v2/") {
+                if let Some(registry) = registry {
+                    metrics::observe_request(&registry, elapsed(start));
+                    if let Ok(response) = &result {
+                        if let (Some(limit), Some(remaining)) = (
+                            parse_rate_limit_header(response, "ratelimit-limit"),
+                            parse_rate_limit_header(response, "ratelimit-remaining"),
+                        ) {
+                            self.ctx.logger.debug(format!(
+                                "{} rate limit: {} of {} requests remaining",
+                                registry, remaining, limit
+                            ));
+                            RATE_LIMITS
+                                .lock()
+                                .unwrap()
+                                .insert(registry, RateLimit { limit, remaining });
+                        }
+                    }
+                }
+            }
+        }
+        let request = format!("{} {}", method, url);
+        let error = match result {
             Ok(response) => {
                 let status = response.status();
                 if status == 404 {
-                    let message = format!("{} {}: Not found!", method, url);
-                    self.ctx.logger.warn(&message);
-                    Err(message)
+                    Error::NotFound { request }
                 } else if status == 401 {
                     if ignore_401 {
-                        Ok(response)
-                    } else {
-                        let message = format!("{} {}: Unauthorized! Please configure authentication for this registry or if you have already done so, please make sure it is correct.", method, url);
-                        self.ctx.logger.warn(&message);
-                        Err(message)
+                        return Ok(response);
                     }
+                    Error::Unauthorized { request }
                 } else if status == 403 {
-                    let message = format!("{} {}: Forbidden! If you've configured authentication for this registry, make sure it is correct. Otherwise there is a chance that the registry is down and a proxy is returning an error.", method, url);
-                    self.ctx.logger.warn(&message);
-                    Err(message)
+                    Error::Forbidden { request }
+                } else if status == 429 {
+                    Error::TooManyRequests { request }
                 } else if status == 502 || status == 503 {
-                    let message = format!(
-                        "{} {}: The registry is currently unavailabile (returned status code {}).",
-                        method, url, status
-                    );
-                    self.ctx.logger.warn(&message);
-                    Err(message)
-                } else if status.as_u16() <= 400 {
-                    Ok(response)
+                    Error::Unavailable {
+                        request,
+                        status: status.as_u16(),
+                    }
+                } else if status.is_success() || status.is_redirection() {
+                    // Redirections which weren't followed are answers too, e.g. 304 Not Modified for conditional requests
+                    return Ok(response);
                 } else {
-                    match method {
-                        RequestMethod::GET => error!(
-                            "{} {}: Unexpected error: {}",
-                            method,
-                            url,
-                            response.text().await.unwrap()
-                        ),
-                        RequestMethod::HEAD => error!(
-                            "{} {}: Unexpected error: Recieved status code {}",
-                            method, url, status
-                        ),
+                    if let RequestMethod::GET = method {
+                        self.ctx.logger.debug(format!(
+                            "{}: Response body: {}",
+                            request,
+                            response.text().await.unwrap_or_default()
+                        ));
+                    }
+                    Error::UnexpectedStatus {
+                        request,
+                        status: status.as_u16(),
                     }
                 }
             }
             Err(error) => {
                 if error.is_connect() {
-                    let message = format!("{} {}: Connection failed!", method, url);
-                    self.ctx.logger.warn(&message);
-                    Err(message)
+                    Error::ConnectionFailed { request }
                 } else if error.is_timeout() {
-                    let message = format!("{} {}: Connection timed out!", method, url);
-                    self.ctx.logger.warn(&message);
-                    Err(message)
+                    Error::Timeout { request }
                 } else if error.is_middleware() {
-                    let message = format!("{} {}: Connection failed after 3 retries!", method, url);
-                    self.ctx.logger.warn(&message);
-                    Err(message)
+                    Error::TooManyRetries { request }
                 } else {
-                    error!(
-                        "{} {}: Unexpected error: {}",
-                        method,
-                        url,
-                        error.to_string()
-                    )
+                    Error::RequestFailed {
+                        request,
+                        message: error.to_string(),
+                    }
                 }
             }
-        }
+        };
+        self.ctx.logger.warn(error.to_string());
+        Err(error)
     }
 
     pub async fn get(
@@ -127,8 +317,8 @@ impl Client {
         url: &str,
         headers: &[(&str, Option<&str>)],
         ignore_401: bool,
-    ) -> Result<Response, String> {
-        self.request(url, RequestMethod::GET, headers, ignore_401)
+    ) -> Result<Response, Error> {
+        self.request(url, RequestMethod::GET, headers, None, ignore_401)
             .await
     }
 
@@ -136,7 +326,107 @@ impl Client {
         &self,
         url: &str,
         headers: &[(&str, Option<&str>)],
-    ) -> Result<Response, String> {
-        self.request(url, RequestMethod::HEAD, headers, false).await
+        ignore_401: bool,
+    ) -> Result<Response, Error> {
+        self.request(url, RequestMethod::HEAD, headers, None, ignore_401)
+            .await
+    }
+
+    pub async fn post(
+        &self,
+        url: &str,
+        headers: &[(&str, Option<&str>)],
+        body: String,
+    ) -> Result<Response, Error> {
+        self.request(url, RequestMethod::POST, headers, Some(body), false)
+            .await
+    }
+
+    pub async fn put(
+        &self,
+        url: &str,
+        headers: &[(&str, Option<&str>)],
+        body: String,
+    ) -> Result<Response, Error> {
+        self.request(url, RequestMethod::PUT, headers, Some(body), false)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::timeout;
+
+    use crate::{
+        config::RegistryConfig,
+        logging::Logger,
+        utils::test_server::{serve, Response},
+    };
+
+    use super::*;
+
+    /// Test that concurrency and request per second limits make requests wait
+    #[tokio::test]
+    async fn limits() {
+        let mut config = Config::new();
+        config.max_concurrent_requests = 1;
+        config.registries.insert(
+            String::from("ghcr.io"),
+            RegistryConfig {
+                requests_per_second: 20.0,
+                ..Default::default()
+            },
+        );
+        let limits = Limits::new(&config);
+        let wait = Duration::from_millis(20);
+
+        let permits = limits.acquire(Some("quay.io")).await;
+        assert!(timeout(wait, limits.acquire(Some("ghcr.io")))
+            .await
+            .is_err());
+        drop(permits);
+        assert!(timeout(wait, limits.acquire(Some("quay.io"))).await.is_ok());
+
+        let start = Instant::now();
+        for _ in 0..4 {
+            limits.acquire(Some("ghcr.io")).await;
+        }
+        // The first request was made above, so the next 4 are spaced 50ms apart
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    /// Test that rate limit headers sent by registries are recorded
+    #[tokio::test]
+    async fn rate_limits() {
+        let (url, _requests) = serve(|_| {
+            Response::new(200, "")
+                .header("RateLimit-Limit", "100;w=21600")
+                .header("RateLimit-Remaining", "76;w=21600")
+        })
+        .await;
+        let ctx = Context {
+            config: Config::new(),
+            logger: Logger::new(false, false),
+        };
+        let registry = url.trim_start_matches("http://");
+        assert_eq!(rate_limit(registry), None);
+
+        Client::new(&ctx)
+            .head(
+                &format!("{}/v2/library/alpine/manifests/latest", url),
+                &[],
+                false,
+            )
+            .await
+            .unwrap();
+        let limit = rate_limit(registry).unwrap();
+        assert_eq!(
+            limit,
+            RateLimit {
+                limit: 100,
+                remaining: 76
+            }
+        );
+        assert_eq!(limit.budget(), 66);
     }
 }
@@ -0,0 +1,86 @@
+//! Cup checks container images for updates.
+//!
+//! Besides the `cup` binary, Cup's checking logic can be used as a library. The items exported from the crate root,
+//! along with the [`config`], [`error`], [`http`], [`logging`], [`registry`] and [`structs`] modules, are the public API
+//! and follow semantic versioning. Other modules are only public so the binary can use them and may change at any time.
+//!
+//! Reading local images from Docker (and compatible runtimes) needs the `docker` feature, which is enabled by default.
+//! Without it, only the references passed to [`get_updates`] and the ones in the configuration are checked.
+//! Failing to read the local images is returned as an [`Error`], while errors with single images are part of their results:
+//!
+//! ```no_run
+//! use cup::{config::RuntimeType, get_updates, logging::Logger, Config, Context, Error};
+//!
+//! # async fn run() -> Result<(), Error> {
+//! let mut config = Config::new();
+//! config.runtime = RuntimeType::None; // Don't look for local images
+//! let ctx = Context::new(config, Logger::new(false, false));
+//!
+//! let references = Some(vec![String::from("ghcr.io/sergi0g/cup:v3.0.0")]);
+//! for update in get_updates(&references, false, &ctx).await? {
+//!     println!("{}: {}", update.reference, update.status);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub use check::get_updates;
+pub use config::Config;
+pub use error::Error;
+pub use http::Client;
+pub use logging::Logger;
+pub use structs::{image::Image, status::Status, update::Update, version::Version};
+
+#[doc(hidden)]
+pub mod acknowledgements;
+#[doc(hidden)]
+#[cfg(feature = "docker")]
+pub mod apply;
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod check;
+#[doc(hidden)]
+pub mod compose;
+pub mod config;
+#[doc(hidden)]
+pub mod credentials;
+#[doc(hidden)]
+pub mod data_store;
+pub mod error;
+#[doc(hidden)]
+#[cfg(feature = "cli")]
+pub mod formatting;
+#[doc(hidden)]
+pub mod history;
+pub mod http;
+pub mod logging;
+#[doc(hidden)]
+pub mod metrics;
+#[doc(hidden)]
+#[cfg(any(feature = "cli", feature = "server"))]
+pub mod notifications;
+pub mod registry;
+#[doc(hidden)]
+pub mod releases;
+#[doc(hidden)]
+pub mod runtime;
+#[doc(hidden)]
+#[cfg(feature = "server")]
+pub mod server;
+pub mod structs;
+#[doc(hidden)]
+pub mod utils;
+
+/// Everything checks need: the configuration and where to log to
+#[derive(Clone)]
+pub struct Context {
+    pub config: Config,
+    pub logger: Logger,
+}
+
+impl Context {
+    pub fn new(config: Config, logger: Logger) -> Self {
+        Self { config, logger }
+    }
+}
@@ -1,27 +1,20 @@
-use check::get_updates;
 use clap::{Parser, Subcommand};
-use config::Config;
-use formatting::spinner::Spinner;
-#[cfg(feature = "cli")]
-use formatting::{print_raw_updates, print_updates};
-use logging::Logger;
 #[cfg(feature = "server")]
-use server::serve;
+use cup::server::serve;
+#[cfg(feature = "cli")]
+use cup::{
+    apply::{applicable, apply_updates, ContainerUpdateResult},
+    compose::write_compose_files,
+    formatting::{print_raw_updates, print_updates, spinner::Spinner},
+    get_updates,
+    history::{CheckRun, History},
+    notifications::notify,
+    utils::time::timestamp,
+};
+use cup::{error, logging::Logger, Config, Context};
 use std::path::PathBuf;
-use std::time::SystemTime;
-
-pub mod check;
-pub mod config;
-pub mod docker;
 #[cfg(feature = "cli")]
-pub mod formatting;
-pub mod http;
-pub mod logging;
-pub mod registry;
-#[cfg(feature = "server")]
-pub mod server;
-pub mod structs;
-pub mod utils;
+use std::time::SystemTime;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -53,6 +46,30 @@ enum Commands {
             help = "Output JSON instead of formatted text"
         )]
         raw: bool,
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Update image tags in Compose files when a new version is available"
+        )]
+        write_compose: bool,
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Fetch and show what changed for images with a digest update"
+        )]
+        details: bool,
+    },
+    #[cfg(feature = "cli")]
+    Update {
+        #[arg(name = "images", default_value = None)]
+        references: Option<Vec<String>>,
+        #[arg(
+            short,
+            long,
+            default_value_t = false,
+            help = "Update all images with updates available"
+        )]
+        all: bool,
     },
     #[cfg(feature = "server")]
     Serve {
@@ -66,12 +83,6 @@ enum Commands {
     },
 }
 
-#[derive(Clone)]
-pub struct Context {
-    pub config: Config,
-    pub logger: Logger,
-}
-
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
@@ -93,24 +104,112 @@ async fn main() {
             references,
             icons,
             raw,
+            write_compose,
+            details,
         }) => {
             let start = SystemTime::now();
+            let started_at = timestamp();
             if *raw {
                 ctx.logger.set_raw(true);
             }
-            match *raw || cli.debug {
+            if *details {
+                ctx.config.details = true;
+            }
+            let updates = match *raw || cli.debug {
                 true => {
-                    let updates = get_updates(references, cli.refresh, &ctx).await;
+                    let updates = get_updates(references, cli.refresh, &ctx)
+                        .await
+                        .unwrap_or_else(|e| error!("{}", e));
                     print_raw_updates(&updates);
+                    updates
                 }
                 false => {
                     let spinner = Spinner::new();
-                    let updates = get_updates(references, cli.refresh, &ctx).await;
+                    let updates = get_updates(references, cli.refresh, &ctx)
+                        .await
+                        .unwrap_or_else(|e| error!("{}", e));
                     spinner.succeed();
-                    print_updates(&updates, icons);
+                    print_updates(&updates, icons, details);
                     ctx.logger.info(format!("✨ Checked {} images in {}ms", updates.len(), start.elapsed().unwrap().as_millis()));
+                    updates
                 }
             };
+            if *write_compose {
+                for file in write_compose_files(&updates, &ctx) {
+                    ctx.logger.info(format!("Updated {}", file));
+                }
+            }
+            // Only complete checks are recorded and notified about, otherwise the server would load partial results on startup and images which weren't checked would look like their updates are gone
+            if references.is_none() {
+                notify(&updates, &ctx).await;
+                if let Some(history) = History::open(&ctx) {
+                    history.record(&CheckRun {
+                        timestamp: started_at,
+                        duration: start.elapsed().unwrap().as_millis() as u32,
+                        updates,
+                    });
+                }
+            }
+        }
+        #[cfg(feature = "cli")]
+        Some(Commands::Update { references, all }) => {
+            if references.is_none() && !all {
+                error!("Please specify the images you want to update, or pass `--all` to update everything.")
+            }
+            let spinner = Spinner::new();
+            let updates = get_updates(references, cli.refresh, &ctx)
+                        .await
+                        .unwrap_or_else(|e| error!("{}", e));
+            spinner.succeed();
+            let updates = applicable(&updates, references);
+            if updates.is_empty() {
+                ctx.logger.info("No updates to apply");
+                return;
+            }
+            let mut failed = false;
+            for update in apply_updates(&updates, &ctx).await {
+                // Containers on hosts are prefixed with the host's name, like in notifications
+                let prefix = match &update.host {
+                    Some(host) => format!("[{}] ", host),
+                    None => String::new(),
+                };
+                if let Some(error) = update.error {
+                    ctx.logger.warn(format!("Failed to update {}{}: {}", prefix, update.reference, error));
+                    failed = true;
+                    continue;
+                }
+                for container in update.containers {
+                    match container.result {
+                        ContainerUpdateResult::Updated => {
+                            ctx.logger.info(format!(
+                                "Updated {}{} ({} → {})",
+                                prefix, container.name, update.reference, update.new_reference
+                            ));
+                            if let Some(error) = container.error {
+                                ctx.logger.warn(error)
+                            }
+                        }
+                        ContainerUpdateResult::RolledBack => ctx.logger.warn(format!(
+                            "Rolled back {}{}: {}",
+                            prefix,
+                            container.name,
+                            container.error.unwrap_or_default()
+                        )),
+                        ContainerUpdateResult::Failed => {
+                            failed = true;
+                            ctx.logger.warn(format!(
+                                "Failed to update {}{}: {}",
+                                prefix,
+                                container.name,
+                                container.error.unwrap_or_default()
+                            ))
+                        }
+                    }
+                }
+            }
+            if failed {
+                std::process::exit(1);
+            }
         }
         #[cfg(feature = "server")]
         Some(Commands::Serve { port }) => {
@@ -0,0 +1,205 @@
+// Prometheus metrics. Histograms are process-wide, so they keep accumulating between checks like Prometheus expects.
+
+use std::{fmt::Write, sync::Mutex};
+
+use once_cell::sync::Lazy;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    structs::{status::Status, update::Update},
+    utils::json::get_metrics,
+};
+
+/// Bucket upper bounds in seconds
+const BUCKETS: [f64; 10] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Time taken to check each image
+static CHECK_DURATION: Lazy<Mutex<Histogram>> = Lazy::new(|| Mutex::new(Histogram::default()));
+/// Time taken by requests to each registry, indexed by registry
+static REQUEST_DURATION: Lazy<Mutex<FxHashMap<String, Histogram>>> =
+    Lazy::new(|| Mutex::new(FxHashMap::default()));
+
+#[derive(Default, Clone)]
+pub struct Histogram {
+    counts: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, seconds: f64) {
+        for (bound, count) in BUCKETS.iter().zip(self.counts.iter_mut()) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    /// Appends the histogram's samples to `out`. `labels` are prepended to the `le` label and must already be formatted.
+    fn render(&self, name: &str, labels: &str, out: &mut String) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        for (bound, count) in BUCKETS.iter().zip(self.counts) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, separator, bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+            name, labels, separator, self.count
+        );
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+        let _ = writeln!(out, "{}_sum{} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, labels, self.count);
+    }
+}
+
+/// Records the time it took to check an image
+pub fn observe_check(time_ms: u32) {
+    CHECK_DURATION
+        .lock()
+        .unwrap()
+        .observe(time_ms as f64 / 1000.0);
+}
+
+/// Records the time a request to a registry took
+pub fn observe_request(registry: &str, time_ms: u32) {
+    REQUEST_DURATION
+        .lock()
+        .unwrap()
+        .entry(registry.to_string())
+        .or_default()
+        .observe(time_ms as f64 / 1000.0);
+}
+
+/// Renders metrics for the updates passed in, along with the accumulated histograms, in the Prometheus text format
+pub fn render(updates: &[Update]) -> String {
+    let mut out = String::new();
+    if let Some(metrics) = get_metrics(updates).as_object() {
+        for (key, value) in metrics {
+            let _ = writeln!(out, "# TYPE cup_{} gauge\ncup_{} {}", key, key, value);
+        }
+    }
+
+    out.push_str("# HELP cup_image_update_available Whether an update is available for the image (1) or not (0)\n# TYPE cup_image_update_available gauge\n");
+    for update in updates {
+        let status = update.get_status();
+        let _ = writeln!(
+            out,
+            "cup_image_update_available{{{},update_type=\"{}\"}} {}",
+            image_labels(update),
+            match status {
+                Status::UpdateMajor => "major",
+                Status::UpdateMinor => "minor",
+                Status::UpdatePatch => "patch",
+                Status::UpdateAvailable => "digest",
+                Status::Acknowledged => "acknowledged",
+                Status::UpToDate => "none",
+                Status::Deferred => "deferred",
+                Status::Unknown(_) => "unknown",
+            },
+            i32::from(status.to_option_bool() == Some(true))
+        );
+    }
+
+    out.push_str("# HELP cup_image_check_duration_seconds Time taken to check the image during the last check\n# TYPE cup_image_check_duration_seconds gauge\n");
+    for update in updates {
+        let _ = writeln!(
+            out,
+            "cup_image_check_duration_seconds{{{}}} {}",
+            image_labels(update),
+            update.time as f64 / 1000.0
+        );
+    }
+
+    out.push_str("# HELP cup_check_duration_seconds Time taken to check an image\n# TYPE cup_check_duration_seconds histogram\n");
+    CHECK_DURATION
+        .lock()
+        .unwrap()
+        .render("cup_check_duration_seconds", "", &mut out);
+
+    out.push_str("# HELP cup_registry_request_duration_seconds Time taken by requests to registries\n# TYPE cup_registry_request_duration_seconds histogram\n");
+    let mut registries: Vec<(String, Histogram)> = REQUEST_DURATION
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(registry, histogram)| (registry.clone(), histogram.clone()))
+        .collect();
+    registries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    for (registry, histogram) in registries {
+        histogram.render(
+            "cup_registry_request_duration_seconds",
+            &format!("registry=\"{}\"", escape(&registry)),
+            &mut out,
+        );
+    }
+    out
+}
+
+fn image_labels(update: &Update) -> String {
+    format!(
+        "reference=\"{}\",registry=\"{}\",server=\"{}\",in_use=\"{}\"",
+        escape(&update.reference),
+        escape(&update.parts.registry),
+        escape(update.server.as_deref().unwrap_or("")),
+        update.in_use
+    )
+}
+
+/// Escapes a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{parts::Parts, update::UpdateResult};
+
+    use super::*;
+
+    /// Test that image gauges are labelled correctly and histograms are cumulative
+    #[test]
+    fn prometheus() {
+        let update = Update {
+            reference: String::from("ghcr.io/sergi0g/cup:latest"),
+            parts: Parts {
+                registry: String::from("ghcr.io"),
+                repository: String::from("sergi0g/cup"),
+                tag: String::from("latest"),
+            },
+            result: UpdateResult {
+                has_update: Some(false),
+                ..Default::default()
+            },
+            time: 250,
+            in_use: true,
+            status: Status::UpToDate,
+            ..Default::default()
+        };
+        observe_request("ghcr.io", 80);
+        observe_request("ghcr.io", 300);
+        let output = render(&[update]);
+        assert!(output.contains("cup_monitored_images 1\n"));
+        assert!(output.contains("cup_image_update_available{reference=\"ghcr.io/sergi0g/cup:latest\",registry=\"ghcr.io\",server=\"\",in_use=\"true\",update_type=\"none\"} 0\n"));
+        assert!(output.contains("cup_image_check_duration_seconds{reference=\"ghcr.io/sergi0g/cup:latest\",registry=\"ghcr.io\",server=\"\",in_use=\"true\"} 0.25\n"));
+        assert!(output.contains(
+            "cup_registry_request_duration_seconds_bucket{registry=\"ghcr.io\",le=\"0.1\"} 1\n"
+        ));
+        assert!(output.contains(
+            "cup_registry_request_duration_seconds_bucket{registry=\"ghcr.io\",le=\"0.5\"} 2\n"
+        ));
+        assert!(output
+            .contains("cup_registry_request_duration_seconds_count{registry=\"ghcr.io\"} 2\n"));
+    }
+}
@@ -0,0 +1,45 @@
+use futures::future::BoxFuture;
+use serde_json::json;
+
+use crate::{
+    config::{DiscordConfig, NotificationStatus},
+    http::Client,
+    structs::update::Update,
+    Context,
+};
+
+use super::{message, title, Notifier};
+
+/// Discord rejects messages longer than this
+const MAX_LENGTH: usize = 2000;
+
+impl Notifier for DiscordConfig {
+    fn statuses(&self) -> &[NotificationStatus] {
+        &self.statuses
+    }
+
+    /// Executes the webhook
+    fn send<'a>(
+        &'a self,
+        updates: &'a [&'a Update],
+        _ctx: &'a Context,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let mut content = format!("**{}**\n{}", title(updates), message(updates));
+            if content.chars().count() > MAX_LENGTH {
+                content = content.chars().take(MAX_LENGTH - 1).collect::<String>() + "…";
+            }
+            let body = json!({
+                "content": content,
+                "username": self.username.as_deref().unwrap_or("Cup"),
+            });
+            let headers = [("Content-Type", Some("application/json"))];
+            client
+                .post(&self.webhook_url, &headers, body.to_string())
+                .await
+                .map(|_| ())
+                .map_err(String::from)
+        })
+    }
+}
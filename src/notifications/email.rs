@@ -0,0 +1,68 @@
+use futures::future::BoxFuture;
+use lettre::{
+    transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Message,
+    Tokio1Executor,
+};
+
+use crate::{
+    config::{EmailConfig, NotificationStatus, SmtpSecurity},
+    http::Client,
+    structs::update::Update,
+    Context,
+};
+
+use super::{message, title, Notifier};
+
+impl Notifier for EmailConfig {
+    fn statuses(&self) -> &[NotificationStatus] {
+        &self.statuses
+    }
+
+    /// Sends an email through the configured SMTP server
+    fn send<'a>(
+        &'a self,
+        updates: &'a [&'a Update],
+        _ctx: &'a Context,
+        _client: &'a Client,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let mut builder = Message::builder()
+                .from(
+                    self.from
+                        .parse()
+                        .map_err(|e| format!("Invalid sender {}. {}", self.from, e))?,
+                )
+                .subject(title(updates));
+            for recipient in &self.to {
+                builder = builder.to(recipient
+                    .parse()
+                    .map_err(|e| format!("Invalid recipient {}. {}", recipient, e))?);
+            }
+            let email = builder.body(message(updates)).map_err(|e| e.to_string())?;
+
+            let mut transport = match self.security {
+                SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host),
+                SmtpSecurity::Starttls => {
+                    AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)
+                }
+                SmtpSecurity::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                    &self.host,
+                )),
+            }
+            .map_err(|e| e.to_string())?;
+            if let Some(port) = self.port {
+                transport = transport.port(port);
+            }
+            if let (Some(username), Some(password)) = (&self.username, &self.password) {
+                transport = transport
+                    .credentials(Credentials::new(username.to_string(), password.to_string()));
+            }
+            transport
+                .build()
+                .send(email)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+    }
+}
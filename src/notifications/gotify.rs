@@ -0,0 +1,43 @@
+use futures::future::BoxFuture;
+use serde_json::json;
+
+use crate::{
+    config::{GotifyConfig, NotificationStatus},
+    http::Client,
+    structs::update::Update,
+    Context,
+};
+
+use super::{message, title, Notifier};
+
+impl Notifier for GotifyConfig {
+    fn statuses(&self) -> &[NotificationStatus] {
+        &self.statuses
+    }
+
+    /// Creates a message using the application token
+    fn send<'a>(
+        &'a self,
+        updates: &'a [&'a Update],
+        _ctx: &'a Context,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let url = format!("{}/message", self.url.trim_end_matches('/'));
+            let body = json!({
+                "title": title(updates),
+                "message": message(updates),
+                "priority": self.priority.unwrap_or(5),
+            });
+            let headers = [
+                ("Content-Type", Some("application/json")),
+                ("X-Gotify-Key", Some(self.token.as_str())),
+            ];
+            client
+                .post(&url, &headers, body.to_string())
+                .await
+                .map(|_| ())
+                .map_err(String::from)
+        })
+    }
+}
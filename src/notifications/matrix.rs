@@ -0,0 +1,62 @@
+use std::str::FromStr;
+
+use futures::future::BoxFuture;
+use reqwest::Url;
+use serde_json::json;
+
+use crate::{
+    config::{MatrixConfig, NotificationStatus},
+    http::Client,
+    structs::update::Update,
+    utils::time::timestamp,
+    Context,
+};
+
+use super::{message, title, Notifier};
+
+impl Notifier for MatrixConfig {
+    fn statuses(&self) -> &[NotificationStatus] {
+        &self.statuses
+    }
+
+    /// Sends a text message to the room using the client-server API
+    fn send<'a>(
+        &'a self,
+        updates: &'a [&'a Update],
+        _ctx: &'a Context,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let mut url = Url::from_str(&self.homeserver)
+                .map_err(|e| format!("Invalid homeserver URL {}. {}", self.homeserver, e))?;
+            // Pushing the segments takes care of escaping the room ID
+            url.path_segments_mut()
+                .map_err(|_| format!("Invalid homeserver URL {}", self.homeserver))?
+                .pop_if_empty()
+                .extend([
+                    "_matrix",
+                    "client",
+                    "v3",
+                    "rooms",
+                    &self.room_id,
+                    "send",
+                    "m.room.message",
+                    &timestamp().to_string(),
+                ]);
+            let body = json!({
+                "msgtype": "m.text",
+                "body": format!("{}\n{}", title(updates), message(updates)),
+            });
+            let authorization = format!("Bearer {}", self.access_token);
+            let headers = [
+                ("Content-Type", Some("application/json")),
+                ("Authorization", Some(authorization.as_str())),
+            ];
+            client
+                .put(url.as_str(), &headers, body.to_string())
+                .await
+                .map(|_| ())
+                .map_err(String::from)
+        })
+    }
+}
@@ -0,0 +1,185 @@
+use futures::future::{join_all, BoxFuture};
+use rustc_hash::FxHashMap;
+use tokio::sync::Mutex;
+
+use crate::{
+    config::{NotificationConfig, NotificationStatus},
+    http::Client,
+    structs::{
+        status::Status,
+        update::{Update, UpdateInfo},
+    },
+    Context,
+};
+
+pub mod discord;
+pub mod email;
+pub mod gotify;
+pub mod matrix;
+pub mod notified;
+pub mod ntfy;
+pub mod slack;
+pub mod webhook;
+
+use notified::Notified;
+
+/// A notification target. Implemented by the config of each backend.
+pub trait Notifier: Sync {
+    /// The update types this target wants to be notified about. If empty, it's notified about all of them.
+    fn statuses(&self) -> &[NotificationStatus];
+
+    /// Sends a notification about the updates passed in
+    fn send<'a>(
+        &'a self,
+        updates: &'a [&'a Update],
+        ctx: &'a Context,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<(), String>>;
+}
+
+impl NotificationStatus {
+    pub fn matches(&self, status: &Status) -> bool {
+        matches!(
+            (self, status),
+            (Self::Major, Status::UpdateMajor)
+                | (Self::Minor, Status::UpdateMinor)
+                | (Self::Patch, Status::UpdatePatch)
+                | (Self::Digest, Status::UpdateAvailable)
+        )
+    }
+}
+
+/// Returns every configured notification target, along with a name used for logging
+fn targets(config: &NotificationConfig) -> Vec<(String, &dyn Notifier)> {
+    fn named<'a, N: Notifier>(
+        kind: &'a str,
+        targets: &'a FxHashMap<String, N>,
+    ) -> impl Iterator<Item = (String, &'a dyn Notifier)> {
+        targets
+            .iter()
+            .map(move |(name, target)| (format!("{} {}", kind, name), target as &dyn Notifier))
+    }
+    named("webhook", &config.webhooks)
+        .chain(named("ntfy", &config.ntfy))
+        .chain(named("Gotify", &config.gotify))
+        .chain(named("Discord", &config.discord))
+        .chain(named("Slack", &config.slack))
+        .chain(named("Matrix", &config.matrix))
+        .chain(named("email", &config.email))
+        .collect()
+}
+
+/// Held while notifications are sent, so concurrent checks don't send the same updates twice (and don't open the database at the same time)
+static NOTIFYING: Mutex<()> = Mutex::const_new(());
+
+/// Notifies each configured target about the updates from `current` it wasn't notified about yet. See `notified::Notified` for how they're remembered.
+pub async fn notify(current: &[Update], ctx: &Context) {
+    if ctx.config.notifications.is_empty() {
+        return;
+    }
+    let _notifying = NOTIFYING.lock().await;
+    let Some(notified) = Notified::open(ctx) else {
+        return;
+    };
+    let targets = targets(&ctx.config.notifications);
+    let names: Vec<&str> = targets.iter().map(|(name, _)| name.as_str()).collect();
+    let pending = notified.pending(&names, current, ctx);
+    let client = Client::new(ctx);
+    let handles = targets
+        .iter()
+        .zip(pending)
+        .map(|((name, target), updates)| {
+            let statuses = target.statuses();
+            let updates: Vec<&Update> = updates
+                .into_iter()
+                .filter(|update| {
+                    statuses.is_empty()
+                        || statuses
+                            .iter()
+                            .any(|status| status.matches(&update.get_status()))
+                })
+                .collect();
+            (name, *target, updates)
+        })
+        .filter(|(_, _, updates)| !updates.is_empty())
+        .map(|(name, target, updates)| {
+            let client = &client;
+            let notified = &notified;
+            async move {
+                ctx.logger.debug(format!(
+                    "Sending notification about {} new updates to {}",
+                    updates.len(),
+                    name
+                ));
+                match target.send(&updates, ctx, client).await {
+                    Ok(()) => {
+                        ctx.logger.debug(format!("Sent notification to {}", name));
+                        notified.remember(name, &updates, ctx);
+                    }
+                    // The updates aren't remembered, so they're sent again after the next check
+                    Err(e) => ctx
+                        .logger
+                        .warn(format!("Failed to send notification to {}: {}", name, e)),
+                }
+            }
+        });
+    join_all(handles).await;
+}
+
+/// A short summary of the updates, for targets that support titles
+pub fn title(updates: &[&Update]) -> String {
+    match updates.len() {
+        1 => format!("Update available for {}", updates[0].reference),
+        count => format!("{} new updates available", count),
+    }
+}
+
+/// Describes each update on its own line, e.g. `postgres:15: Major update (15 → 17)`
+pub fn message(updates: &[&Update]) -> String {
+    updates
+        .iter()
+        .map(|update| {
+            format!(
+                "{}{}: {}{}",
+                match &update.server {
+                    Some(server) => format!("[{}] ", server),
+                    None => String::new(),
+                },
+                update.reference,
+                update.get_status(),
+                match &update.result.info {
+                    UpdateInfo::Version(info) => {
+                        format!(" ({} → {})", info.current_version, info.new_version)
+                    }
+                    _ => String::new(),
+                }
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::update::fixtures::{create_digest_update, create_version_update};
+
+    use super::*;
+
+    /// Test that updates are described correctly and matched with the statuses targets want
+    #[test]
+    fn describe() {
+        let current = [
+            create_version_update("postgres:15", "17"),
+            create_digest_update("busybox", "sha256:2"),
+        ];
+        let updates: Vec<&Update> = current.iter().collect();
+        assert_eq!(title(&updates), "2 new updates available");
+        assert_eq!(title(&updates[..1]), "Update available for postgres:15");
+        assert_eq!(
+            message(&updates),
+            "postgres:15: Major update (15 → 17)\nbusybox: Update available"
+        );
+        assert!(NotificationStatus::Major.matches(&updates[0].get_status()));
+        assert!(!NotificationStatus::Major.matches(&updates[1].get_status()));
+    }
+}
@@ -0,0 +1,257 @@
+// Remembers which updates each notification target received, so each one is only sent once, even across restarts, without history or when an image can't be checked for a while.
+// Updates are only remembered once a target received them, so targets which fail are sent them again after the next check.
+
+use redb::{Database, ReadableTable, TableDefinition};
+use rustc_hash::FxHashSet;
+
+use crate::{data_store, structs::update::Update, utils::time::timestamp, Context};
+
+/// The Unix timestamp (in milliseconds) each target was notified about each update at. Keys are the target's name and the key generated by `Update::update_key`, separated by a null byte. The key of the name alone marks targets which were set up.
+const NOTIFIED: TableDefinition<&str, u64> = TableDefinition::new("notified");
+
+pub struct Notified {
+    db: Database,
+}
+
+impl Notified {
+    pub fn open(ctx: &Context) -> Option<Self> {
+        data_store::open("notifications.redb", "notifications will not be sent", ctx)
+            .map(|db| Self { db })
+    }
+
+    /// Returns the updates from `current` each of `targets` wasn't notified about yet, in the same order as the targets, and forgets updates which are no longer available.
+    /// Targets which weren't set up before only have the current updates remembered, since every update would look new to them.
+    pub fn pending<'a>(
+        &self,
+        targets: &[&str],
+        current: &'a [Update],
+        ctx: &Context,
+    ) -> Vec<Vec<&'a Update>> {
+        self.try_pending(targets, current, ctx).unwrap_or_else(|e| {
+            ctx.logger
+                .warn(format!("Failed to read notified updates: {}", e));
+            Vec::new()
+        })
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn try_pending<'a>(
+        &self,
+        targets: &[&str],
+        current: &'a [Update],
+        ctx: &Context,
+    ) -> Result<Vec<Vec<&'a Update>>, redb::Error> {
+        let now = timestamp();
+        let txn = self.db.begin_write()?;
+        let pending = {
+            let mut table = txn.open_table(NOTIFIED)?;
+            let mut notified = FxHashSet::default();
+            for entry in table.iter()? {
+                notified.insert(entry?.0.value().to_string());
+            }
+            let mut pending = Vec::with_capacity(targets.len());
+            let mut remembered = FxHashSet::default();
+            for target in targets {
+                let marker = format!("{}\0", target);
+                let target_notified: FxHashSet<String> = notified
+                    .iter()
+                    .filter_map(|key| key.strip_prefix(&marker))
+                    .filter(|key| !key.is_empty())
+                    .map(str::to_string)
+                    .collect();
+                let (new, target_remembered) = diff(&target_notified, current);
+                remembered.extend(
+                    target_remembered
+                        .into_iter()
+                        .map(|key| format!("{}{}", marker, key)),
+                );
+                remembered.insert(marker.clone());
+                if notified.contains(&marker) {
+                    pending.push(new);
+                    continue;
+                }
+                ctx.logger.info(format!(
+                    "Notifications will be sent to {} for updates which become available from the next check on",
+                    target
+                ));
+                table.insert(marker.as_str(), now)?;
+                for update in new {
+                    let key = format!("{}{}", marker, update.update_key().unwrap());
+                    table.insert(key.as_str(), now)?;
+                    remembered.insert(key);
+                }
+                pending.push(Vec::new());
+            }
+            table.retain(|key, _| remembered.contains(key))?;
+            pending
+        };
+        txn.commit()?;
+        Ok(pending)
+    }
+
+    /// Remembers that `target` received `updates`
+    pub fn remember(&self, target: &str, updates: &[&Update], ctx: &Context) {
+        if let Err(e) = self.try_remember(target, updates) {
+            ctx.logger.warn(format!(
+                "Failed to remember notifications sent to {}, they will be sent again: {}",
+                target, e
+            ));
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn try_remember(&self, target: &str, updates: &[&Update]) -> Result<(), redb::Error> {
+        let now = timestamp();
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(NOTIFIED)?;
+            for update in updates {
+                let key = format!("{}\0{}", target, update.update_key().unwrap());
+                table.insert(key.as_str(), now)?;
+            }
+        }
+        txn.commit()?;
+        Ok(())
+    }
+}
+
+/// Compares the current results with the updates that were notified about. Returns the updates which are new, along with the keys of all updates that should be remembered: the ones still available and those of images which couldn't be checked this time.
+/// Acknowledged updates are left out, and count as new once they're no longer acknowledged (e.g. when a snooze ends).
+fn diff<'a>(
+    notified: &FxHashSet<String>,
+    current: &'a [Update],
+) -> (Vec<&'a Update>, FxHashSet<String>) {
+    let unchecked: Vec<String> = current
+        .iter()
+        .filter(|update| update.is_unchecked())
+        .map(Update::image_key)
+        .collect();
+    let mut remembered: FxHashSet<String> = notified
+        .iter()
+        .filter(|key| unchecked.iter().any(|image| key.starts_with(image)))
+        .cloned()
+        .collect();
+    let mut new = Vec::new();
+    for update in current {
+        if update.get_status().to_option_bool() != Some(true) {
+            continue;
+        }
+        let Some(key) = update.update_key() else {
+            continue;
+        };
+        if notified.contains(&key) {
+            remembered.insert(key);
+        } else {
+            new.push(update);
+        }
+    }
+    (new, remembered)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        logging::Logger,
+        structs::{
+            acknowledgement::Acknowledgement,
+            update::{
+                fixtures::{create_digest_update, create_version_update},
+                UpdateResult,
+            },
+        },
+    };
+
+    use super::*;
+
+    /// Remembers the new updates returned by `diff` along with the other ones, like a target receiving them would
+    fn deliver(new: &[&Update], mut remembered: FxHashSet<String>) -> FxHashSet<String> {
+        remembered.extend(new.iter().filter_map(|update| update.update_key()));
+        remembered
+    }
+
+    /// Test that only updates which weren't notified about are new, and that updates of images which couldn't be checked aren't forgotten
+    #[test]
+    fn new() {
+        let previous = vec![
+            create_version_update("postgres:15", "16"),
+            create_digest_update("alpine", "sha256:1"),
+        ];
+        let (new, remembered) = diff(&FxHashSet::default(), &previous);
+        assert_eq!(new.len(), 2);
+        let notified = deliver(&new, remembered);
+
+        let current = vec![
+            create_version_update("postgres:15", "17"),
+            create_digest_update("alpine", "sha256:1"),
+            create_digest_update("busybox", "sha256:2"),
+        ];
+        let (new, remembered) = diff(&notified, &current);
+        assert_eq!(new, vec![&current[0], &current[2]]);
+        assert_eq!(remembered.len(), 1);
+        let remembered = deliver(&new, remembered);
+
+        // Acknowledged updates aren't notified about until they're no longer acknowledged
+        let mut snoozed = current.clone();
+        snoozed[2].set_acknowledgement(Some(Acknowledgement {
+            version: None,
+            until: Some(u64::MAX),
+        }));
+        let (new, notified) = diff(&notified, &snoozed);
+        assert_eq!(new, vec![&snoozed[0]]);
+        let notified = deliver(&new, notified);
+        assert_eq!(diff(&notified, &current).0, vec![&current[2]]);
+
+        // Going unknown for a while doesn't make an update new again
+        let mut unknown = current.clone();
+        unknown[1].result = UpdateResult {
+            has_update: None,
+            error: Some(String::from("Connection failed!")),
+            ..Default::default()
+        };
+        let (new, notified) = diff(&remembered, &unknown);
+        assert!(new.is_empty());
+        assert!(diff(&notified, &current).0.is_empty());
+    }
+
+    /// Test that targets which weren't set up before aren't flooded, and that updates are sent again to targets which failed to receive them
+    #[test]
+    fn retried() {
+        let directory =
+            std::env::temp_dir().join(format!("cup-notifications-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let mut config = Config::new();
+        config.data_directory = Some(directory.to_str().unwrap().to_string());
+        let ctx = Context {
+            config,
+            logger: Logger::new(false, true),
+        };
+        let notified = Notified::open(&ctx).unwrap();
+        let targets = ["ntfy Phone", "webhook Home Assistant"];
+
+        let previous = vec![create_digest_update("alpine", "sha256:1")];
+        let pending = notified.pending(&targets, &previous, &ctx);
+        assert!(pending.iter().all(Vec::is_empty));
+
+        let current = vec![
+            create_digest_update("alpine", "sha256:1"),
+            create_version_update("postgres:15", "17"),
+        ];
+        let pending = notified.pending(&targets, &current, &ctx);
+        assert_eq!(pending, vec![vec![&current[1]], vec![&current[1]]]);
+        // Only the first target received the notification
+        notified.remember(targets[0], &pending[0], &ctx);
+        assert_eq!(
+            notified.pending(&targets, &current, &ctx),
+            vec![vec![], vec![&current[1]]]
+        );
+
+        // A target added later only gets updates which become available afterwards
+        let targets = ["ntfy Phone", "webhook Home Assistant", "email Me"];
+        assert_eq!(
+            notified.pending(&targets, &current, &ctx),
+            vec![vec![], vec![&current[1]], vec![]]
+        );
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
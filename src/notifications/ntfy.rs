@@ -0,0 +1,45 @@
+use futures::future::BoxFuture;
+use serde_json::json;
+
+use crate::{
+    config::{NotificationStatus, NtfyConfig},
+    http::Client,
+    structs::update::Update,
+    Context,
+};
+
+use super::{message, title, Notifier};
+
+impl Notifier for NtfyConfig {
+    fn statuses(&self) -> &[NotificationStatus] {
+        &self.statuses
+    }
+
+    /// Publishes a message to the topic using ntfy's JSON API
+    fn send<'a>(
+        &'a self,
+        updates: &'a [&'a Update],
+        _ctx: &'a Context,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let body = json!({
+                "topic": self.topic,
+                "title": title(updates),
+                "message": message(updates),
+                "priority": self.priority.unwrap_or(3),
+                "tags": ["whale"],
+            });
+            let authorization = self.token.as_ref().map(|token| format!("Bearer {}", token));
+            let headers = [
+                ("Content-Type", Some("application/json")),
+                ("Authorization", authorization.as_deref()),
+            ];
+            client
+                .post(self.url.trim_end_matches('/'), &headers, body.to_string())
+                .await
+                .map(|_| ())
+                .map_err(String::from)
+        })
+    }
+}
@@ -0,0 +1,37 @@
+use futures::future::BoxFuture;
+use serde_json::json;
+
+use crate::{
+    config::{NotificationStatus, SlackConfig},
+    http::Client,
+    structs::update::Update,
+    Context,
+};
+
+use super::{message, title, Notifier};
+
+impl Notifier for SlackConfig {
+    fn statuses(&self) -> &[NotificationStatus] {
+        &self.statuses
+    }
+
+    /// Posts a message using an incoming webhook
+    fn send<'a>(
+        &'a self,
+        updates: &'a [&'a Update],
+        _ctx: &'a Context,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let body = json!({
+                "text": format!("*{}*\n{}", title(updates), message(updates)),
+            });
+            let headers = [("Content-Type", Some("application/json"))];
+            client
+                .post(&self.webhook_url, &headers, body.to_string())
+                .await
+                .map(|_| ())
+                .map_err(String::from)
+        })
+    }
+}
@@ -0,0 +1,124 @@
+use futures::future::BoxFuture;
+use liquid::object;
+use serde_json::{json, Value};
+
+use crate::{
+    config::{NotificationStatus, WebhookConfig},
+    http::Client,
+    structs::update::Update,
+    Context,
+};
+
+use super::Notifier;
+
+impl Notifier for WebhookConfig {
+    fn statuses(&self) -> &[NotificationStatus] {
+        &self.statuses
+    }
+
+    /// POSTs the updates to the webhook
+    fn send<'a>(
+        &'a self,
+        updates: &'a [&'a Update],
+        _ctx: &'a Context,
+        client: &'a Client,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let body =
+                render_body(self, updates).map_err(|e| format!("Failed to render body. {}", e))?;
+            let mut headers: Vec<(&str, Option<&str>)> = self
+                .headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), Some(value.as_str())))
+                .collect();
+            if !self
+                .headers
+                .keys()
+                .any(|name| name.eq_ignore_ascii_case("content-type"))
+            {
+                headers.push(("Content-Type", Some("application/json")));
+            }
+            client
+                .post(&self.url, &headers, body)
+                .await
+                .map(|_| ())
+                .map_err(String::from)
+        })
+    }
+}
+
+/// Renders the request body. Without a template, the updates are sent in the same format the API uses.
+fn render_body(config: &WebhookConfig, updates: &[&Update]) -> Result<String, String> {
+    let updates_json: Vec<Value> = updates
+        .iter()
+        .map(|update| {
+            let mut value = serde_json::to_value(update).unwrap();
+            value["status"] = update.get_status().to_string().into();
+            value
+        })
+        .collect();
+    match &config.body {
+        None => Ok(json!({ "updates": updates_json }).to_string()),
+        Some(template) => {
+            let template = liquid::ParserBuilder::with_stdlib()
+                .build()
+                .unwrap()
+                .parse(template)
+                .map_err(|e| e.to_string())?;
+            let globals = object!({
+                "updates": liquid::model::to_value(&updates_json).map_err(|e| e.to_string())?,
+                // Liquid can't serialize JSON, so we provide it pre-serialized for templates that need it
+                "updates_json": Value::from(updates_json).to_string(),
+                "count": updates.len(),
+            });
+            template.render(&globals).map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rustc_hash::FxHashMap;
+
+    use crate::{
+        config::Config,
+        logging::Logger,
+        structs::update::fixtures::create_version_update,
+        utils::test_server::{serve, Response},
+    };
+
+    use super::*;
+
+    /// Test that the rendered template is POSTed to the webhook along with the configured headers
+    #[tokio::test]
+    async fn webhook() {
+        let (url, mut requests) = serve(|_| Response::new(200, "OK")).await;
+        let config = WebhookConfig {
+            url: format!("{}/hook", url),
+            body: Some(String::from(
+                r#"{"text": "{{ count }} new: {% for update in updates %}{{ update.reference }} ({{ update.status }}) -> {{ update.result.info.new_tag }}{% endfor %}"}"#,
+            )),
+            headers: FxHashMap::from_iter([(String::from("X-Token"), String::from("secret"))]),
+            statuses: Vec::new(),
+        };
+        let ctx = Context {
+            config: Config::new(),
+            logger: Logger::new(false, true),
+        };
+        let update = create_version_update("postgres:15", "17");
+        config
+            .send(&[&update], &ctx, &Client::new(&ctx))
+            .await
+            .unwrap();
+
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/hook");
+        assert_eq!(request.headers["x-token"], "secret");
+        assert_eq!(request.headers["content-type"], "application/json");
+        assert_eq!(
+            request.body,
+            r#"{"text": "1 new: postgres:15 (Major update) -> 17"}"#
+        );
+    }
+}
@@ -1,51 +1,225 @@
-use std::time::SystemTime;
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
 
 use itertools::Itertools;
+use once_cell::sync::Lazy;
+use reqwest::Response;
+use rustc_hash::FxHashMap;
+use serde_json::Value;
 
 use crate::{
+    cache::{self, CachedResponse, ResponseCache},
     config::UpdateType,
-    error,
+    credentials::get_credentials,
+    error::Error,
     http::Client,
     structs::{
-        image::{DigestInfo, Image, VersionInfo},
+        details::{ImageDetails, LABELS},
+        image::{DigestInfo, Image, PlatformManifest, VersionInfo},
+        platform::Platform,
+        status::Status,
         version::Version,
     },
     utils::{
         link::parse_link,
-        request::{
-            get_protocol, get_response_body, parse_json, parse_www_authenticate, to_bearer_string,
-        },
-        time::{elapsed, now},
+        request::{get_protocol, get_response_body, parse_json, parse_www_authenticate, Challenge},
+        time::{elapsed, now, timestamp},
     },
     Context,
 };
 
-pub async fn check_auth(registry: &str, ctx: &Context, client: &Client) -> Option<String> {
+/// Tokens are only reused if they're valid for at least this long, so they don't expire in the middle of a check
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+/// How long a token is valid for if the token server doesn't say, as defined by the distribution spec
+const DEFAULT_TOKEN_LIFETIME: u64 = 60;
+
+/// The manifest media types we accept
+const MANIFEST_TYPES: &str = "application/vnd.docker.distribution.manifest.list.v2+json, application/vnd.docker.distribution.manifest.v2+json, application/vnd.oci.image.index.v1+json, application/vnd.oci.image.manifest.v1+json";
+/// The media types of manifest lists, which point to a manifest for each platform
+const MANIFEST_LIST_TYPES: &str =
+    "application/vnd.docker.distribution.manifest.list.v2+json, application/vnd.oci.image.index.v1+json";
+/// The media types of the manifests of a single platform's image
+const IMAGE_MANIFEST_TYPES: &str =
+    "application/vnd.docker.distribution.manifest.v2+json, application/vnd.oci.image.manifest.v1+json";
+
+/// The challenges registries responded with, indexed by registry. `None` means the registry doesn't need authentication.
+static CHALLENGES: Lazy<Mutex<FxHashMap<String, Option<Challenge>>>> =
+    Lazy::new(|| Mutex::new(FxHashMap::default()));
+
+/// A registry and the (sorted) repositories a token grants access to
+type TokenScope = (String, Vec<String>);
+
+/// Tokens from token servers with the time they expire at, indexed by their scope
+static TOKENS: Lazy<Mutex<FxHashMap<TokenScope, (String, SystemTime)>>> =
+    Lazy::new(|| Mutex::new(FxHashMap::default()));
+
+/// Finds out how to authenticate with a registry. Returns `None` if it doesn't need authentication (or couldn't be reached, in which case the requests for its images will report the error).
+pub async fn check_auth(
+    registry: &str,
+    ctx: &Context,
+    client: &Client,
+) -> Result<Option<Challenge>, Error> {
+    if let Some(challenge) = CHALLENGES.lock().unwrap().get(registry) {
+        return Ok(challenge.clone());
+    }
     let protocol = get_protocol(registry, &ctx.config.registries);
     let url = format!("{}://{}/v2/", protocol, registry);
     let response = client.get(&url, &[], true).await;
     match response {
         Ok(response) => {
             let status = response.status();
-            if status == 401 {
-                match response.headers().get("www-authenticate") {
-                        Some(challenge) => Some(parse_www_authenticate(challenge.to_str().unwrap())),
-                        None => error!(
-                            "Unauthorized to access registry {} and no way to authenticate was provided",
-                            registry
-                        ),
+            let challenge = if status == 401 {
+                match response
+                    .headers()
+                    .get("www-authenticate")
+                    .and_then(|challenge| challenge.to_str().ok())
+                {
+                    Some(challenge) => Some(parse_www_authenticate(challenge)?),
+                    None => {
+                        return Err(Error::MissingChallenge {
+                            registry: registry.to_string(),
+                        })
                     }
+                }
             } else {
                 None
+            };
+            CHALLENGES
+                .lock()
+                .unwrap()
+                .insert(registry.to_string(), challenge.clone());
+            Ok(challenge)
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// Returns the value of the Authorization header to send to a registry to pull the given repositories, if it needs one. Tokens are cached until they expire, so repeated checks don't need to ask the token server again.
+pub async fn authorize(
+    registry: &str,
+    repositories: &[&str],
+    ctx: &Context,
+    client: &Client,
+) -> Result<Option<String>, Error> {
+    let challenge = match check_auth(registry, ctx, client).await? {
+        Some(challenge) => challenge,
+        None => return Ok(None),
+    };
+    Ok(match challenge {
+        Challenge::Bearer(auth_url) => {
+            let mut repositories: Vec<String> =
+                repositories.iter().map(|repo| repo.to_string()).collect();
+            repositories.sort_unstable();
+            repositories.dedup();
+            let key = (registry.to_string(), repositories);
+            if let Some((token, expires_at)) = TOKENS.lock().unwrap().get(&key) {
+                if *expires_at > SystemTime::now() + TOKEN_EXPIRY_MARGIN {
+                    ctx.logger
+                        .debug(format!("Reusing cached token for {}", registry));
+                    return Ok(Some(format!("Bearer {}", token)));
+                }
             }
+            let credentials = get_credentials(registry, ctx, client).await;
+            let (token, expires_at) = get_token(&key.1, &auth_url, &credentials, client).await?;
+            let authorization = format!("Bearer {}", token);
+            TOKENS.lock().unwrap().insert(key, (token, expires_at));
+            Some(authorization)
+        }
+        Challenge::Basic => get_credentials(registry, ctx, client)
+            .await
+            .map(|credentials| format!("Basic {}", credentials)),
+    })
+}
+
+/// Forgets an authorization the registry rejected. Only the rejected token is dropped, so images using other tokens don't all have to authenticate again. If it wasn't a token, the registry's challenge may have changed, so it's asked again.
+fn forget_authorization(registry: &str, authorization: Option<&str>) {
+    match authorization.and_then(|authorization| authorization.strip_prefix("Bearer ")) {
+        Some(rejected) => TOKENS
+            .lock()
+            .unwrap()
+            .retain(|(token_registry, _), (token, _)| {
+                token_registry != registry || token != rejected
+            }),
+        None => {
+            CHALLENGES.lock().unwrap().remove(registry);
+        }
+    }
+}
+
+/// Sends a GET (or HEAD) request to a registry. If the registry rejects the authorization (e.g. because the token was revoked in the middle of a check), authenticates again and retries once, updating `authorization`.
+async fn send(
+    url: &str,
+    head: bool,
+    headers: &[(&str, Option<&str>)],
+    authorization: &mut Option<String>,
+    image: &Image,
+    ctx: &Context,
+    client: &Client,
+) -> Result<Response, Error> {
+    // Only manifest GETs count towards rate limits like Docker Hub's, HEADs and blobs are free
+    if !head && url.contains("/manifests/") && !client.spend(&image.parts.registry) {
+        return Err(Error::BudgetExhausted {
+            registry: image.parts.registry.clone(),
+        });
+    }
+    let request = |authorization: Option<String>, ignore_401: bool| async move {
+        let mut headers = headers.to_vec();
+        headers.push(("Authorization", authorization.as_deref()));
+        if head {
+            client.head(url, &headers, ignore_401).await
+        } else {
+            client.get(url, &headers, ignore_401).await
         }
-        Err(_) => None,
+    };
+    let response = request(authorization.clone(), true).await?;
+    if response.status() != 401 {
+        return Ok(response);
     }
+    ctx.logger.debug(format!(
+        "{} rejected the authorization for {}, authenticating again",
+        image.parts.registry, image.reference
+    ));
+    forget_authorization(&image.parts.registry, authorization.as_deref());
+    *authorization = authorize(
+        &image.parts.registry,
+        &[&image.parts.repository],
+        ctx,
+        client,
+    )
+    .await?;
+    request(authorization.clone(), false).await
+}
+
+/// Updates the registry's rate limit with a HEAD request for the image's manifest. These don't count towards Docker Hub's limit.
+pub async fn refresh_rate_limit(
+    image: &Image,
+    authorization: Option<&str>,
+    ctx: &Context,
+    client: &Client,
+) {
+    let protocol = get_protocol(&image.parts.registry, &ctx.config.registries);
+    let url = format!(
+        "{}://{}/v2/{}/manifests/{}",
+        protocol, &image.parts.registry, &image.parts.repository, &image.parts.tag
+    );
+    let headers = [("Accept", Some(MANIFEST_TYPES))];
+    let _ = send(
+        &url,
+        true,
+        &headers,
+        &mut authorization.map(str::to_string),
+        image,
+        ctx,
+        client,
+    )
+    .await;
 }
 
 pub async fn get_latest_digest(
     image: &Image,
-    token: Option<&str>,
+    authorization: Option<&str>,
     ctx: &Context,
     client: &Client,
 ) -> Image {
@@ -57,35 +231,123 @@ pub async fn get_latest_digest(
         "{}://{}/v2/{}/manifests/{}",
         protocol, &image.parts.registry, &image.parts.repository, &image.parts.tag
     );
-    let authorization = to_bearer_string(&token);
-    let headers = [("Accept", Some("application/vnd.docker.distribution.manifest.list.v2+json, application/vnd.docker.distribution.manifest.v2+json, application/vnd.oci.image.index.v1+json, application/vnd.oci.image.manifest.v1+json")), ("Authorization", authorization.as_deref())];
+    let headers = [("Accept", Some(MANIFEST_TYPES))];
+    let mut authorization = authorization.map(str::to_string);
 
-    let response = client.head(&url, &headers).await;
-    let time = start.elapsed().unwrap().as_millis() as u32;
-    ctx.logger.debug(format!(
-        "Checked for digest update to {} in {}ms",
-        image.reference, time
-    ));
-    match response {
+    let response = send(&url, true, &headers, &mut authorization, image, ctx, client).await;
+    let result = match response {
         Ok(res) => match res.headers().get("docker-content-digest") {
             Some(digest) => {
-                let local_digests = match &image.digest_info {
-                    Some(data) => data.local_digests.clone(),
+                let digest = digest.to_str().unwrap().to_string();
+                let mut digest_info = match &image.digest_info {
+                    Some(data) => data.clone(),
                     None => return image.clone(),
                 };
-                Image {
-                    digest_info: Some(DigestInfo {
-                        remote_digest: Some(digest.to_str().unwrap().to_string()),
-                        local_digests,
-                    }),
-                    time_ms: image.time_ms + time,
-                    ..image.clone()
+                let is_list = res
+                    .headers()
+                    .get("content-type")
+                    .and_then(|content_type| content_type.to_str().ok())
+                    .and_then(|content_type| content_type.split(';').next())
+                    .is_some_and(|content_type| {
+                        MANIFEST_LIST_TYPES
+                            .split(", ")
+                            .any(|list_type| list_type == content_type.trim())
+                    });
+                // Only worth the extra requests if the digest changed
+                let changed = !digest_info.local_digests.contains(&digest);
+                let mut manifest = None;
+                if is_list && changed {
+                    match get_platform_update(
+                        &url,
+                        &mut digest_info,
+                        &mut authorization,
+                        image,
+                        ctx,
+                        client,
+                    )
+                    .await
+                    {
+                        Ok(platform_manifest) => manifest = platform_manifest,
+                        // Without knowing whether our platform changed, the update may not be one
+                        Err(Error::BudgetExhausted { .. }) => {
+                            return Image {
+                                deferred: true,
+                                ..image.clone()
+                            }
+                        }
+                        Err(e) => ctx.logger.debug(format!(
+                            "Failed to check which platforms of {} changed: {}",
+                            image.reference, e
+                        )),
+                    }
+                }
+                // The manifest describing the remote image. For lists, that's the local platform's one.
+                let manifest_digest = match is_list {
+                    true => {
+                        let platform = digest_info.platform.clone().unwrap_or_else(Platform::host);
+                        digest_info
+                            .platforms
+                            .iter()
+                            .find(|manifest| platform.matches(&manifest.platform))
+                            .map(|manifest| manifest.digest.clone())
+                    }
+                    false => Some(digest.clone()),
+                };
+                if let (true, Some(manifest_digest)) = (
+                    ctx.config.details && changed && digest_info.platform_updated != Some(false),
+                    manifest_digest,
+                ) {
+                    // Manifests never change, so the details of one only have to be fetched once
+                    let cache = ResponseCache::get(ctx);
+                    match cache.and_then(|cache| cache.load_details(&manifest_digest)) {
+                        Some(details) => digest_info.remote_details = Some(details),
+                        None => {
+                            let manifest_url =
+                                format!("{}/{}", url.rsplit_once('/').unwrap().0, manifest_digest);
+                            match get_image_details(
+                                &manifest_url,
+                                manifest,
+                                &mut authorization,
+                                image,
+                                ctx,
+                                client,
+                            )
+                            .await
+                            {
+                                Ok(details) => {
+                                    if let Some(cache) = cache {
+                                        cache.store_details(&manifest_digest, &details);
+                                    }
+                                    digest_info.remote_details = Some(details)
+                                }
+                                Err(e) => ctx.logger.debug(format!(
+                                    "Failed to fetch details of the update to {}: {}",
+                                    image.reference, e
+                                )),
+                            }
+                        }
+                    }
                 }
+                digest_info.remote_digest = Some(digest);
+                Ok(digest_info)
             }
-            None => error!(
-                "Server returned invalid response! No docker-content-digest!\n{:#?}",
-                res
-            ),
+            None => Err(Error::invalid_response(format!(
+                "No docker-content-digest in response to HEAD {}",
+                url
+            ))),
+        },
+        Err(error) => Err(error),
+    };
+    let time = start.elapsed().unwrap().as_millis() as u32;
+    ctx.logger.debug(format!(
+        "Checked for digest update to {} in {}ms",
+        image.reference, time
+    ));
+    match result {
+        Ok(digest_info) => Image {
+            digest_info: Some(digest_info),
+            time_ms: image.time_ms + time,
+            ..image.clone()
         },
         Err(error) => Image {
             error: Some(error),
@@ -95,31 +357,177 @@ pub async fn get_latest_digest(
     }
 }
 
+/// Fetches the manifest list at `url` to find out whether the image for the local image's platform changed. Manifest lists change when the image for any of their platforms does, so comparing their digest alone would report updates which don't affect us.
+/// Returns the platform's manifest, if it was fetched.
+async fn get_platform_update(
+    url: &str,
+    digest_info: &mut DigestInfo,
+    authorization: &mut Option<String>,
+    image: &Image,
+    ctx: &Context,
+    client: &Client,
+) -> Result<Option<Value>, Error> {
+    let headers = [("Accept", Some(MANIFEST_LIST_TYPES))];
+    let response = send(url, false, &headers, authorization, image, ctx, client).await?;
+    let list = parse_json(&get_response_body(response).await?)?;
+    digest_info.platforms = list["manifests"]
+        .as_array()
+        .ok_or_else(|| Error::invalid_response(format!("No manifests in response to GET {}", url)))?
+        .iter()
+        .filter_map(|manifest| {
+            let platform = &manifest["platform"];
+            Some(PlatformManifest {
+                platform: Platform::new(
+                    platform["os"].as_str()?,
+                    platform["architecture"].as_str()?,
+                    platform["variant"].as_str(),
+                ),
+                digest: manifest["digest"].as_str()?.to_string(),
+            })
+        })
+        // Attestations are listed with an unknown platform
+        .filter(|manifest| manifest.platform.os != "unknown")
+        .collect();
+
+    let platform = digest_info.platform.clone().unwrap_or_else(Platform::host);
+    let (Some(manifest), Some(image_id)) = (
+        digest_info
+            .platforms
+            .iter()
+            .find(|manifest| platform.matches(&manifest.platform)),
+        &digest_info.image_id,
+    ) else {
+        return Ok(None);
+    };
+    // The image ID is the digest of the image's config, which the platform's manifest points to
+    let manifest_url = format!("{}/{}", url.rsplit_once('/').unwrap().0, manifest.digest);
+    let headers = [("Accept", Some(IMAGE_MANIFEST_TYPES))];
+    let response = send(
+        &manifest_url,
+        false,
+        &headers,
+        authorization,
+        image,
+        ctx,
+        client,
+    )
+    .await?;
+    let manifest = parse_json(&get_response_body(response).await?)?;
+    let config = manifest["config"]["digest"].as_str().ok_or_else(|| {
+        Error::invalid_response(format!("No config in response to GET {}", manifest_url))
+    })?;
+    ctx.logger.debug(format!(
+        "Image for {} of {} has config {}, local image is {}",
+        platform, image.reference, config, image_id
+    ));
+    digest_info.platform_updated = Some(config != image_id);
+    Ok(Some(manifest))
+}
+
+/// Describes the image whose manifest is at `manifest_url` using the manifest (unless it's passed in) and the config it points to
+async fn get_image_details(
+    manifest_url: &str,
+    manifest: Option<Value>,
+    authorization: &mut Option<String>,
+    image: &Image,
+    ctx: &Context,
+    client: &Client,
+) -> Result<ImageDetails, Error> {
+    let manifest = match manifest {
+        Some(manifest) => manifest,
+        None => {
+            let headers = [("Accept", Some(IMAGE_MANIFEST_TYPES))];
+            let response = send(
+                manifest_url,
+                false,
+                &headers,
+                authorization,
+                image,
+                ctx,
+                client,
+            )
+            .await?;
+            parse_json(&get_response_body(response).await?)?
+        }
+    };
+    let config_digest = manifest["config"]["digest"].as_str().ok_or_else(|| {
+        Error::invalid_response(format!("No config in response to GET {}", manifest_url))
+    })?;
+    let config_url = format!(
+        "{}/blobs/{}",
+        manifest_url.rsplit_once("/manifests/").unwrap().0,
+        config_digest
+    );
+    let response = send(&config_url, false, &[], authorization, image, ctx, client).await?;
+    let config = parse_json(&get_response_body(response).await?)?;
+    let strings = |value: &Value| -> Vec<String> {
+        value
+            .as_array()
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(|value| value.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    Ok(ImageDetails {
+        created: config["created"].as_str().map(str::to_string),
+        layers: strings(&config["rootfs"]["diff_ids"]),
+        layer_sizes: manifest["layers"]
+            .as_array()
+            .map(|layers| {
+                layers
+                    .iter()
+                    .map(|layer| layer["size"].as_u64().unwrap_or_default())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        labels: config["config"]["Labels"]
+            .as_object()
+            .map(|labels| {
+                labels
+                    .iter()
+                    .filter(|(name, _)| LABELS.contains(&name.as_str()))
+                    .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+/// Gets a token which grants pull access to the given repositories from a token server. Returns it along with the time it expires at.
 pub async fn get_token(
-    images: &[&Image],
+    repositories: &[String],
     auth_url: &str,
     credentials: &Option<String>,
     client: &Client,
-) -> String {
+) -> Result<(String, SystemTime), Error> {
     let mut url = auth_url.to_owned();
-    for image in images {
-        url = format!("{}&scope=repository:{}:pull", url, image.parts.repository);
+    for repository in repositories {
+        url = format!("{}&scope=repository:{}:pull", url, repository);
     }
     let authorization = credentials.as_ref().map(|creds| format!("Basic {}", creds));
     let headers = [("Authorization", authorization.as_deref())];
 
-    let response = client.get(&url, &headers, false).await;
-    let response_json = match response {
-        Ok(response) => parse_json(&get_response_body(response).await),
-        Err(_) => error!("GET {}: Request failed!", url),
-    };
-    response_json["token"].as_str().unwrap().to_string()
+    let response = client.get(&url, &headers, false).await?;
+    let response_json = parse_json(&get_response_body(response).await?)?;
+    // Some token servers only return an OAuth 2.0 compatible `access_token`
+    let token = response_json["token"]
+        .as_str()
+        .or(response_json["access_token"].as_str())
+        .ok_or_else(|| Error::invalid_response(format!("No token in response to GET {}", url)))?
+        .to_string();
+    let expires_in = response_json["expires_in"]
+        .as_u64()
+        .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+    Ok((token, SystemTime::now() + Duration::from_secs(expires_in)))
 }
 
 pub async fn get_latest_tag(
     image: &Image,
     base: &Version,
-    token: Option<&str>,
+    authorization: Option<&str>,
     ctx: &Context,
     client: &Client,
     excluded_tags: Vec<String>,
@@ -132,11 +540,7 @@ pub async fn get_latest_tag(
         "{}://{}/v2/{}/tags/list",
         protocol, &image.parts.registry, &image.parts.repository,
     );
-    let authorization = to_bearer_string(&token);
-    let headers = [
-        ("Accept", Some("application/json")),
-        ("Authorization", authorization.as_deref()),
-    ];
+    let mut authorization = authorization.map(str::to_string);
 
     let mut tags: Vec<Version> = Vec::new();
     let mut next_url = Some(url);
@@ -149,7 +553,8 @@ pub async fn get_latest_tag(
         ));
         let (new_tags, next) = match get_extra_tags(
             &next_url.unwrap(),
-            &headers,
+            &mut authorization,
+            image,
             base,
             &image.version_info.as_ref().unwrap().format_str,
             ctx,
@@ -159,9 +564,9 @@ pub async fn get_latest_tag(
         .await
         {
             Ok(t) => t,
-            Err(message) => {
+            Err(error) => {
                 return Image {
-                    error: Some(message),
+                    error: Some(error),
                     time_ms: image.time_ms + elapsed(start),
                     ..image.clone()
                 }
@@ -190,7 +595,7 @@ pub async fn get_latest_tag(
                         time_ms: image.time_ms + elapsed(start),
                         ..image.clone()
                     },
-                    token,
+                    authorization.as_deref(),
                     ctx,
                     client,
                 )
@@ -206,10 +611,14 @@ pub async fn get_latest_tag(
                 }
             }
         }
-        None => error!(
-            "Image {} has no remote version tags! Local tag: {}",
-            image.reference, image.parts.tag
-        ),
+        None => Image {
+            error: Some(Error::NoVersionTags {
+                reference: image.reference.clone(),
+                tag: image.parts.tag.clone(),
+            }),
+            time_ms: image.time_ms + elapsed(start),
+            ..image.clone()
+        },
     }
 }
 
@@ -227,53 +636,315 @@ fn is_excluded_tag(tag: &str, excluded_tags: &[String], ctx: &Context) -> bool {
     false
 }
 
+/// Fetches a page of tags. If the response cache is enabled, cached pages younger than the registry's TTL are used as is, while older ones are revalidated with a conditional request.
+async fn get_tags_page(
+    url: &str,
+    authorization: &mut Option<String>,
+    image: &Image,
+    ctx: &Context,
+    client: &Client,
+) -> Result<CachedResponse, Error> {
+    let cache = ResponseCache::get(ctx);
+    let key = cache::key(url, authorization.as_deref());
+    let cached = cache.and_then(|cache| cache.load(&key));
+    let ttl = ctx
+        .config
+        .registries
+        .get(&image.parts.registry)
+        .map_or(0, |config| config.cache_ttl);
+    let mut headers = vec![("Accept", Some("application/json"))];
+    if let Some(cached) = &cached {
+        if cached.is_fresh(ttl) {
+            ctx.logger
+                .debug(format!("Using cached response for {}", url));
+            return Ok(cached.clone());
+        }
+        headers.push(("If-None-Match", cached.etag.as_deref()));
+        headers.push(("If-Modified-Since", cached.last_modified.as_deref()));
+    }
+
+    let response = send(url, false, &headers, authorization, image, ctx, client).await?;
+    let page = match cached {
+        Some(cached) if response.status() == 304 => {
+            ctx.logger
+                .debug(format!("Cached response for {} is still valid", url));
+            CachedResponse {
+                stored_at: timestamp(),
+                ..cached
+            }
+        }
+        _ => {
+            let header = |name: &str| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+            };
+            let (link, etag, last_modified) =
+                (header("Link"), header("ETag"), header("Last-Modified"));
+            CachedResponse {
+                body: get_response_body(response).await?,
+                link,
+                etag,
+                last_modified,
+                stored_at: timestamp(),
+            }
+        }
+    };
+    if let Some(cache) = cache {
+        // Without a validator, a response can only be reused within the TTL
+        if ttl != 0 || page.etag.is_some() || page.last_modified.is_some() {
+            cache.store(&key, &page);
+        }
+    }
+    Ok(page)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn get_extra_tags(
     url: &str,
-    headers: &[(&str, Option<&str>)],
+    authorization: &mut Option<String>,
+    image: &Image,
     base: &Version,
     format_str: &str,
     ctx: &Context,
     client: &Client,
     excluded_tags: &[String],
-) -> Result<(Vec<Version>, Option<String>), String> {
-    let response = client.get(url, headers, false).await;
+) -> Result<(Vec<Version>, Option<String>), Error> {
+    let page = get_tags_page(url, authorization, image, ctx, client).await?;
+    let next_url = page.link.map(|link| parse_link(&link, url)).transpose()?;
+    let response_json = parse_json(&page.body)?;
+    let channel = ctx.config.images.channel(&image.reference);
+    let result = response_json["tags"]
+        .as_array()
+        .ok_or_else(|| Error::invalid_response(format!("No tags in response to GET {}", url)))?
+        .iter()
+        .filter_map(|tag| tag.as_str())
+        .filter(|tag| !is_excluded_tag(tag, excluded_tags, ctx))
+        .filter_map(|tag| ctx.config.images.parse_tag(&image.reference, tag))
+        .filter(|(tag, format_string)| match (base.minor, tag.minor) {
+            (Some(_), Some(_)) | (None, None) => {
+                matches!((base.patch, tag.patch), (Some(_), Some(_)) | (None, None))
+                    && format_str == *format_string
+            }
+            _ => false,
+        })
+        // The current version is kept even if it's a pre-release outside the channel, so it can always be compared to
+        .filter(|(tag, _)| channel.allows(tag) || tag == base)
+        .filter(|(tag, _)| tag == base || ctx.config.images.allows(&image.reference, tag, base))
+        .filter(|(tag, _)| match ctx.config.ignore_update_type {
+            UpdateType::None => true,
+            UpdateType::Major => tag.to_status(base) != Status::UpdateMajor,
+            UpdateType::Minor => !matches!(
+                tag.to_status(base),
+                Status::UpdateMajor | Status::UpdateMinor
+            ),
+            UpdateType::Patch => !matches!(
+                tag.to_status(base),
+                Status::UpdateMajor | Status::UpdateMinor | Status::UpdatePatch
+            ),
+        })
+        .map(|(tag, _)| tag)
+        .dedup()
+        .collect();
+    Ok((result, next_url))
+}
 
-    match response {
-        Ok(res) => {
-            let next_url = res
-                .headers()
-                .get("Link")
-                .map(|link| parse_link(link.to_str().unwrap(), url));
-            let response_json = parse_json(&get_response_body(res).await);
-            let result = response_json["tags"]
-                .as_array()
-                .unwrap()
-                .iter()
-                .filter(|tag| !is_excluded_tag(tag.as_str().unwrap(), excluded_tags, ctx))
-                .filter_map(|tag| Version::from_tag(tag.as_str().unwrap()))
-                .filter(|(tag, format_string)| match (base.minor, tag.minor) {
-                    (Some(_), Some(_)) | (None, None) => {
-                        matches!((base.patch, tag.patch), (Some(_), Some(_)) | (None, None))
-                            && format_str == *format_string
-                    }
-                    _ => false,
-                })
-                .filter_map(|(tag, _)| match ctx.config.ignore_update_type {
-                    UpdateType::None => Some(tag),
-                    UpdateType::Major => Some(tag).filter(|tag| base.major == tag.major),
-                    UpdateType::Minor => {
-                        Some(tag).filter(|tag| base.major == tag.major && base.minor == tag.minor)
-                    }
-                    UpdateType::Patch => Some(tag).filter(|tag| {
-                        base.major == tag.major
-                            && base.minor == tag.minor
-                            && base.patch == tag.patch
-                    }),
-                })
-                .dedup()
-                .collect();
-            Ok((result, next_url))
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use once_cell::sync::OnceCell;
+
+    use crate::{
+        config::{Config, RegistryConfig},
+        logging::Logger,
+        utils::test_server::{serve, Response},
+    };
+
+    use super::*;
+
+    /// Test that tokens are reused across checks and that a registry rejecting a token mid-check leads to a new one, without dropping the tokens for other repositories
+    #[tokio::test]
+    async fn token_reuse() {
+        let realm: Arc<OnceCell<String>> = Arc::new(OnceCell::new());
+        let server_realm = realm.clone();
+        let tokens = AtomicUsize::new(0);
+        let (url, mut requests) = serve(move |request| match request.path.as_str() {
+            "/v2/" => Response::new(401, "").header(
+                "WWW-Authenticate",
+                &format!(
+                    "Bearer realm=\"{}\",service=\"test\"",
+                    server_realm.get().unwrap()
+                ),
+            ),
+            path if path.starts_with("/token") => Response::new(
+                200,
+                format!(
+                    r#"{{"token":"token-{}","expires_in":300}}"#,
+                    tokens.fetch_add(1, Ordering::SeqCst)
+                ),
+            ),
+            // The first token has been revoked
+            _ if request.headers["authorization"] == "Bearer token-0" => Response::new(401, ""),
+            _ => Response::new(200, "").header("Docker-Content-Digest", "sha256:1234"),
+        })
+        .await;
+        realm.set(format!("{}/token", url)).unwrap();
+        let registry = url.trim_start_matches("http://").to_string();
+        let mut config = Config::new();
+        config.registries.insert(
+            registry.clone(),
+            RegistryConfig {
+                insecure: true,
+                ..Default::default()
+            },
+        );
+        let ctx = Context {
+            config,
+            logger: Logger::new(false, false),
+        };
+        let client = Client::new(&ctx);
+
+        let mut authorization = None;
+        for _ in 0..2 {
+            authorization = authorize(&registry, &["library/alpine"], &ctx, &client)
+                .await
+                .unwrap();
+            assert_eq!(authorization.as_deref(), Some("Bearer token-0"));
+        }
+        let other = || authorize(&registry, &["library/busybox"], &ctx, &client);
+        assert_eq!(other().await.unwrap().as_deref(), Some("Bearer token-1"));
+
+        let image = Image::from_reference(&format!("{}/library/alpine:3.20", registry));
+        let url = format!("{}/v2/library/alpine/manifests/3.20", url);
+        let response = send(&url, true, &[], &mut authorization, &image, &ctx, &client)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(authorization.as_deref(), Some("Bearer token-2"));
+        assert_eq!(other().await.unwrap().as_deref(), Some("Bearer token-1"));
+
+        let paths: Vec<String> = std::iter::from_fn(|| requests.try_recv().ok())
+            .map(|request| request.path)
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "/v2/",
+                "/token?&service=test&scope=repository:library/alpine:pull",
+                "/token?&service=test&scope=repository:library/busybox:pull",
+                "/v2/library/alpine/manifests/3.20",
+                "/token?&service=test&scope=repository:library/alpine:pull",
+                "/v2/library/alpine/manifests/3.20"
+            ]
+        );
+    }
+
+    /// Test that a registry which can't be authenticated with and an image which can't be checked lead to errors instead of exiting
+    #[tokio::test]
+    async fn check_errors() {
+        let (url, _requests) = serve(|_| Response::new(401, "")).await;
+        let registry = url.trim_start_matches("http://").to_string();
+        let mut config = Config::new();
+        config.registries.insert(
+            registry.clone(),
+            RegistryConfig {
+                insecure: true,
+                ..Default::default()
+            },
+        );
+        let ctx = Context {
+            config,
+            logger: Logger::new(false, false),
+        };
+        let client = Client::new(&ctx);
+
+        assert_eq!(
+            authorize(&registry, &["library/alpine"], &ctx, &client).await,
+            Err(Error::MissingChallenge {
+                registry: registry.clone()
+            })
+        );
+
+        let image = Image::from_reference(&format!("{}/library/alpine:latest", registry));
+        let update = image
+            .check(None, &ctx, &client, Vec::new())
+            .await
+            .to_update();
+        assert_eq!(update.result.has_update, None);
+        assert_eq!(update.result.error_code.as_deref(), Some("unsupported_tag"));
+    }
+
+    /// Test that tag lists are cached, revalidated with conditional requests and used as is within the TTL
+    #[tokio::test]
+    async fn tag_cache() {
+        let (url, mut requests) = serve(|request| {
+            if request.headers.get("if-none-match").map(String::as_str) == Some("\"v1\"") {
+                Response::new(304, "")
+            } else {
+                Response::new(
+                    200,
+                    r#"{"name":"library/node","tags":["1.0","2.0","latest"]}"#,
+                )
+                .header("ETag", "\"v1\"")
+            }
+        })
+        .await;
+        let registry = url.trim_start_matches("http://").to_string();
+        let directory = std::env::temp_dir().join(format!("cup-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let mut config = Config::new();
+        config.data_directory = Some(directory.to_str().unwrap().to_string());
+        config.registries.insert(
+            registry.clone(),
+            RegistryConfig {
+                insecure: true,
+                ..Default::default()
+            },
+        );
+        let ctx = Context {
+            config,
+            logger: Logger::new(false, false),
+        };
+        let mut ttl_ctx = ctx.clone();
+        ttl_ctx
+            .config
+            .registries
+            .get_mut(&registry)
+            .unwrap()
+            .cache_ttl = 60;
+        let client = Client::new(&ctx);
+        let image = Image::from_reference(&format!("{}/library/node:1.0", registry));
+        let (base, format_str) = Version::from_tag("1.0").unwrap();
+        let url = format!("{}/v2/library/node/tags/list", url);
+
+        for ctx in [&ctx, &ctx, &ttl_ctx] {
+            let (tags, next) = get_extra_tags(
+                &url,
+                &mut None,
+                &image,
+                &base,
+                &format_str,
+                ctx,
+                &client,
+                &[],
+            )
+            .await
+            .unwrap();
+            assert_eq!(tags.len(), 2);
+            assert_eq!(next, None);
         }
-        Err(message) => Err(message),
+
+        let first = requests.recv().await.unwrap();
+        assert_eq!(first.headers.get("if-none-match"), None);
+        let second = requests.recv().await.unwrap();
+        assert_eq!(second.headers["if-none-match"], "\"v1\"");
+        // The last check was answered from the cache
+        assert!(requests.try_recv().is_err());
     }
 }
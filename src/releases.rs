@@ -0,0 +1,441 @@
+// Release notes for version updates, from the forge (GitHub, GitLab or Gitea) hosting the image's source
+
+use reqwest::Url;
+
+use crate::{
+    cache::{self, CachedResponse, ResponseCache},
+    config::ForgeType,
+    error::Error,
+    http::Client,
+    structs::{release::Release, status::Status, version::Version},
+    utils::{
+        request::{get_response_body, parse_json},
+        time::timestamp,
+    },
+    Context,
+};
+
+/// How long (in seconds) cached release lists are used without asking the forge whether they changed. GitHub only allows 60 requests per hour without a token.
+const CACHE_TTL: u64 = 60 * 60;
+
+/// A repository on a forge, resolved from an image's `org.opencontainers.image.source` label
+#[derive(Debug, PartialEq)]
+pub struct Repository {
+    pub forge_type: ForgeType,
+    /// The scheme and domain of the forge, e.g. `https://github.com`
+    pub base_url: String,
+    /// The path of the repository, e.g. `sergi0g/cup`. On GitLab, this may include subgroups.
+    pub path: String,
+}
+
+impl Repository {
+    /// Resolves the repository a source URL points to. Returns `None` if it isn't on a known forge.
+    pub fn from_source(source: &str, ctx: &Context) -> Option<Self> {
+        let url = Url::parse(source).ok()?;
+        let domain = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str()?, port),
+            None => url.host_str()?.to_string(),
+        };
+        let release_notes = &ctx.config.release_notes;
+        // Labels may link to a forge over plain HTTP, but its API is only used that way if it's configured with an `http://` URL
+        let base_url = [
+            format!("{}://{}", url.scheme(), domain),
+            format!("https://{}", domain),
+        ]
+        .into_iter()
+        .find(|base_url| release_notes.forge(base_url).is_some())
+        .unwrap_or_else(|| format!("https://{}", domain));
+        let forge_type = match release_notes.forge(&base_url) {
+            Some(forge) => forge.forge_type,
+            None => match domain.as_str() {
+                "github.com" => ForgeType::Github,
+                "gitlab.com" => ForgeType::Gitlab,
+                "codeberg.org" => ForgeType::Gitea,
+                _ => return None,
+            },
+        };
+        let segments: Vec<&str> = url
+            .path_segments()?
+            .filter(|segment| !segment.is_empty())
+            .collect();
+        let segments = match forge_type {
+            // Subgroups can be nested, everything after `-` is a page of the project
+            ForgeType::Gitlab => segments
+                .iter()
+                .position(|segment| *segment == "-")
+                .map_or(&segments[..], |end| &segments[..end]),
+            // Anything after the owner and repository is a page of it (e.g. `/tree/main`)
+            _ => segments.get(..2)?,
+        };
+        if segments.len() < 2 {
+            return None;
+        }
+        Some(Self {
+            forge_type,
+            base_url,
+            path: segments.join("/").trim_end_matches(".git").to_string(),
+        })
+    }
+
+    /// The page listing the repository's releases
+    pub fn releases_url(&self) -> String {
+        match self.forge_type {
+            ForgeType::Gitlab => format!("{}/{}/-/releases", self.base_url, self.path),
+            _ => format!("{}/{}/releases", self.base_url, self.path),
+        }
+    }
+
+    /// The API endpoint listing the repository's latest releases
+    fn api_url(&self) -> String {
+        match self.forge_type {
+            ForgeType::Github if self.base_url == "https://github.com" => format!(
+                "https://api.github.com/repos/{}/releases?per_page=100",
+                self.path
+            ),
+            // GitHub Enterprise
+            ForgeType::Github => format!(
+                "{}/api/v3/repos/{}/releases?per_page=100",
+                self.base_url, self.path
+            ),
+            ForgeType::Gitlab => format!(
+                "{}/api/v4/projects/{}/releases?per_page=100",
+                self.base_url,
+                self.path.replace('/', "%2F")
+            ),
+            ForgeType::Gitea => format!(
+                "{}/api/v1/repos/{}/releases?limit=50",
+                self.base_url, self.path
+            ),
+        }
+    }
+}
+
+/// Returns the releases of a repository newer than `current` and up to `new`, newest first. Only the latest page of releases is read, which covers all but the most outdated images.
+pub async fn get_releases(
+    repository: &Repository,
+    current: &Version,
+    new: &Version,
+    ctx: &Context,
+    client: &Client,
+) -> Result<Vec<Release>, Error> {
+    let url = repository.api_url();
+    let authorization = ctx
+        .config
+        .release_notes
+        .forge(&repository.base_url)
+        .and_then(|forge| forge.token.as_ref())
+        .map(|token| format!("Bearer {}", token));
+    let body = get_releases_page(&url, authorization.as_deref(), ctx, client).await?;
+    let releases = parse_json(&body)?;
+    let mut releases: Vec<(Version, Release)> = releases
+        .as_array()
+        .ok_or_else(|| Error::invalid_response(format!("No releases in response to GET {}", url)))?
+        .iter()
+        .filter(|release| !release["draft"].as_bool().unwrap_or(false))
+        .filter_map(|release| {
+            let tag = release["tag_name"].as_str()?;
+            let (version, _) = Version::from_tag(tag)?;
+            if version <= *current || version > *new {
+                return None;
+            }
+            let (url, notes) = match repository.forge_type {
+                ForgeType::Gitlab => (
+                    release["_links"]["self"].as_str(),
+                    release["description"].as_str(),
+                ),
+                _ => (release["html_url"].as_str(), release["body"].as_str()),
+            };
+            let title = release["name"]
+                .as_str()
+                .filter(|name| !name.is_empty())
+                .unwrap_or(tag);
+            let mentions_breaking = |text: &str| text.to_lowercase().contains("breaking");
+            let breaking = (version.to_status(current) == Status::UpdateMajor
+                && version.minor.unwrap_or(0) == 0
+                && version.patch.unwrap_or(0) == 0)
+                || mentions_breaking(title)
+                || notes.is_some_and(mentions_breaking);
+            let release = Release {
+                tag: tag.to_string(),
+                title: title.to_string(),
+                url: url.map_or_else(
+                    || format!("{}/tag/{}", repository.releases_url(), tag),
+                    str::to_string,
+                ),
+                breaking,
+            };
+            Some((version, release))
+        })
+        .collect();
+    releases.sort_by(|a, b| b.0.cmp(&a.0));
+    Ok(releases.into_iter().map(|(_, release)| release).collect())
+}
+
+/// Fetches a list of releases. Like tag lists, it's cached and revalidated with a conditional request once it's older than `CACHE_TTL`, which doesn't count towards GitHub's rate limit.
+async fn get_releases_page(
+    url: &str,
+    authorization: Option<&str>,
+    ctx: &Context,
+    client: &Client,
+) -> Result<String, Error> {
+    let cache = ResponseCache::get(ctx);
+    let key = cache::key(url, authorization);
+    let cached = cache.and_then(|cache| cache.load(&key));
+    // GitHub rejects requests without a user agent
+    let mut headers = vec![
+        ("Accept", Some("application/json")),
+        ("User-Agent", Some("cup")),
+        ("Authorization", authorization),
+    ];
+    if let Some(cached) = &cached {
+        if cached.is_fresh(CACHE_TTL) {
+            ctx.logger
+                .debug(format!("Using cached response for {}", url));
+            return Ok(cached.body.clone());
+        }
+        headers.push(("If-None-Match", cached.etag.as_deref()));
+        headers.push(("If-Modified-Since", cached.last_modified.as_deref()));
+    }
+    let response = client.get(url, &headers, false).await?;
+    let page = match cached {
+        Some(cached) if response.status() == 304 => CachedResponse {
+            stored_at: timestamp(),
+            ..cached
+        },
+        _ => {
+            let header = |name: &str| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string)
+            };
+            let (etag, last_modified) = (header("ETag"), header("Last-Modified"));
+            CachedResponse {
+                body: get_response_body(response).await?,
+                link: None,
+                etag,
+                last_modified,
+                stored_at: timestamp(),
+            }
+        }
+    };
+    if let Some(cache) = cache {
+        cache.store(&key, &page);
+    }
+    Ok(page.body)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        config::{Config, ForgeConfig},
+        logging::Logger,
+        utils::test_server::{serve, Response},
+    };
+
+    use super::*;
+
+    fn context(forges: &[(&str, ForgeType, Option<&str>)]) -> Context {
+        let mut config = Config::new();
+        config.cache.enabled = false;
+        config.release_notes.enabled = true;
+        for (domain, forge_type, token) in forges {
+            config.release_notes.forges.insert(
+                domain.to_string(),
+                ForgeConfig {
+                    forge_type: *forge_type,
+                    token: token.map(str::to_string),
+                },
+            );
+        }
+        Context::new(config, Logger::new(false, false))
+    }
+
+    fn version(tag: &str) -> Version {
+        Version::from_tag(tag).unwrap().0
+    }
+
+    /// Test that source URLs are resolved to repositories on known or configured forges
+    #[test]
+    fn repository() {
+        let ctx = context(&[("git.example.com", ForgeType::Gitea, None)]);
+        let resolve = |source: &str| {
+            Repository::from_source(source, &ctx)
+                .map(|repository| (repository.forge_type, repository.api_url()))
+        };
+        assert_eq!(
+            resolve("https://github.com/sergi0g/cup"),
+            Some((
+                ForgeType::Github,
+                String::from("https://api.github.com/repos/sergi0g/cup/releases?per_page=100")
+            ))
+        );
+        assert_eq!(
+            resolve("https://github.com/sergi0g/cup.git/tree/main"),
+            resolve("https://github.com/sergi0g/cup")
+        );
+        assert_eq!(
+            resolve("https://gitlab.com/group/subgroup/project/-/tree/main"),
+            Some((
+                ForgeType::Gitlab,
+                String::from(
+                    "https://gitlab.com/api/v4/projects/group%2Fsubgroup%2Fproject/releases?per_page=100"
+                )
+            ))
+        );
+        assert_eq!(
+            resolve("https://git.example.com/owner/repo"),
+            Some((
+                ForgeType::Gitea,
+                String::from("https://git.example.com/api/v1/repos/owner/repo/releases?limit=50")
+            ))
+        );
+        // The API is only used over plain HTTP for forges configured with an `http://` URL
+        assert_eq!(
+            resolve("http://github.com/sergi0g/cup"),
+            resolve("https://github.com/sergi0g/cup")
+        );
+        assert_eq!(
+            resolve("http://git.example.com/owner/repo"),
+            resolve("https://git.example.com/owner/repo")
+        );
+        let ctx = context(&[("http://git.local", ForgeType::Gitea, None)]);
+        assert_eq!(
+            Repository::from_source("http://git.local/owner/repo", &ctx)
+                .map(|repository| repository.base_url),
+            Some(String::from("http://git.local"))
+        );
+        assert_eq!(
+            Repository::from_source("https://git.local/owner/repo", &ctx),
+            None
+        );
+        assert_eq!(resolve("https://example.com/owner/repo"), None);
+        assert_eq!(resolve("https://github.com/sergi0g"), None);
+        assert_eq!(resolve("not a url"), None);
+    }
+
+    /// Test that only the releases between the current and the new version are returned, newest first, with breaking ones flagged
+    #[tokio::test]
+    async fn releases() {
+        let (url, mut requests) = serve(|request| {
+            let release = |tag: &str, body: &str| {
+                json!({
+                    "tag_name": tag,
+                    "name": format!("Release {}", tag),
+                    "html_url": format!("https://github.com/owner/repo/releases/tag/{}", tag),
+                    "body": body,
+                    "draft": false
+                })
+            };
+            let releases = json!([
+                release("v3.1.0", "Fixes"),
+                release("v3.0.0", "A new major version"),
+                release(
+                    "v2.5.0",
+                    "Removes the old API. BREAKING CHANGE: clients need updating"
+                ),
+                release("v2.4.0", "Features"),
+                release("v2.3.0", "The current version"),
+                release("nightly", "Not a version")
+            ]);
+            match request.path.as_str() {
+                "/api/v3/repos/owner/repo/releases?per_page=100" => {
+                    Response::new(200, releases.to_string())
+                }
+                _ => Response::new(404, ""),
+            }
+        })
+        .await;
+        let ctx = context(&[(&url, ForgeType::Github, Some("secret"))]);
+        let client = Client::new(&ctx);
+        let repository = Repository::from_source(&format!("{}/owner/repo", url), &ctx).unwrap();
+
+        let releases = get_releases(
+            &repository,
+            &version("v2.3.0"),
+            &version("v3.0.0"),
+            &ctx,
+            &client,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            releases
+                .iter()
+                .map(|release| (release.tag.as_str(), release.breaking))
+                .collect::<Vec<_>>(),
+            [("v3.0.0", true), ("v2.5.0", true), ("v2.4.0", false)]
+        );
+        assert_eq!(releases[0].title, "Release v3.0.0");
+        assert_eq!(
+            releases[0].url,
+            "https://github.com/owner/repo/releases/tag/v3.0.0"
+        );
+        let request = requests.recv().await.unwrap();
+        assert_eq!(
+            request.headers.get("authorization").map(String::as_str),
+            Some("Bearer secret")
+        );
+        assert_eq!(
+            request.headers.get("user-agent").map(String::as_str),
+            Some("cup")
+        );
+    }
+
+    /// Test reading GitLab's and Gitea's releases, which are shaped a little differently
+    #[tokio::test]
+    async fn other_forges() {
+        let (url, _requests) = serve(|request| {
+            let body = match request.path.as_str() {
+                "/api/v4/projects/group%2Fproject/releases?per_page=100" => json!([{
+                    "tag_name": "1.1.0",
+                    "name": "",
+                    "description": "Changes",
+                    "_links": { "self": "https://gitlab.example.com/group/project/-/releases/1.1.0" }
+                }]),
+                "/api/v1/repos/owner/repo/releases?limit=50" => json!([
+                    { "tag_name": "1.1.0", "name": "Draft", "html_url": "", "body": "", "draft": true }
+                ]),
+                _ => return Response::new(404, ""),
+            };
+            Response::new(200, body.to_string())
+        })
+        .await;
+        let client = |ctx: &Context| Client::new(ctx);
+
+        let ctx = context(&[(&url, ForgeType::Gitlab, None)]);
+        let repository = Repository::from_source(&format!("{}/group/project", url), &ctx).unwrap();
+        let releases = get_releases(
+            &repository,
+            &version("1.0.0"),
+            &version("1.1.0"),
+            &ctx,
+            &client(&ctx),
+        )
+        .await
+        .unwrap();
+        // Releases without a name are titled after their tag
+        assert_eq!(releases[0].title, "1.1.0");
+        assert_eq!(
+            releases[0].url,
+            "https://gitlab.example.com/group/project/-/releases/1.1.0"
+        );
+
+        // Drafts aren't released yet
+        let ctx = context(&[(&url, ForgeType::Gitea, None)]);
+        let repository = Repository::from_source(&format!("{}/owner/repo", url), &ctx).unwrap();
+        let releases = get_releases(
+            &repository,
+            &version("1.0.0"),
+            &version("1.1.0"),
+            &ctx,
+            &client(&ctx),
+        )
+        .await
+        .unwrap();
+        assert!(releases.is_empty());
+    }
+}
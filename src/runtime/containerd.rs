@@ -0,0 +1,392 @@
+// containerd's gRPC API. Images and containers are namespaced (e.g. `k8s.io` for Kubernetes' CRI plugin, `default` for nerdctl), so every namespace is queried.
+// Only the fields we need are defined below, the rest are skipped when decoding.
+
+use std::collections::HashMap;
+
+use futures::{future::BoxFuture, FutureExt};
+use hyper_util::rt::TokioIo;
+use prost::Message;
+use rustc_hash::FxHashMap;
+use tokio::net::UnixStream;
+use tonic::{
+    client::Grpc,
+    codec::ProstCodec,
+    codegen::http::uri::PathAndQuery,
+    transport::{Channel, Endpoint, Uri},
+};
+use tower::service_fn;
+
+use crate::{
+    error::Error,
+    structs::{compose::ComposeService, image::Image},
+    utils::reference::split,
+    Context,
+};
+
+use super::{in_use_images, unix_path, Runtime};
+
+#[derive(Clone, PartialEq, Message)]
+struct ListNamespacesRequest {
+    #[prost(string, tag = "1")]
+    filter: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ListNamespacesResponse {
+    #[prost(message, repeated, tag = "1")]
+    namespaces: Vec<Namespace>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Namespace {
+    #[prost(string, tag = "1")]
+    name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ListRequest {
+    #[prost(string, repeated, tag = "1")]
+    filters: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ListImagesResponse {
+    #[prost(message, repeated, tag = "1")]
+    images: Vec<ContainerdImage>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ContainerdImage {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(map = "string, string", tag = "2")]
+    labels: HashMap<String, String>,
+    #[prost(message, optional, tag = "3")]
+    target: Option<Descriptor>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Descriptor {
+    #[prost(string, tag = "1")]
+    media_type: String,
+    #[prost(string, tag = "2")]
+    digest: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct ListContainersResponse {
+    #[prost(message, repeated, tag = "1")]
+    containers: Vec<Container>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Container {
+    #[prost(string, tag = "1")]
+    id: String,
+    #[prost(map = "string, string", tag = "2")]
+    labels: HashMap<String, String>,
+    #[prost(string, tag = "3")]
+    image: String,
+}
+
+pub struct ContainerdRuntime {
+    channel: Channel,
+}
+
+impl ContainerdRuntime {
+    pub fn new(socket: &str) -> Self {
+        let path = unix_path(socket).unwrap_or(socket).to_string();
+        // The URI is required, but isn't used since we always connect to the socket
+        let channel = Endpoint::from_static("http://localhost").connect_with_connector_lazy(
+            service_fn(move |_: Uri| {
+                let path = path.clone();
+                async move { UnixStream::connect(path).await.map(TokioIo::new) }
+            }),
+        );
+        Self { channel }
+    }
+
+    /// Calls a method of containerd's API in the namespace passed in
+    async fn call<Req, Res>(
+        &self,
+        method: &'static str,
+        namespace: Option<&str>,
+        request: Req,
+    ) -> Result<Res, String>
+    where
+        Req: Message + Send + Sync + 'static,
+        Res: Message + Default + Send + Sync + 'static,
+    {
+        let mut client = Grpc::new(self.channel.clone());
+        client.ready().await.map_err(|e| e.to_string())?;
+        let mut request = tonic::Request::new(request);
+        if let Some(namespace) = namespace {
+            request.metadata_mut().insert(
+                "containerd-namespace",
+                namespace.parse().map_err(|_| "Invalid namespace")?,
+            );
+        }
+        client
+            .unary(
+                request,
+                PathAndQuery::from_static(method),
+                ProstCodec::default(),
+            )
+            .await
+            .map(|response| response.into_inner())
+            .map_err(|e| e.message().to_string())
+    }
+
+    async fn namespaces(&self) -> Result<Vec<String>, Error> {
+        match self
+            .call::<_, ListNamespacesResponse>(
+                "/containerd.services.namespaces.v1.Namespaces/List",
+                None,
+                ListNamespacesRequest::default(),
+            )
+            .await
+        {
+            Ok(response) => Ok(response
+                .namespaces
+                .into_iter()
+                .map(|namespace| namespace.name)
+                .collect()),
+            Err(e) => Err(Error::runtime(format!(
+                "Failed to retrieve list of containerd namespaces!\n{}",
+                e
+            ))),
+        }
+    }
+}
+
+impl Runtime for ContainerdRuntime {
+    fn images<'a>(
+        &'a self,
+        ctx: &'a Context,
+        references: &'a Option<Vec<String>>,
+    ) -> BoxFuture<'a, Result<Vec<Image>, Error>> {
+        async move {
+            let mut images = Vec::new();
+            for namespace in self.namespaces().await? {
+                match self
+                    .call::<_, ListImagesResponse>(
+                        "/containerd.services.images.v1.Images/List",
+                        Some(&namespace),
+                        ListRequest::default(),
+                    )
+                    .await
+                {
+                    Ok(response) => images.extend(response.images),
+                    Err(e) => {
+                        return Err(Error::runtime(format!(
+                            "Failed to retrieve list of images available in namespace {}!\n{}",
+                            namespace, e
+                        )))
+                    }
+                }
+            }
+            Ok(local_images(ctx, &images, references))
+        }
+        .boxed()
+    }
+
+    fn in_use_images<'a>(
+        &'a self,
+        _ctx: &'a Context,
+    ) -> BoxFuture<'a, Result<FxHashMap<String, Vec<ComposeService>>, Error>> {
+        async move {
+            let mut containers = Vec::new();
+            for namespace in self.namespaces().await? {
+                match self
+                    .call::<_, ListContainersResponse>(
+                        "/containerd.services.containers.v1.Containers/List",
+                        Some(&namespace),
+                        ListRequest::default(),
+                    )
+                    .await
+                {
+                    Ok(response) => containers.extend(response.containers),
+                    Err(e) => {
+                        return Err(Error::runtime(format!(
+                            "Failed to retrieve list of containers available in namespace {}!\n{}",
+                            namespace, e
+                        )))
+                    }
+                }
+            }
+            Ok(in_use_images(containers.iter().map(|container| {
+                (container.image.as_str(), Some(&container.labels))
+            })))
+        }
+        .boxed()
+    }
+}
+
+/// Converts the images of every namespace to the ones we can check. If `references` is Some, return only the images whose references match the ones specified.
+fn local_images(
+    ctx: &Context,
+    images: &[ContainerdImage],
+    references: &Option<Vec<String>>,
+) -> Vec<Image> {
+    images
+        .iter()
+        // Images are also stored by digest, which we can't check
+        .filter(|image| !image.name.contains('@') && !image.name.starts_with("sha256:"))
+        .filter(|image| match references {
+            Some(refs) => refs
+                .iter()
+                .any(|reference| split(reference) == split(&image.name)),
+            None => true,
+        })
+        .filter_map(|image| {
+            let digest = &image.target.as_ref()?.digest;
+            Image::from_inspect_data(ctx, &format!("{}@{}", image.name, digest)).map(
+                |local_image| Image {
+                    url: image.labels.get("org.opencontainers.image.url").cloned(),
+                    ..local_image
+                },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::Config, logging::Logger};
+
+    use super::*;
+
+    // The parts of containerd's messages we skip, to make sure responses with them still decode
+
+    #[derive(Clone, PartialEq, Message)]
+    struct FullImage {
+        #[prost(string, tag = "1")]
+        name: String,
+        #[prost(map = "string, string", tag = "2")]
+        labels: HashMap<String, String>,
+        #[prost(message, optional, tag = "3")]
+        target: Option<FullDescriptor>,
+        #[prost(message, optional, tag = "7")]
+        created_at: Option<Timestamp>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct FullDescriptor {
+        #[prost(string, tag = "1")]
+        media_type: String,
+        #[prost(string, tag = "2")]
+        digest: String,
+        #[prost(int64, tag = "3")]
+        size: i64,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct Timestamp {
+        #[prost(int64, tag = "1")]
+        seconds: i64,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct FullContainer {
+        #[prost(string, tag = "1")]
+        id: String,
+        #[prost(map = "string, string", tag = "2")]
+        labels: HashMap<String, String>,
+        #[prost(string, tag = "3")]
+        image: String,
+        #[prost(string, tag = "6")]
+        snapshotter: String,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct FullListImagesResponse {
+        #[prost(message, repeated, tag = "1")]
+        images: Vec<FullImage>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    struct FullListContainersResponse {
+        #[prost(message, repeated, tag = "1")]
+        containers: Vec<FullContainer>,
+    }
+
+    fn image(name: &str, digest: &str) -> FullImage {
+        FullImage {
+            name: name.to_string(),
+            labels: HashMap::from([(
+                String::from("org.opencontainers.image.url"),
+                String::from("https://example.com"),
+            )]),
+            target: Some(FullDescriptor {
+                media_type: String::from("application/vnd.oci.image.index.v1+json"),
+                digest: digest.to_string(),
+                size: 1609,
+            }),
+            created_at: Some(Timestamp {
+                seconds: 1706316648,
+            }),
+        }
+    }
+
+    /// Test that images are decoded from containerd's responses, skipping the ones stored by digest
+    #[test]
+    fn images() {
+        let digest = "sha256:c5b1261d6d3e43071626931fc004f70149baeba2c8ec672bd4f27761f8e1ad6b";
+        let response = FullListImagesResponse {
+            images: vec![
+                image("docker.io/library/alpine:3.19", digest),
+                image(&format!("docker.io/library/alpine@{}", digest), digest),
+                image(&format!("sha256:{}", "0".repeat(64)), digest),
+            ],
+        }
+        .encode_to_vec();
+        let decoded = ListImagesResponse::decode(response.as_slice()).unwrap();
+        assert_eq!(decoded.images.len(), 3);
+        assert_eq!(decoded.images[0].target.as_ref().unwrap().digest, digest);
+
+        let ctx = Context {
+            config: Config::new(),
+            logger: Logger::new(false, false),
+        };
+        let images = local_images(&ctx, &decoded.images, &None);
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].reference, "docker.io/library/alpine:3.19");
+        assert_eq!(images[0].url.as_deref(), Some("https://example.com"));
+        assert_eq!(
+            images[0].digest_info.as_ref().unwrap().local_digests,
+            vec![digest.to_string()]
+        );
+        assert!(local_images(&ctx, &decoded.images, &Some(vec![String::from("nginx")])).is_empty());
+    }
+
+    /// Test that containers are decoded from containerd's responses along with their Compose labels
+    #[test]
+    fn containers() {
+        let response = FullListContainersResponse {
+            containers: vec![FullContainer {
+                id: String::from("4f0c7bb0"),
+                labels: HashMap::from([
+                    (
+                        String::from("com.docker.compose.project"),
+                        String::from("tools"),
+                    ),
+                    (
+                        String::from("com.docker.compose.service"),
+                        String::from("shell"),
+                    ),
+                ]),
+                image: String::from("docker.io/library/alpine:3.19"),
+                snapshotter: String::from("overlayfs"),
+            }],
+        }
+        .encode_to_vec();
+        let decoded = ListContainersResponse::decode(response.as_slice()).unwrap();
+        let in_use = in_use_images(
+            decoded
+                .containers
+                .iter()
+                .map(|container| (container.image.as_str(), Some(&container.labels))),
+        );
+        assert_eq!(in_use["docker.io/library/alpine:3.19"][0].service, "shell");
+    }
+}
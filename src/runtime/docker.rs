@@ -0,0 +1,285 @@
+use std::path::Path;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bollard::{
+    auth::DockerCredentials, container::ListContainersOptions, image::CreateImageOptions,
+    models::ImageInspect, ClientVersion, Docker,
+};
+
+use futures::{
+    future::{join_all, BoxFuture},
+    FutureExt, StreamExt,
+};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    config::HostConfig,
+    credentials::get_credentials,
+    error::Error,
+    http::Client,
+    structs::{compose::ComposeService, details::ImageDetails, image::Image},
+    utils::reference::split,
+    Context,
+};
+
+use super::{in_use_images, normalize_image, ssh::SshTunnel, unix_path, Runtime};
+
+const CLIENT_VERSION: ClientVersion = ClientVersion {
+    major_version: 1,
+    minor_version: 44,
+};
+
+/// Docker, or anything else that implements its API (e.g. Podman over TCP)
+pub struct DockerRuntime {
+    client: Docker,
+    /// Kept around so the connection stays open for as long as the client is used
+    _tunnel: Option<SshTunnel>,
+}
+
+impl DockerRuntime {
+    pub fn new(socket: Option<&str>) -> Result<Self, Error> {
+        Ok(Self {
+            client: create_docker_client(socket)?,
+            _tunnel: None,
+        })
+    }
+
+    /// Connects to one of the configured Docker hosts. Unlike `new`, this makes sure the host can be reached, so hosts which can't are skipped right away.
+    pub async fn for_host(name: &str, host: &HostConfig) -> Result<Self, String> {
+        let (client, tunnel) = if host.socket.starts_with("ssh://") {
+            let tunnel = SshTunnel::open(name, &host.socket).await?;
+            let client =
+                Docker::connect_with_unix(&tunnel.socket.to_string_lossy(), 120, &CLIENT_VERSION);
+            (client, Some(tunnel))
+        } else if let Some(path) = unix_path(&host.socket) {
+            (Docker::connect_with_unix(path, 120, &CLIENT_VERSION), None)
+        } else {
+            let client = match &host.tls {
+                Some(tls) => Docker::connect_with_ssl(
+                    &host.socket,
+                    Path::new(&tls.key),
+                    Path::new(&tls.cert),
+                    Path::new(&tls.ca),
+                    120,
+                    &CLIENT_VERSION,
+                ),
+                None => Docker::connect_with_http(&host.socket, 120, &CLIENT_VERSION),
+            };
+            (client, None)
+        };
+        let client = client.map_err(|e| e.to_string())?;
+        client.ping().await.map_err(|e| e.to_string())?;
+        Ok(Self {
+            client,
+            _tunnel: tunnel,
+        })
+    }
+
+    pub fn client(&self) -> &Docker {
+        &self.client
+    }
+}
+
+impl From<Docker> for DockerRuntime {
+    fn from(client: Docker) -> Self {
+        Self {
+            client,
+            _tunnel: None,
+        }
+    }
+}
+
+impl Runtime for DockerRuntime {
+    fn images<'a>(
+        &'a self,
+        ctx: &'a Context,
+        references: &'a Option<Vec<String>>,
+    ) -> BoxFuture<'a, Result<Vec<Image>, Error>> {
+        get_images_from_docker_daemon(&self.client, ctx, references).boxed()
+    }
+
+    fn in_use_images<'a>(
+        &'a self,
+        _ctx: &'a Context,
+    ) -> BoxFuture<'a, Result<FxHashMap<String, Vec<ComposeService>>, Error>> {
+        get_in_use_images(&self.client).boxed()
+    }
+
+    fn details<'a>(&'a self, reference: &'a str) -> BoxFuture<'a, Option<ImageDetails>> {
+        async move {
+            let inspect = self.client.inspect_image(reference).await.ok()?;
+            Some(ImageDetails {
+                created: inspect.created.as_ref().map(ToString::to_string),
+                layers: inspect
+                    .root_fs
+                    .and_then(|root_fs| root_fs.layers)
+                    .unwrap_or_default(),
+                layer_sizes: Vec::new(),
+                labels: inspect
+                    .config
+                    .and_then(|config| config.labels)
+                    .map(|labels| ImageDetails::filter_labels(&labels))
+                    .unwrap_or_default(),
+            })
+        }
+        .boxed()
+    }
+}
+
+pub fn create_docker_client(socket: Option<&str>) -> Result<Docker, Error> {
+    let client: Result<Docker, bollard::errors::Error> = match socket {
+        Some(sock) => {
+            if sock.starts_with("unix://") || sock.starts_with('/') {
+                Docker::connect_with_unix(sock, 120, &CLIENT_VERSION)
+            } else {
+                Docker::connect_with_http(sock, 120, &CLIENT_VERSION)
+            }
+        }
+        None => Docker::connect_with_local_defaults(), // Respects DOCKER_HOST
+    };
+
+    client.map_err(|e| Error::runtime(format!("Failed to connect to docker daemon!\n{}", e)))
+}
+
+/// Retrieves images from Docker daemon. If `references` is Some, return only the images whose references match the ones specified.
+pub async fn get_images_from_docker_daemon(
+    client: &Docker,
+    ctx: &Context,
+    references: &Option<Vec<String>>,
+) -> Result<Vec<Image>, Error> {
+    let mut swarm_images = match client.list_services::<String>(None).await {
+        Ok(services) => services
+            .iter()
+            .filter_map(|service| match &service.spec {
+                Some(service_spec) => match &service_spec.task_template {
+                    Some(task_spec) => match &task_spec.container_spec {
+                        Some(container_spec) => match &container_spec.image {
+                            Some(image) => Image::from_inspect_data(ctx, image),
+                            None => None,
+                        },
+                        None => None,
+                    },
+                    None => None,
+                },
+                None => None,
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    let mut local_images = match references {
+        Some(refs) => {
+            let mut inspect_handles = Vec::with_capacity(refs.len());
+            for reference in refs {
+                inspect_handles.push(client.inspect_image(reference));
+            }
+            let inspects: Vec<ImageInspect> = join_all(inspect_handles)
+                .await
+                .iter()
+                .filter(|inspect| inspect.is_ok())
+                .map(|inspect| inspect.as_ref().unwrap().clone())
+                .collect();
+            inspects
+                .iter()
+                .filter_map(|inspect| Image::from_inspect_data(ctx, inspect.clone()))
+                .collect()
+        }
+        None => {
+            let images = client.list_images::<String>(None).await.map_err(|e| {
+                Error::runtime(format!(
+                    "Failed to retrieve list of images available!\n{}",
+                    e
+                ))
+            })?;
+            images
+                .iter()
+                .filter_map(|image| Image::from_inspect_data(ctx, image.clone()))
+                .collect::<Vec<Image>>()
+        }
+    };
+    local_images.append(&mut swarm_images);
+    Ok(local_images)
+}
+
+/// Returns the images used by containers, along with the Compose services using them (if any).
+pub async fn get_in_use_images(
+    client: &Docker,
+) -> Result<FxHashMap<String, Vec<ComposeService>>, Error> {
+    let containers = client
+        .list_containers::<String>(Some(ListContainersOptions {
+            all: true,
+            ..Default::default()
+        }))
+        .await
+        .map_err(|e| {
+            Error::runtime(format!(
+                "Failed to retrieve list of containers available!\n{}",
+                e
+            ))
+        })?;
+
+    Ok(in_use_images(containers.iter().filter_map(|container| {
+        container
+            .image
+            .as_deref()
+            .map(|image| (image, container.labels.as_ref()))
+    })))
+}
+
+/// Returns the IDs of all containers created from the image passed in
+pub async fn get_containers_using(client: &Docker, reference: &str) -> Result<Vec<String>, String> {
+    let containers = client
+        .list_containers::<String>(Some(ListContainersOptions {
+            all: true,
+            ..Default::default()
+        }))
+        .await
+        .map_err(|e| format!("Failed to retrieve list of containers available! {}", e))?;
+    Ok(containers
+        .iter()
+        .filter(|container| {
+            container.image.as_deref().map(normalize_image).as_deref() == Some(reference)
+        })
+        .filter_map(|container| container.id.clone())
+        .collect())
+}
+
+/// Pulls an image, authenticating with the credentials configured for its registry if there are any
+pub async fn pull_image(client: &Docker, reference: &str, ctx: &Context) -> Result<(), String> {
+    let registry = split(reference).0;
+    let credentials = get_credentials(&registry, ctx, &Client::new(ctx))
+        .await
+        .and_then(|auth| STANDARD.decode(auth).ok())
+        .and_then(|auth| String::from_utf8(auth).ok())
+        .and_then(|auth| {
+            auth.split_once(':')
+                .map(|(username, password)| DockerCredentials {
+                    username: Some(username.to_string()),
+                    password: Some(password.to_string()),
+                    serveraddress: Some(registry.clone()),
+                    ..Default::default()
+                })
+        });
+    let mut stream = client.create_image(
+        Some(CreateImageOptions {
+            from_image: reference.to_string(),
+            ..Default::default()
+        }),
+        None,
+        credentials,
+    );
+    while let Some(info) = stream.next().await {
+        match info {
+            Ok(info) => {
+                if let Some(error) = info.error {
+                    return Err(error);
+                }
+                if let Some(status) = info.status {
+                    ctx.logger
+                        .debug(format!("Pulling {}: {}", reference, status));
+                }
+            }
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,707 @@
+// Images used by Kubernetes workloads, read from the cluster's API server. Unlike the other runtimes this doesn't replace the local runtime, it adds to it.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{Certificate, Identity};
+use rustc_hash::FxHashMap;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    structs::{image::Image, version::Version, workload::Workload},
+    utils::reference::split,
+    Context,
+};
+
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+const K3S_KUBECONFIG: &str = "/etc/rancher/k3s/k3s.yaml";
+/// How many items are requested at a time when listing objects
+const PAGE_SIZE: u16 = 500;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Kubeconfig {
+    #[serde(default)]
+    clusters: Vec<NamedCluster>,
+    #[serde(default)]
+    contexts: Vec<NamedContext>,
+    #[serde(default)]
+    users: Vec<NamedUser>,
+    current_context: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NamedCluster {
+    name: String,
+    cluster: KubeconfigCluster,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct KubeconfigCluster {
+    server: String,
+    certificate_authority: Option<String>,
+    certificate_authority_data: Option<String>,
+    #[serde(default)]
+    insecure_skip_tls_verify: bool,
+}
+
+#[derive(Deserialize)]
+struct NamedContext {
+    name: String,
+    context: KubeconfigContext,
+}
+
+#[derive(Deserialize)]
+struct KubeconfigContext {
+    cluster: String,
+    user: String,
+}
+
+#[derive(Deserialize)]
+struct NamedUser {
+    name: String,
+    user: KubeconfigUser,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+struct KubeconfigUser {
+    token: Option<String>,
+    #[serde(rename = "tokenFile")]
+    token_file: Option<String>,
+    client_certificate: Option<String>,
+    client_certificate_data: Option<String>,
+    client_key: Option<String>,
+    client_key_data: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct List<T> {
+    items: Vec<T>,
+    #[serde(default)]
+    metadata: ListMetadata,
+}
+
+#[derive(Deserialize, Default)]
+struct ListMetadata {
+    /// Set when there are more items to fetch
+    #[serde(rename = "continue")]
+    continue_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Metadata {
+    name: String,
+    #[serde(default)]
+    namespace: String,
+    #[serde(default)]
+    owner_references: Vec<OwnerReference>,
+}
+
+#[derive(Deserialize)]
+struct OwnerReference {
+    kind: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PodSpec {
+    #[serde(default)]
+    containers: Vec<Container>,
+    #[serde(default)]
+    init_containers: Vec<Container>,
+}
+
+impl PodSpec {
+    fn containers(&self) -> impl Iterator<Item = &Container> {
+        self.init_containers.iter().chain(self.containers.iter())
+    }
+}
+
+#[derive(Deserialize)]
+struct Container {
+    name: String,
+    image: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Pod {
+    metadata: Metadata,
+    spec: PodSpec,
+    #[serde(default)]
+    status: PodStatus,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct PodStatus {
+    #[serde(default)]
+    container_statuses: Vec<ContainerStatus>,
+    #[serde(default)]
+    init_container_statuses: Vec<ContainerStatus>,
+}
+
+#[derive(Deserialize)]
+struct ContainerStatus {
+    name: String,
+    #[serde(rename = "imageID", default)]
+    image_id: String,
+}
+
+/// A Deployment, StatefulSet, DaemonSet or CronJob
+#[derive(Deserialize)]
+struct Controller {
+    metadata: Metadata,
+    spec: ControllerSpec,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ControllerSpec {
+    template: Option<PodTemplate>,
+    job_template: Option<JobTemplate>,
+}
+
+impl ControllerSpec {
+    fn pod_spec(&self) -> Option<&PodSpec> {
+        match (&self.template, &self.job_template) {
+            (Some(template), _) => Some(&template.spec),
+            (None, Some(job_template)) => Some(&job_template.spec.template.spec),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PodTemplate {
+    spec: PodSpec,
+}
+
+#[derive(Deserialize)]
+struct JobTemplate {
+    spec: JobSpec,
+}
+
+#[derive(Deserialize)]
+struct JobSpec {
+    template: PodTemplate,
+}
+
+/// Controllers whose pods we can trace back to them, with the path of their API
+const CONTROLLERS: [(&str, &str); 4] = [
+    ("Deployment", "/apis/apps/v1/deployments"),
+    ("StatefulSet", "/apis/apps/v1/statefulsets"),
+    ("DaemonSet", "/apis/apps/v1/daemonsets"),
+    ("CronJob", "/apis/batch/v1/cronjobs"),
+];
+
+/// Local digests and workloads of an image
+#[derive(Default)]
+struct ImageUsage {
+    digests: Vec<String>,
+    workloads: Vec<Workload>,
+}
+
+/// A connection to a cluster's API server
+pub struct Cluster {
+    url: String,
+    client: reqwest::Client,
+    token: Option<String>,
+}
+
+impl Cluster {
+    /// Connects to the cluster configured in the kubeconfig found (see `KubernetesConfig`), or the one Cup is running in
+    pub fn connect(ctx: &Context) -> Result<Self, String> {
+        let config = &ctx.config.kubernetes;
+        let path = match &config.kubeconfig {
+            Some(path) => Some(PathBuf::from(path)),
+            None => kubeconfig_path(),
+        };
+        match path {
+            Some(path) => {
+                ctx.logger
+                    .debug(format!("Using kubeconfig at {}", path.display()));
+                let contents = fs::read_to_string(&path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                let kubeconfig: Kubeconfig = serde_yaml::from_str(&contents)
+                    .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+                Self::from_kubeconfig(
+                    &kubeconfig,
+                    config.context.as_deref(),
+                    path.parent().unwrap_or(&PathBuf::from(".")),
+                )
+            }
+            None => Self::in_cluster(),
+        }
+    }
+
+    /// Uses the service account mounted into the pod Cup is running in
+    fn in_cluster() -> Result<Self, String> {
+        let (host, port) = match (
+            env::var("KUBERNETES_SERVICE_HOST"),
+            env::var("KUBERNETES_SERVICE_PORT"),
+        ) {
+            (Ok(host), Ok(port)) => (host, port),
+            _ => {
+                return Err(String::from(
+                    "No kubeconfig was found and Cup isn't running inside a cluster",
+                ))
+            }
+        };
+        let dir = PathBuf::from(SERVICE_ACCOUNT_DIR);
+        let token = read(&dir.join("token"))?;
+        let ca = fs::read(dir.join("ca.crt")).map_err(|e| e.to_string())?;
+        let client = reqwest::Client::builder()
+            .add_root_certificate(Certificate::from_pem(&ca).map_err(|e| e.to_string())?)
+            .build()
+            .map_err(|e| e.to_string())?;
+        // IPv6 addresses need brackets in URLs
+        let host = if host.contains(':') {
+            format!("[{}]", host)
+        } else {
+            host
+        };
+        Ok(Self {
+            url: format!("https://{}:{}", host, port),
+            client,
+            token: Some(token.trim().to_string()),
+        })
+    }
+
+    /// Uses the cluster and user of a kubeconfig's context. Relative paths in the kubeconfig are resolved from `dir`.
+    fn from_kubeconfig(
+        kubeconfig: &Kubeconfig,
+        context: Option<&str>,
+        dir: &Path,
+    ) -> Result<Self, String> {
+        let context_name = context
+            .or(kubeconfig.current_context.as_deref())
+            .ok_or("The kubeconfig doesn't have a current context")?;
+        let context = &kubeconfig
+            .contexts
+            .iter()
+            .find(|c| c.name == context_name)
+            .ok_or(format!("Context {} not found in kubeconfig", context_name))?
+            .context;
+        let cluster = &kubeconfig
+            .clusters
+            .iter()
+            .find(|c| c.name == context.cluster)
+            .ok_or(format!(
+                "Cluster {} not found in kubeconfig",
+                context.cluster
+            ))?
+            .cluster;
+        let default_user = KubeconfigUser::default();
+        let user = kubeconfig
+            .users
+            .iter()
+            .find(|u| u.name == context.user)
+            .map(|u| &u.user)
+            .unwrap_or(&default_user);
+
+        let mut builder = reqwest::Client::builder();
+        if let Some(ca) = data_or_file(
+            &cluster.certificate_authority_data,
+            &cluster.certificate_authority,
+            dir,
+        )? {
+            builder = builder
+                .add_root_certificate(Certificate::from_pem(&ca).map_err(|e| e.to_string())?);
+        }
+        if cluster.insecure_skip_tls_verify {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        let certificate =
+            data_or_file(&user.client_certificate_data, &user.client_certificate, dir)?;
+        let key = data_or_file(&user.client_key_data, &user.client_key, dir)?;
+        if let (Some(mut pem), Some(key)) = (certificate, key) {
+            pem.push(b'\n');
+            pem.extend(key);
+            builder = builder.identity(Identity::from_pem(&pem).map_err(|e| e.to_string())?);
+        }
+        let token = match (&user.token, &user.token_file) {
+            (Some(token), _) => Some(token.clone()),
+            (None, Some(file)) => Some(read(&dir.join(file))?.trim().to_string()),
+            (None, None) => None,
+        };
+        Ok(Self {
+            url: cluster.server.trim_end_matches('/').to_string(),
+            client: builder.build().map_err(|e| e.to_string())?,
+            token,
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        let mut request = self.client.get(format!("{}{}", self.url, path));
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        let body = response.text().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!("{} returned {}: {}", path, status, body));
+        }
+        serde_json::from_str(&body).map_err(|e| e.to_string())
+    }
+
+    /// Lists the objects at `path` (e.g. `/api/v1/pods`) in the configured namespaces
+    async fn list<T: DeserializeOwned>(&self, ctx: &Context, path: &str) -> Result<Vec<T>, String> {
+        let namespaces = &ctx.config.kubernetes.namespaces;
+        if namespaces.is_empty() {
+            return self.list_pages(path).await;
+        }
+        // Paths look like `/apis/<group>/<version>/<resource>`, namespaced ones have `/namespaces/<namespace>` before the resource
+        let (prefix, resource) = path.rsplit_once('/').unwrap();
+        let mut items = Vec::new();
+        for namespace in namespaces {
+            items.extend(
+                self.list_pages(&format!("{}/namespaces/{}/{}", prefix, namespace, resource))
+                    .await?,
+            );
+        }
+        Ok(items)
+    }
+
+    /// Fetches every page of the list at `path`, since the API server may split large lists
+    async fn list_pages<T: DeserializeOwned>(&self, path: &str) -> Result<Vec<T>, String> {
+        let mut items = Vec::new();
+        let mut continue_token: Option<String> = None;
+        loop {
+            let mut page_path = format!("{}?limit={}", path, PAGE_SIZE);
+            if let Some(token) = &continue_token {
+                page_path.push_str("&continue=");
+                page_path.extend(form_urlencoded::byte_serialize(token.as_bytes()));
+            }
+            let page = self.get::<List<T>>(&page_path).await?;
+            items.extend(page.items);
+            match page.metadata.continue_token {
+                Some(token) if !token.is_empty() => continue_token = Some(token),
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    /// Returns the images used by pods and workloads. If `references` is Some, return only the images whose references match the ones specified.
+    pub async fn images(&self, ctx: &Context, references: &Option<Vec<String>>) -> Vec<Image> {
+        let pods: Vec<Pod> = match self.list(ctx, "/api/v1/pods").await {
+            Ok(pods) => pods,
+            Err(e) => {
+                ctx.logger.warn(format!(
+                    "Failed to retrieve list of pods, skipping Kubernetes images: {}",
+                    e
+                ));
+                return Vec::new();
+            }
+        };
+        let mut controllers: Vec<(&str, Controller)> = Vec::new();
+        for (kind, path) in CONTROLLERS {
+            match self.list::<Controller>(ctx, path).await {
+                Ok(items) => controllers.extend(items.into_iter().map(|item| (kind, item))),
+                // Cup's service account might not be allowed to list everything
+                Err(e) => ctx
+                    .logger
+                    .warn(format!("Failed to retrieve list of {}s: {}", kind, e)),
+            }
+        }
+        ctx.logger.debug(format!(
+            "Found {} pods and {} workloads",
+            pods.len(),
+            controllers.len()
+        ));
+
+        let mut usages: FxHashMap<String, ImageUsage> = FxHashMap::default();
+        for pod in &pods {
+            let workload = workload(pod, &controllers);
+            let statuses: Vec<&ContainerStatus> = pod
+                .status
+                .init_container_statuses
+                .iter()
+                .chain(pod.status.container_statuses.iter())
+                .collect();
+            for container in pod.spec.containers() {
+                let image = match &container.image {
+                    Some(image) => image,
+                    None => continue,
+                };
+                let usage = usages.entry(image.clone()).or_default();
+                // Only repository digests are useful, some runtimes report the image's ID instead
+                if let Some((_, digest)) = statuses
+                    .iter()
+                    .find(|status| status.name == container.name)
+                    .and_then(|status| status.image_id.rsplit_once('@'))
+                {
+                    if !usage.digests.iter().any(|d| d == digest) {
+                        usage.digests.push(digest.to_string());
+                    }
+                }
+                if !usage.workloads.contains(&workload) {
+                    usage.workloads.push(workload.clone());
+                }
+            }
+        }
+        // Workloads without pods (e.g. CronJobs between runs) are only known from their templates
+        for (kind, controller) in &controllers {
+            let workload = Workload {
+                namespace: controller.metadata.namespace.clone(),
+                kind: kind.to_string(),
+                name: controller.metadata.name.clone(),
+            };
+            for image in controller
+                .spec
+                .pod_spec()
+                .into_iter()
+                .flat_map(|spec| spec.containers())
+                .filter_map(|container| container.image.as_ref())
+            {
+                let usage = usages.entry(image.clone()).or_default();
+                if !usage.workloads.contains(&workload) {
+                    usage.workloads.push(workload.clone());
+                }
+            }
+        }
+
+        usages
+            .into_iter()
+            .filter(|(reference, _)| match references {
+                Some(refs) => refs.iter().any(|r| split(r) == split(reference)),
+                None => true,
+            })
+            .filter_map(|(reference, usage)| {
+                let mut image = match usage.digests.first() {
+                    Some(digest) => {
+                        Image::from_inspect_data(ctx, &format!("{}@{}", reference, digest))?
+                    }
+                    // Without a digest we can only check for new versions
+                    None => {
+                        let (_, _, tag) = split(&reference);
+                        Version::from_tag(&tag)?;
+                        Image::from_reference(&reference)
+                    }
+                };
+                if let Some(digest_info) = &mut image.digest_info {
+                    digest_info.local_digests = usage.digests;
+                }
+                image.in_use = true;
+                image.workloads = usage.workloads;
+                Some(image)
+            })
+            .collect()
+    }
+}
+
+/// Returns the images used by the cluster's workloads
+pub async fn get_kubernetes_images(ctx: &Context, references: &Option<Vec<String>>) -> Vec<Image> {
+    match Cluster::connect(ctx) {
+        Ok(cluster) => cluster.images(ctx, references).await,
+        Err(e) => {
+            ctx.logger.warn(format!(
+                "Failed to connect to Kubernetes, skipping its images: {}",
+                e
+            ));
+            Vec::new()
+        }
+    }
+}
+
+/// Finds the workload a pod belongs to. Pods of Deployments and CronJobs are owned by ReplicaSets and Jobs named after them with a suffix.
+fn workload(pod: &Pod, controllers: &[(&str, Controller)]) -> Workload {
+    let namespace = pod.metadata.namespace.clone();
+    let (kind, name) = match pod.metadata.owner_references.first() {
+        Some(owner) => {
+            let parent = match owner.kind.as_str() {
+                "ReplicaSet" => Some("Deployment"),
+                "Job" => Some("CronJob"),
+                _ => None,
+            }
+            .and_then(|parent_kind| {
+                let (parent_name, _) = owner.name.rsplit_once('-')?;
+                controllers
+                    .iter()
+                    .any(|(kind, controller)| {
+                        *kind == parent_kind
+                            && controller.metadata.namespace == namespace
+                            && controller.metadata.name == parent_name
+                    })
+                    .then(|| (parent_kind.to_string(), parent_name.to_string()))
+            });
+            parent.unwrap_or((owner.kind.clone(), owner.name.clone()))
+        }
+        None => (String::from("Pod"), pod.metadata.name.clone()),
+    };
+    Workload {
+        namespace,
+        kind,
+        name,
+    }
+}
+
+/// Returns the path of the first kubeconfig found
+fn kubeconfig_path() -> Option<PathBuf> {
+    if let Ok(paths) = env::var("KUBECONFIG") {
+        // Multiple kubeconfigs can be listed, we only use the first one
+        if let Some(path) = paths.split(':').find(|path| !path.is_empty()) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    let mut candidates = Vec::with_capacity(2);
+    if let Ok(home) = env::var("HOME") {
+        candidates.push(PathBuf::from(home).join(".kube/config"));
+    }
+    candidates.push(PathBuf::from(K3S_KUBECONFIG));
+    candidates.into_iter().find(|path| path.exists())
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+/// Kubeconfigs contain either the base64 encoded contents of a file or its path
+fn data_or_file(
+    data: &Option<String>,
+    file: &Option<String>,
+    dir: &Path,
+) -> Result<Option<Vec<u8>>, String> {
+    match (data, file) {
+        (Some(data), _) => STANDARD
+            .decode(data.trim())
+            .map(Some)
+            .map_err(|e| e.to_string()),
+        (None, Some(file)) => {
+            let path = dir.join(file);
+            fs::read(&path)
+                .map(Some)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        logging::Logger,
+        utils::test_server::{serve, Response},
+    };
+
+    use super::*;
+
+    const KUBECONFIG: &str = r#"
+apiVersion: v1
+kind: Config
+clusters:
+- cluster:
+    server: SERVER/
+  name: default
+contexts:
+- context:
+    cluster: default
+    user: default
+  name: default
+current-context: default
+users:
+- name: default
+  user:
+    token: secret
+"#;
+
+    // Split across two pages, like the API server does with large lists
+    const PODS: &str = r#"{"kind":"PodList","apiVersion":"v1","metadata":{"continue":"pods/2"},"items":[
+        {"metadata":{"name":"web-7d4b9c-x2x9k","namespace":"default","ownerReferences":[{"apiVersion":"apps/v1","kind":"ReplicaSet","name":"web-7d4b9c","controller":true}]},
+         "spec":{"containers":[{"name":"nginx","image":"nginx:1.25.3"}]},
+         "status":{"phase":"Running","containerStatuses":[{"name":"nginx","image":"docker.io/library/nginx:1.25.3","imageID":"docker.io/library/nginx@sha256:4c0fdaa8b6341bfdeca5f18f7837462c80cff90527ee35ef185571e1c327beac"}]}}
+    ]}"#;
+
+    const PODS_NEXT: &str = r#"{"kind":"PodList","apiVersion":"v1","metadata":{"continue":""},"items":[
+        {"metadata":{"name":"coredns-5fc6c5f-lj2xw","namespace":"kube-system","ownerReferences":[{"kind":"ReplicaSet","name":"coredns-5fc6c5f"}]},
+         "spec":{"containers":[{"name":"coredns","image":"rancher/mirrored-coredns-coredns:1.10.1"}]},
+         "status":{"containerStatuses":[{"name":"coredns","imageID":"docker.io/rancher/mirrored-coredns-coredns@sha256:a11fafae1f8037cbbd66c5afa40ba2423936b72b4fd50a7034a7e8b955163594"}]}}
+    ]}"#;
+
+    const DEPLOYMENTS: &str = r#"{"kind":"DeploymentList","items":[
+        {"metadata":{"name":"web","namespace":"default"},"spec":{"replicas":1,"template":{"spec":{"containers":[{"name":"nginx","image":"nginx:1.25.3"}]}}}}
+    ]}"#;
+
+    const CRONJOBS: &str = r#"{"kind":"CronJobList","items":[
+        {"metadata":{"name":"backup","namespace":"default"},"spec":{"schedule":"0 3 * * *","jobTemplate":{"spec":{"template":{"spec":{"containers":[{"name":"restic","image":"restic/restic:0.16.4"}]}}}}}}
+    ]}"#;
+
+    /// Test that images are read from pods and workloads of a fake API server using a kubeconfig's credentials
+    #[tokio::test]
+    async fn kubernetes_images() {
+        let (url, mut requests) = serve(|request| match request.path.as_str() {
+            "/api/v1/pods?limit=500" => Response::new(200, PODS),
+            "/api/v1/pods?limit=500&continue=pods%2F2" => Response::new(200, PODS_NEXT),
+            "/apis/apps/v1/deployments?limit=500" => Response::new(200, DEPLOYMENTS),
+            "/apis/batch/v1/cronjobs?limit=500" => Response::new(200, CRONJOBS),
+            "/apis/apps/v1/daemonsets?limit=500" => Response::new(403, "{}"),
+            _ => Response::new(200, r#"{"items":[]}"#),
+        })
+        .await;
+        let ctx = Context {
+            config: Config::new(),
+            logger: Logger::new(false, false),
+        };
+        let kubeconfig: Kubeconfig =
+            serde_yaml::from_str(&KUBECONFIG.replace("SERVER", &url)).unwrap();
+        let cluster = Cluster::from_kubeconfig(&kubeconfig, None, &PathBuf::from(".")).unwrap();
+        let mut images = cluster.images(&ctx, &None).await;
+        images.sort_by(|a, b| a.reference.cmp(&b.reference));
+
+        assert_eq!(
+            images
+                .iter()
+                .map(|image| image.reference.as_str())
+                .collect::<Vec<&str>>(),
+            vec![
+                "nginx:1.25.3",
+                "rancher/mirrored-coredns-coredns:1.10.1",
+                "restic/restic:0.16.4"
+            ]
+        );
+        assert_eq!(
+            images[0].digest_info.as_ref().unwrap().local_digests,
+            vec![String::from(
+                "sha256:4c0fdaa8b6341bfdeca5f18f7837462c80cff90527ee35ef185571e1c327beac"
+            )]
+        );
+        assert_eq!(
+            images[0].workloads,
+            vec![Workload {
+                namespace: String::from("default"),
+                kind: String::from("Deployment"),
+                name: String::from("web"),
+            }]
+        );
+        // The ReplicaSet's Deployment wasn't listed, so we can't know about it
+        assert_eq!(images[1].workloads[0].kind, "ReplicaSet");
+        assert_eq!(images[2].workloads[0].kind, "CronJob");
+        assert!(images[2].digest_info.is_none());
+        assert_eq!(
+            requests.recv().await.unwrap().headers["authorization"],
+            "Bearer secret"
+        );
+    }
+
+    /// Test that a cluster which can't be read from doesn't stop the other images from being checked
+    #[tokio::test]
+    async fn kubernetes_unavailable() {
+        let (url, _requests) = serve(|_| Response::new(500, "{}")).await;
+        let ctx = Context {
+            config: Config::new(),
+            logger: Logger::new(false, false),
+        };
+        let kubeconfig: Kubeconfig =
+            serde_yaml::from_str(&KUBECONFIG.replace("SERVER", &url)).unwrap();
+        let cluster = Cluster::from_kubeconfig(&kubeconfig, None, &PathBuf::from(".")).unwrap();
+        assert!(cluster.images(&ctx, &None).await.is_empty());
+    }
+}
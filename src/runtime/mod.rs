@@ -0,0 +1,285 @@
+// Container runtimes Cup can get local images from
+
+use std::{collections::HashMap, env, path::PathBuf};
+
+#[cfg(feature = "docker")]
+use bollard::Docker;
+use futures::{future::BoxFuture, FutureExt};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    config::RuntimeType,
+    error::Error,
+    structs::{compose::ComposeService, details::ImageDetails, image::Image},
+    Context,
+};
+
+#[cfg(feature = "containerd")]
+pub mod containerd;
+#[cfg(feature = "docker")]
+pub mod docker;
+pub mod kubernetes;
+pub mod podman;
+#[cfg(feature = "docker")]
+mod ssh;
+
+#[cfg(feature = "containerd")]
+const CONTAINERD_SOCKET: &str = "/run/containerd/containerd.sock";
+
+/// A container runtime. Implementations return an `Error::Runtime` if the runtime can't be reached, it's up to the caller to decide whether that's fatal.
+pub trait Runtime: Send + Sync {
+    /// Returns the images available locally. If `references` is Some, only the images whose references match the ones specified are returned.
+    fn images<'a>(
+        &'a self,
+        ctx: &'a Context,
+        references: &'a Option<Vec<String>>,
+    ) -> BoxFuture<'a, Result<Vec<Image>, Error>>;
+
+    /// Returns the images used by containers, along with the Compose services using them (if any)
+    fn in_use_images<'a>(
+        &'a self,
+        ctx: &'a Context,
+    ) -> BoxFuture<'a, Result<FxHashMap<String, Vec<ComposeService>>, Error>>;
+
+    /// Returns what the local image with the given reference contains, if the runtime reports it. Unlike the other methods, errors are ignored, since the details are optional.
+    fn details<'a>(&'a self, _reference: &'a str) -> BoxFuture<'a, Option<ImageDetails>> {
+        async { None }.boxed()
+    }
+}
+
+/// Connects to the configured container runtime. Returns `None` if the runtime is set to `none`.
+pub fn connect(ctx: &Context) -> Result<Option<Box<dyn Runtime>>, Error> {
+    let Some((runtime, socket)) = detect(ctx) else {
+        return Ok(None);
+    };
+    ctx.logger.debug(format!(
+        "Using {:?} runtime with socket {}",
+        runtime,
+        socket.as_deref().unwrap_or("(default)")
+    ));
+    match runtime {
+        RuntimeType::Podman => match socket.as_deref().map(unix_path) {
+            Some(Some(path)) => Ok(Some(Box::new(podman::PodmanRuntime::new(path)))),
+            // The libpod API is only available over a Unix socket, otherwise we fall back to the Docker-compatible API
+            _ => docker_runtime(socket.as_deref(), ctx),
+        },
+        #[cfg(feature = "containerd")]
+        RuntimeType::Containerd => Ok(Some(Box::new(containerd::ContainerdRuntime::new(
+            socket.as_deref().unwrap_or(CONTAINERD_SOCKET),
+        )))),
+        #[cfg(not(feature = "containerd"))]
+        RuntimeType::Containerd => Err(Error::runtime("This build of Cup doesn't support containerd. Please build Cup with the `containerd` feature enabled.")),
+        RuntimeType::Docker | RuntimeType::Auto => docker_runtime(socket.as_deref(), ctx),
+        RuntimeType::None => Ok(None),
+    }
+}
+
+/// Connects to a Docker-compatible API. Builds without the `docker` feature (e.g. when Cup is used as a library) can't, so they only check the references they're given.
+#[cfg(feature = "docker")]
+fn docker_runtime(socket: Option<&str>, _ctx: &Context) -> Result<Option<Box<dyn Runtime>>, Error> {
+    Ok(Some(Box::new(docker::DockerRuntime::new(socket)?)))
+}
+
+#[cfg(not(feature = "docker"))]
+fn docker_runtime(_socket: Option<&str>, ctx: &Context) -> Result<Option<Box<dyn Runtime>>, Error> {
+    ctx.logger
+        .debug("This build of Cup doesn't support the Docker API, local images won't be checked");
+    Ok(None)
+}
+
+/// Connects to the Docker-compatible API of the runtime in use, for operations only that API supports (e.g. recreating containers)
+#[cfg(feature = "docker")]
+pub fn docker_client(ctx: &Context) -> Result<Docker, String> {
+    match detect(ctx) {
+        Some((RuntimeType::Docker | RuntimeType::Podman, socket)) => {
+            Ok(docker::create_docker_client(socket.as_deref())?)
+        }
+        Some((runtime, _)) => Err(format!("This isn't supported by the {:?} runtime", runtime)),
+        None => Err(String::from("No container runtime is available")),
+    }
+}
+
+/// Figures out which runtime to use and its socket. If no socket is returned, the runtime's default should be used. Returns `None` if the runtime is set to `none`.
+fn detect(ctx: &Context) -> Option<(RuntimeType, Option<String>)> {
+    if ctx.config.runtime == RuntimeType::None {
+        return None;
+    }
+    let socket = ctx.config.socket.clone();
+    Some(match (&ctx.config.runtime, socket) {
+        (RuntimeType::Auto, Some(socket)) => {
+            if socket.contains("podman") {
+                (RuntimeType::Podman, Some(socket))
+            } else if socket.contains("containerd") {
+                (RuntimeType::Containerd, Some(socket))
+            } else {
+                (RuntimeType::Docker, Some(socket))
+            }
+        }
+        (RuntimeType::Auto, None) => auto_detect(),
+        (RuntimeType::Podman, None) => (
+            RuntimeType::Podman,
+            podman_sockets()
+                .iter()
+                .find(|socket| socket.exists())
+                .or(podman_sockets().first())
+                .map(|socket| format!("unix://{}", socket.display())),
+        ),
+        (runtime, socket) => (runtime.clone(), socket),
+    })
+}
+
+/// Looks for the socket of a running runtime. Docker is preferred, since Podman can also be used through Docker's socket.
+fn auto_detect() -> (RuntimeType, Option<String>) {
+    if env::var("DOCKER_HOST").is_ok() || PathBuf::from("/var/run/docker.sock").exists() {
+        return (RuntimeType::Docker, None);
+    }
+    if let Some(socket) = podman_sockets().iter().find(|socket| socket.exists()) {
+        return (
+            RuntimeType::Podman,
+            Some(format!("unix://{}", socket.display())),
+        );
+    }
+    #[cfg(feature = "containerd")]
+    if PathBuf::from(CONTAINERD_SOCKET).exists() {
+        return (
+            RuntimeType::Containerd,
+            Some(format!("unix://{}", CONTAINERD_SOCKET)),
+        );
+    }
+    (RuntimeType::Docker, None)
+}
+
+/// Podman's sockets, in the order they should be tried. Rootless Podman puts its socket in `$XDG_RUNTIME_DIR`.
+fn podman_sockets() -> Vec<PathBuf> {
+    let mut sockets = Vec::with_capacity(2);
+    if let Ok(dir) = env::var("XDG_RUNTIME_DIR") {
+        if !dir.is_empty() {
+            sockets.push(PathBuf::from(dir).join("podman/podman.sock"));
+        }
+    }
+    sockets.push(PathBuf::from("/run/podman/podman.sock"));
+    sockets
+}
+
+/// Returns the path of a Unix socket, or `None` if the socket isn't a Unix socket
+fn unix_path(socket: &str) -> Option<&str> {
+    match socket.strip_prefix("unix://") {
+        Some(path) => Some(path),
+        None => socket.starts_with('/').then_some(socket),
+    }
+}
+
+/// Adds the tag to an image reference if it was omitted, so it matches the references of local images
+fn normalize_image(image: &str) -> String {
+    if image.contains(":") {
+        image.to_string()
+    } else {
+        format!("{image}:latest")
+    }
+}
+
+/// Maps the images of the containers passed in (as image, labels pairs) to the Compose services using them
+fn in_use_images<'a>(
+    containers: impl Iterator<Item = (&'a str, Option<&'a HashMap<String, String>>)>,
+) -> FxHashMap<String, Vec<ComposeService>> {
+    let mut images: FxHashMap<String, Vec<ComposeService>> = FxHashMap::default();
+    for (image, labels) in containers {
+        let services = images.entry(normalize_image(image)).or_default();
+        // Scaled services have more than one container
+        if let Some(service) = labels.and_then(compose_service) {
+            if !services.contains(&service) {
+                services.push(service);
+            }
+        }
+    }
+    images
+}
+
+/// Reads the Compose project and service a container belongs to from its labels. Podman Compose and nerdctl use the same labels as Docker Compose.
+fn compose_service(labels: &HashMap<String, String>) -> Option<ComposeService> {
+    Some(ComposeService {
+        project: labels.get("com.docker.compose.project")?.clone(),
+        service: labels.get("com.docker.compose.service")?.clone(),
+        config_files: labels
+            .get("com.docker.compose.project.config_files")
+            .map(|files| {
+                files
+                    .split(',')
+                    .filter(|file| !file.is_empty())
+                    .map(|file| file.to_string())
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::Config, logging::Logger};
+
+    use super::*;
+
+    /// Test that the runtime is picked based on the configured socket and that containers are grouped by image
+    #[test]
+    fn runtime() {
+        let mut config = Config::new();
+        let mut detected = Vec::new();
+        for socket in [
+            "unix:///run/user/1000/podman/podman.sock",
+            "/run/containerd/containerd.sock",
+            "tcp://localhost:2375",
+        ] {
+            config.socket = Some(socket.to_string());
+            let ctx = Context {
+                config: config.clone(),
+                logger: Logger::new(false, false),
+            };
+            detected.push(detect(&ctx).unwrap().0);
+        }
+        assert_eq!(
+            detected,
+            vec![
+                RuntimeType::Podman,
+                RuntimeType::Containerd,
+                RuntimeType::Docker
+            ]
+        );
+        assert_eq!(
+            unix_path("unix:///run/podman/podman.sock"),
+            Some("/run/podman/podman.sock")
+        );
+        assert_eq!(unix_path("tcp://localhost:2375"), None);
+
+        let labels = HashMap::from([
+            (
+                String::from("com.docker.compose.project"),
+                String::from("immich"),
+            ),
+            (
+                String::from("com.docker.compose.service"),
+                String::from("database"),
+            ),
+            (
+                String::from("com.docker.compose.project.config_files"),
+                String::from("/srv/immich/compose.yml"),
+            ),
+        ]);
+        let images = in_use_images(
+            [
+                ("postgres:15", Some(&labels)),
+                ("postgres:15", Some(&labels)),
+                ("alpine", None),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(
+            images["postgres:15"],
+            vec![ComposeService {
+                project: String::from("immich"),
+                service: String::from("database"),
+                config_files: vec![String::from("/srv/immich/compose.yml")],
+            }]
+        );
+        assert!(images["alpine:latest"].is_empty());
+    }
+}
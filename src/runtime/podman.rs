@@ -0,0 +1,316 @@
+// Podman's native (libpod) API. Podman also implements the Docker API, which is used when its socket isn't a Unix socket.
+
+use std::collections::HashMap;
+
+use futures::{future::BoxFuture, FutureExt};
+use http_body_util::{BodyExt, Empty};
+use hyper::{body::Bytes, client::conn::http1, Request};
+use hyper_util::rt::TokioIo;
+use rustc_hash::FxHashMap;
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::net::UnixStream;
+
+use crate::{
+    error::Error,
+    structs::{
+        compose::ComposeService, details::ImageDetails, image::Image, inspectdata::InspectData,
+    },
+    utils::reference::split,
+    Context,
+};
+
+use super::{in_use_images, Runtime};
+
+/// Any version of the API since Podman 4 works
+const API_PREFIX: &str = "/v4.0.0/libpod";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PodmanImage {
+    id: String,
+    repo_tags: Option<Vec<String>>,
+    repo_digests: Option<Vec<String>>,
+    labels: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PodmanImageInspect {
+    created: Option<String>,
+    #[serde(rename = "RootFS")]
+    root_fs: Option<PodmanRootFs>,
+    labels: Option<HashMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PodmanRootFs {
+    layers: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PodmanContainer {
+    image: String,
+    labels: Option<HashMap<String, String>>,
+}
+
+impl InspectData for &PodmanImage {
+    fn tags(&self) -> Option<Vec<String>> {
+        Some(self.repo_tags.clone().unwrap_or_default())
+    }
+
+    fn digests(&self) -> Option<Vec<String>> {
+        Some(self.repo_digests.clone().unwrap_or_default())
+    }
+
+    fn url(&self) -> Option<String> {
+        self.labels
+            .as_ref()
+            .and_then(|labels| labels.get("org.opencontainers.image.url").cloned())
+    }
+
+    fn source(&self) -> Option<String> {
+        self.labels
+            .as_ref()
+            .and_then(|labels| labels.get("org.opencontainers.image.source").cloned())
+    }
+
+    fn id(&self) -> Option<String> {
+        // Unlike Docker, Podman omits the algorithm
+        Some(match self.id.contains(':') {
+            true => self.id.clone(),
+            false => format!("sha256:{}", self.id),
+        })
+    }
+}
+
+pub struct PodmanRuntime {
+    socket: String,
+}
+
+impl PodmanRuntime {
+    pub fn new(socket: &str) -> Self {
+        Self {
+            socket: socket.to_string(),
+        }
+    }
+
+    /// Sends a GET request to the libpod API and parses the JSON response
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        let stream = UnixStream::connect(&self.socket)
+            .await
+            .map_err(|e| format!("Failed to connect to {}: {}", self.socket, e))?;
+        let (mut sender, connection) = http1::handshake(TokioIo::new(stream))
+            .await
+            .map_err(|e| e.to_string())?;
+        tokio::spawn(connection);
+        let request = Request::get(format!("{}{}", API_PREFIX, path))
+            .header("Host", "localhost")
+            .body(Empty::<Bytes>::new())
+            .unwrap();
+        let response = sender
+            .send_request(request)
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = response.status();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| e.to_string())?
+            .to_bytes();
+        if !status.is_success() {
+            return Err(format!(
+                "Podman returned {}: {}",
+                status,
+                String::from_utf8_lossy(&body)
+            ));
+        }
+        serde_json::from_slice(&body).map_err(|e| e.to_string())
+    }
+}
+
+impl Runtime for PodmanRuntime {
+    fn images<'a>(
+        &'a self,
+        ctx: &'a Context,
+        references: &'a Option<Vec<String>>,
+    ) -> BoxFuture<'a, Result<Vec<Image>, Error>> {
+        async move {
+            let images: Vec<PodmanImage> = self.get("/images/json").await.map_err(|e| {
+                Error::runtime(format!(
+                    "Failed to retrieve list of images available!\n{}",
+                    e
+                ))
+            })?;
+            Ok(images
+                .iter()
+                .filter(|image| match references {
+                    // Podman stores images with their full name (e.g. `docker.io/library/alpine:latest`), so references are compared by their parts
+                    Some(refs) => image
+                        .repo_tags
+                        .iter()
+                        .flatten()
+                        .any(|tag| refs.iter().any(|reference| split(reference) == split(tag))),
+                    None => true,
+                })
+                .filter_map(|image| Image::from_inspect_data(ctx, image))
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn in_use_images<'a>(
+        &'a self,
+        _ctx: &'a Context,
+    ) -> BoxFuture<'a, Result<FxHashMap<String, Vec<ComposeService>>, Error>> {
+        async move {
+            let containers: Vec<PodmanContainer> =
+                self.get("/containers/json?all=true").await.map_err(|e| {
+                    Error::runtime(format!(
+                        "Failed to retrieve list of containers available!\n{}",
+                        e
+                    ))
+                })?;
+            Ok(in_use_images(containers.iter().map(|container| {
+                (container.image.as_str(), container.labels.as_ref())
+            })))
+        }
+        .boxed()
+    }
+
+    fn details<'a>(&'a self, reference: &'a str) -> BoxFuture<'a, Option<ImageDetails>> {
+        async move {
+            let inspect: PodmanImageInspect = self
+                .get(&format!("/images/{}/json", reference))
+                .await
+                .ok()?;
+            Some(ImageDetails {
+                created: inspect.created,
+                layers: inspect
+                    .root_fs
+                    .and_then(|root_fs| root_fs.layers)
+                    .unwrap_or_default(),
+                layer_sizes: Vec::new(),
+                labels: inspect
+                    .labels
+                    .map(|labels| ImageDetails::filter_labels(&labels))
+                    .unwrap_or_default(),
+            })
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        logging::Logger,
+        utils::test_server::{serve_unix, Response},
+    };
+
+    use super::*;
+
+    const IMAGES: &str = r#"[
+        {"Id":"3cc2f0a4d9e5a0e2c8f9c0b7c5b6e0c7d5a2f1e0b9c8d7e6f5a4b3c2d1e0f9a8","RepoTags":["docker.io/library/alpine:3.19"],"RepoDigests":["docker.io/library/alpine@sha256:c5b1261d6d3e43071626931fc004f70149baeba2c8ec672bd4f27761f8e1ad6b"],"Labels":null},
+        {"Id":"sha256:9b1f5e8c7d6a5b4c3d2e1f0a9b8c7d6e5f4a3b2c1d0e9f8a7b6c5d4e3f2a1b0c","RepoTags":["ghcr.io/sergi0g/cup:v3.0.0"],"RepoDigests":["ghcr.io/sergi0g/cup@sha256:4c0fdaa8b6341bfdeca5f18f7837462c80cff90527ee35ef185571e1c327beac"],"Labels":{"org.opencontainers.image.source":"https://github.com/sergi0g/cup"}},
+        {"Id":"5d4c3b2a1f0e9d8c7b6a5f4e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c","RepoTags":["localhost/built:latest"],"RepoDigests":[],"Labels":null}
+    ]"#;
+
+    const CONTAINERS: &str = r#"[
+        {"Image":"docker.io/library/alpine:3.19","Labels":{"com.docker.compose.project":"tools","com.docker.compose.service":"shell"}},
+        {"Image":"docker.io/library/alpine:3.19","Labels":{"com.docker.compose.project":"tools","com.docker.compose.service":"shell"}},
+        {"Image":"ghcr.io/sergi0g/cup","Labels":null}
+    ]"#;
+
+    const INSPECT: &str = r#"{"Created":"2024-01-27T00:30:48.743965523Z","RootFS":{"Type":"layers","Layers":["sha256:d4fc045c9e3a848011de66f34b81f052d4f2c15a17bb196d637e526349601820"]},"Labels":{"org.opencontainers.image.version":"3.19.1","maintainer":"someone"}}"#;
+
+    /// Test that images, containers and image details are read from a stand-in for Podman's socket
+    #[tokio::test]
+    async fn podman_images() {
+        let socket = std::env::temp_dir().join(format!("cup-podman-{}.sock", std::process::id()));
+        let mut requests = serve_unix(&socket, |request| match request.path.as_str() {
+            "/v4.0.0/libpod/images/json" => Response::new(200, IMAGES),
+            "/v4.0.0/libpod/containers/json?all=true" => Response::new(200, CONTAINERS),
+            "/v4.0.0/libpod/images/docker.io/library/alpine:3.19/json" => {
+                Response::new(200, INSPECT)
+            }
+            _ => Response::new(404, r#"{"cause":"image not known"}"#),
+        })
+        .await;
+        let ctx = Context {
+            config: Config::new(),
+            logger: Logger::new(false, false),
+        };
+        let runtime = PodmanRuntime::new(socket.to_str().unwrap());
+
+        // Images without a digest (e.g. ones built locally) can't be checked
+        let images = runtime.images(&ctx, &None).await.unwrap();
+        assert_eq!(
+            images
+                .iter()
+                .map(|image| image.reference.as_str())
+                .collect::<Vec<&str>>(),
+            vec![
+                "docker.io/library/alpine:3.19",
+                "ghcr.io/sergi0g/cup:v3.0.0"
+            ]
+        );
+        // Podman leaves out the algorithm of IDs
+        assert_eq!(
+            images[0].digest_info.as_ref().unwrap().image_id.as_deref(),
+            Some("sha256:3cc2f0a4d9e5a0e2c8f9c0b7c5b6e0c7d5a2f1e0b9c8d7e6f5a4b3c2d1e0f9a8")
+        );
+        assert_eq!(
+            images[1].source.as_deref(),
+            Some("https://github.com/sergi0g/cup")
+        );
+        assert_eq!(requests.recv().await.unwrap().headers["host"], "localhost");
+
+        // Short references match Podman's full ones
+        let images = runtime
+            .images(&ctx, &Some(vec![String::from("alpine:3.19")]))
+            .await
+            .unwrap();
+        assert_eq!(images.len(), 1);
+
+        let in_use = runtime.in_use_images(&ctx).await.unwrap();
+        assert_eq!(in_use.len(), 2);
+        assert_eq!(
+            in_use["docker.io/library/alpine:3.19"],
+            vec![ComposeService {
+                project: String::from("tools"),
+                service: String::from("shell"),
+                config_files: Vec::new(),
+            }]
+        );
+        assert!(in_use["ghcr.io/sergi0g/cup:latest"].is_empty());
+
+        let details = runtime
+            .details("docker.io/library/alpine:3.19")
+            .await
+            .unwrap();
+        assert_eq!(details.layers.len(), 1);
+        assert_eq!(
+            details
+                .labels
+                .into_iter()
+                .collect::<Vec<(String, String)>>(),
+            vec![(
+                String::from("org.opencontainers.image.version"),
+                String::from("3.19.1")
+            )]
+        );
+        // Details are optional, so failing to get them isn't an error
+        assert!(runtime.details("busybox:latest").await.is_none());
+
+        let _ = std::fs::remove_file(&socket);
+        // A runtime that can't be reached is reported to the caller
+        assert_eq!(
+            runtime.images(&ctx, &None).await.unwrap_err().code(),
+            "runtime_error"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+// Docker hosts reached over SSH. Like the Docker CLI, we use the system's `ssh` client (so its config, agent and known hosts apply) and forward the remote socket to a local one.
+
+use std::{env, fs, path::PathBuf, process::Stdio, time::Duration};
+
+use tokio::{
+    io::AsyncReadExt,
+    process::{Child, Command},
+    time::sleep,
+};
+
+const DEFAULT_REMOTE_SOCKET: &str = "/var/run/docker.sock";
+
+/// A running `ssh` process forwarding a remote Docker socket. The process is stopped when this is dropped.
+pub struct SshTunnel {
+    child: Child,
+    pub socket: PathBuf,
+}
+
+impl SshTunnel {
+    /// Connects to the host in an `ssh://[user@]host[:port][/path/to/docker.sock]` URL. `name` is used to name the local socket.
+    pub async fn open(name: &str, url: &str) -> Result<Self, String> {
+        let (destination, port, remote_socket) =
+            parse_url(url).ok_or(format!("Invalid SSH URL {}", url))?;
+        let name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let socket = env::temp_dir().join(format!("cup-{}-{}.sock", std::process::id(), name));
+        let _ = fs::remove_file(&socket);
+        let mut command = Command::new("ssh");
+        command
+            .args([
+                "-nNT",
+                "-o",
+                "BatchMode=yes",
+                "-o",
+                "ExitOnForwardFailure=yes",
+                "-L",
+            ])
+            .arg(format!("{}:{}", socket.display(), remote_socket));
+        if let Some(port) = port {
+            command.args(["-p", port]);
+        }
+        let mut child = command
+            .arg(destination)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to run ssh: {}", e))?;
+        // The socket is created once the connection is established
+        for _ in 0..150 {
+            if socket.exists() {
+                return Ok(Self { child, socket });
+            }
+            if let Ok(Some(status)) = child.try_wait() {
+                let mut stderr = String::new();
+                if let Some(mut output) = child.stderr.take() {
+                    let _ = output.read_to_string(&mut stderr).await;
+                }
+                return Err(format!("ssh exited with {}: {}", status, stderr.trim()));
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        Err(format!("Timed out connecting to {}", url))
+    }
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        let _ = self.child.start_kill();
+        let _ = fs::remove_file(&self.socket);
+    }
+}
+
+/// Splits an SSH URL into its destination (`[user@]host`), port and the path of the remote socket
+fn parse_url(url: &str) -> Option<(String, Option<&str>, &str)> {
+    let rest = url.strip_prefix("ssh://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, ""),
+    };
+    let (user, host) = match authority.rsplit_once('@') {
+        Some((user, host)) => (Some(user), host),
+        None => (None, authority),
+    };
+    let (host, port) = match host.rsplit_once(':') {
+        // Bracketed IPv6 addresses contain colons too
+        Some((host, port)) if !port.contains(']') => (host, Some(port)),
+        _ => (host, None),
+    };
+    // ssh doesn't understand brackets
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host.is_empty() {
+        return None;
+    }
+    let destination = match user {
+        Some(user) => format!("{}@{}", user, host),
+        None => host.to_string(),
+    };
+    let path = if path.is_empty() || path == "/" {
+        DEFAULT_REMOTE_SOCKET
+    } else {
+        path
+    };
+    Some((destination, port, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that SSH URLs are split into the arguments passed to ssh
+    #[test]
+    #[rustfmt::skip]
+    fn ssh_url() {
+        assert_eq!(parse_url("ssh://nas"),                                 Some((String::from("nas"),            None,         "/var/run/docker.sock")));
+        assert_eq!(parse_url("ssh://sergio@nas:2222"),                     Some((String::from("sergio@nas"),     Some("2222"), "/var/run/docker.sock")));
+        assert_eq!(parse_url("ssh://sergio@nas/run/user/1000/docker.sock"), Some((String::from("sergio@nas"),     None,         "/run/user/1000/docker.sock")));
+        assert_eq!(parse_url("ssh://root@[fd00::1]:22"),                   Some((String::from("root@fd00::1"),   Some("22"),   "/var/run/docker.sock")));
+        assert_eq!(parse_url("ssh://"),                                    None);
+        assert_eq!(parse_url("tcp://nas:2375"),                            None);
+    }
+}
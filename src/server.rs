@@ -1,7 +1,8 @@
 use std::{env, sync::Arc};
 
-use chrono::Local;
+use chrono::{DateTime, Local, NaiveDate};
 use chrono_tz::Tz;
+use croner::Cron;
 use liquid::{object, Object, ValueView};
 use rustc_hash::FxHashMap;
 use serde_json::Value;
@@ -12,21 +13,26 @@ use xitca_web::{
     bytes::Bytes,
     error::Error,
     handler::{handler_service, path::PathRef, state::StateRef},
-    http::{StatusCode, WebResponse},
-    route::get,
+    http::{header, StatusCode, WebRequest, WebResponse},
+    route::{get, post},
     service::Service,
     App, WebContext,
 };
 
 use crate::{
+    acknowledgements::Acknowledgements,
+    apply::{applicable, apply_updates},
     check::get_updates,
     config::Theme,
     error,
+    history::{CheckRun, History},
+    metrics,
+    notifications::notify,
     structs::update::Update,
     utils::{
-        json::{to_full_json, to_simple_json},
+        json::{get_metrics, to_full_json, to_simple_json},
         sort_update_vec::sort_update_vec,
-        time::{elapsed, now},
+        time::{elapsed, now, timestamp},
     },
     Context,
 };
@@ -38,33 +44,45 @@ const FAVICON_ICO: Bytes = Bytes::from_static(include_bytes!("static/favicon.ico
 const FAVICON_SVG: Bytes = Bytes::from_static(include_bytes!("static/favicon.svg"));
 const APPLE_TOUCH_ICON: Bytes = Bytes::from_static(include_bytes!("static/apple-touch-icon.png"));
 
-const SORT_ORDER: [&str; 8] = [
+const SORT_ORDER: [&str; 9] = [
     "monitored_images",
     "updates_available",
     "major_updates",
     "minor_updates",
     "patch_updates",
     "other_updates",
+    "acknowledged",
     "up_to_date",
     "unknown",
 ]; // For Liquid rendering
 
+/// The only routes that accept `POST` requests. Everything else is read-only.
+const POST_ROUTES: [&str; 4] = [
+    "/api/v3/update",
+    "/api/v3/acknowledge",
+    "/api/v3/snooze",
+    "/api/v3/unacknowledge",
+];
+
+/// Held while updates are being applied, so they can't run concurrently
+static APPLYING: Mutex<()> = Mutex::const_new(());
+
 pub async fn serve(port: &u16, ctx: &Context) -> std::io::Result<()> {
     ctx.logger.info("Starting server, please wait...");
-    let data = ServerData::new(ctx).await;
-    let scheduler = JobScheduler::new().await.unwrap();
-    let data = Arc::new(Mutex::new(data));
-    let data_copy = data.clone();
     let tz = env::var("TZ")
         .map(|tz| tz.parse().unwrap_or(Tz::UTC))
         .unwrap_or(Tz::UTC);
+    let data = ServerData::new(ctx, tz).await;
+    let scheduler = JobScheduler::new().await.unwrap();
+    let data = Arc::new(Mutex::new(data));
+    let data_copy = data.clone();
     if let Some(interval) = &ctx.config.refresh_interval {
         scheduler
             .add(
                 match Job::new_async_tz(interval, tz, move |_uuid, _lock| {
                     let data_copy = data_copy.clone();
                     Box::pin(async move {
-                        data_copy.lock().await.refresh().await;
+                        refresh_and_notify(&data_copy).await;
                     })
                 }) {
                     Ok(job) => job,
@@ -90,7 +108,27 @@ pub async fn serve(port: &u16, ctx: &Context) -> std::io::Result<()> {
         .at("/api/v2/json", get(handler_service(api_simple)))
         .at("/api/v3/json", get(handler_service(api_full)))
         .at("/api/v2/refresh", get(handler_service(refresh)))
-        .at("/api/v3/refresh", get(handler_service(refresh)));
+        .at("/api/v3/refresh", get(handler_service(refresh)))
+        .at("/api/v3/history", get(handler_service(api_history)))
+        .at("/metrics", get(handler_service(api_metrics)));
+    // Routes that change something are opt-in, since anyone who can reach the server could use them
+    if ctx.config.apply.endpoint {
+        if token(ctx).is_some() {
+            app_builder = app_builder
+                .at("/api/v3/update", post(handler_service(api_update)))
+                .at(
+                    "/api/v3/acknowledge",
+                    post(handler_service(api_acknowledge)),
+                )
+                .at("/api/v3/snooze", post(handler_service(api_snooze)))
+                .at(
+                    "/api/v3/unacknowledge",
+                    post(handler_service(api_unacknowledge)),
+                );
+        } else {
+            ctx.logger.warn("The update and acknowledgement endpoints are enabled, but `apply.token` isn't set. They will stay disabled until you set one.");
+        }
+    }
     if !ctx.config.agent {
         app_builder = app_builder
             .at("/", get(handler_service(_static)))
@@ -172,11 +210,237 @@ async fn api_full(data: StateRef<'_, Arc<Mutex<ServerData>>>) -> WebResponse {
     }
 }
 
+async fn api_history(data: StateRef<'_, Arc<Mutex<ServerData>>>) -> WebResponse {
+    let runs = match &data.lock().await.history {
+        Some(history) => history
+            .runs()
+            .iter()
+            .rev()
+            .map(|run| {
+                serde_json::json!({
+                    "timestamp": to_rfc3339(run.timestamp),
+                    "duration": run.duration,
+                    "metrics": get_metrics(&run.updates),
+                })
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    WebResponse::builder()
+        .header("Content-Type", "application/json")
+        .body(ResponseBody::from(
+            serde_json::json!({ "runs": runs }).to_string(),
+        ))
+        .unwrap()
+}
+
+async fn api_metrics(data: StateRef<'_, Arc<Mutex<ServerData>>>) -> WebResponse {
+    WebResponse::builder()
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(ResponseBody::from(metrics::render(
+            &data.lock().await.raw_updates,
+        )))
+        .unwrap()
+}
+
+async fn api_update(
+    data: StateRef<'_, Arc<Mutex<ServerData>>>,
+    request: &WebRequest<()>,
+    body: String,
+) -> WebResponse {
+    let ctx = data.lock().await.ctx.clone();
+    if let Some(response) = reject(request, &ctx) {
+        return response;
+    }
+    let request: Value = serde_json::from_str(&body).unwrap_or_default();
+    let references = match (request["all"].as_bool(), request["images"].as_array()) {
+        (Some(true), _) => None,
+        (_, Some(images)) => Some(
+            images
+                .iter()
+                .filter_map(|image| image.as_str().map(|image| image.to_string()))
+                .collect(),
+        ),
+        _ => {
+            return error_response(
+                400,
+                "Expected a JSON body with either an `images` array or `\"all\": true`",
+            )
+        }
+    };
+    // The server keeps responding while the updates are applied, only other updates have to wait
+    let _applying = APPLYING.lock().await;
+    let updates: Vec<Update> = applicable(&data.lock().await.raw_updates, &references)
+        .into_iter()
+        .cloned()
+        .collect();
+    let applied = apply_updates(&updates.iter().collect::<Vec<&Update>>(), &ctx).await;
+    if !applied.is_empty() {
+        refresh_and_notify(&data).await;
+    }
+    WebResponse::builder()
+        .header("Content-Type", "application/json")
+        .body(ResponseBody::from(
+            serde_json::json!({ "updates": applied }).to_string(),
+        ))
+        .unwrap()
+}
+
+async fn api_acknowledge(
+    data: StateRef<'_, Arc<Mutex<ServerData>>>,
+    request: &WebRequest<()>,
+    body: String,
+) -> WebResponse {
+    let mut data = data.lock().await;
+    if let Some(response) = reject(request, &data.ctx) {
+        return response;
+    }
+    let request: Value = serde_json::from_str(&body).unwrap_or_default();
+    let Some(reference) = request["reference"].as_str() else {
+        return error_response(400, "Expected a JSON body with a `reference`");
+    };
+    let server = request["server"].as_str();
+    // Without a version, the update that's currently available is acknowledged
+    let version = match request["version"].as_str() {
+        Some(version) => version.to_string(),
+        None => match data
+            .find(server, reference)
+            .and_then(|update| update.new_version())
+        {
+            Some(version) => version.to_string(),
+            None => return error_response(404, "No update is available for this image"),
+        },
+    };
+    data.acknowledge(server, reference, |acknowledgements| {
+        acknowledgements.acknowledge(server, reference, &version)
+    })
+}
+
+async fn api_snooze(
+    data: StateRef<'_, Arc<Mutex<ServerData>>>,
+    request: &WebRequest<()>,
+    body: String,
+) -> WebResponse {
+    let mut data = data.lock().await;
+    if let Some(response) = reject(request, &data.ctx) {
+        return response;
+    }
+    let request: Value = serde_json::from_str(&body).unwrap_or_default();
+    let (Some(reference), Some(until)) = (
+        request["reference"].as_str(),
+        request["until"].as_str().and_then(parse_until),
+    ) else {
+        return error_response(
+            400,
+            "Expected a JSON body with a `reference` and an `until` date (YYYY-MM-DD or RFC 3339)",
+        );
+    };
+    let server = request["server"].as_str();
+    data.acknowledge(server, reference, |acknowledgements| {
+        acknowledgements.snooze(server, reference, until)
+    })
+}
+
+async fn api_unacknowledge(
+    data: StateRef<'_, Arc<Mutex<ServerData>>>,
+    request: &WebRequest<()>,
+    body: String,
+) -> WebResponse {
+    let mut data = data.lock().await;
+    if let Some(response) = reject(request, &data.ctx) {
+        return response;
+    }
+    let request: Value = serde_json::from_str(&body).unwrap_or_default();
+    let Some(reference) = request["reference"].as_str() else {
+        return error_response(400, "Expected a JSON body with a `reference`");
+    };
+    let server = request["server"].as_str();
+    data.acknowledge(server, reference, |acknowledgements| {
+        acknowledgements.remove(server, reference)
+    })
+}
+
+/// Parses the end of a snooze, which is either a date (snoozing until the start of that day) or an RFC 3339 timestamp
+fn parse_until(until: &str) -> Option<u64> {
+    let until = match DateTime::parse_from_rfc3339(until) {
+        Ok(until) => until.timestamp_millis(),
+        Err(_) => NaiveDate::parse_from_str(until, "%Y-%m-%d")
+            .ok()?
+            .and_hms_opt(0, 0, 0)?
+            .and_local_timezone(Local)
+            .earliest()?
+            .timestamp_millis(),
+    };
+    u64::try_from(until).ok()
+}
+
+/// Returns the token requests that change something must be authorized with, if one is configured
+fn token(ctx: &Context) -> Option<&str> {
+    ctx.config
+        .apply
+        .token
+        .as_deref()
+        .filter(|token| !token.is_empty())
+}
+
+/// Returns an error response if a request isn't allowed to change anything. It has to send the configured token as a bearer token and a JSON body, which browsers won't send to another site without asking it first.
+fn reject(request: &WebRequest<()>, ctx: &Context) -> Option<WebResponse> {
+    let header = |name| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    let authorized = match (
+        token(ctx),
+        header(header::AUTHORIZATION).and_then(|value| value.strip_prefix("Bearer ")),
+    ) {
+        (Some(token), Some(sent)) => constant_time_eq(token.as_bytes(), sent.as_bytes()),
+        _ => false,
+    };
+    if !authorized {
+        return Some(error_response(401, "Missing or invalid token"));
+    }
+    let json = header(header::CONTENT_TYPE).is_some_and(|content_type| {
+        content_type
+            .split(';')
+            .next()
+            .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+    });
+    if !json {
+        return Some(error_response(415, "Expected a JSON body"));
+    }
+    None
+}
+
+/// Compares two byte strings in an amount of time that only depends on their length, so the token can't be guessed by timing responses
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn error_response(status: u16, message: &'static str) -> WebResponse {
+    WebResponse::builder()
+        .status(status)
+        .body(ResponseBody::from(message))
+        .unwrap()
+}
+
 async fn refresh(data: StateRef<'_, Arc<Mutex<ServerData>>>) -> WebResponse {
-    data.lock().await.refresh().await;
+    refresh_and_notify(&data).await;
     WebResponse::new(ResponseBody::from("OK"))
 }
 
+/// Checks for updates and notifies about the new ones once the data is unlocked again, so the server keeps responding while notifications are sent
+async fn refresh_and_notify(data: &Mutex<ServerData>) {
+    let (updates, ctx) = {
+        let mut data = data.lock().await;
+        (data.refresh().await, data.ctx.clone())
+    };
+    if let Some(updates) = updates {
+        notify(&updates, &ctx).await;
+    }
+}
+
 struct ServerData {
     template: String,
     raw_updates: Vec<Update>,
@@ -184,10 +448,12 @@ struct ServerData {
     full_json: Value,
     ctx: Context,
     theme: &'static str,
+    history: Option<History>,
+    last_updated: DateTime<Local>,
 }
 
 impl ServerData {
-    async fn new(ctx: &Context) -> Self {
+    async fn new(ctx: &Context, tz: Tz) -> Self {
         let mut s = Self {
             ctx: ctx.clone(),
             template: String::new(),
@@ -195,22 +461,102 @@ impl ServerData {
             full_json: Value::Null,
             raw_updates: Vec::new(),
             theme: "neutral",
+            history: History::open(ctx),
+            last_updated: Local::now(),
         };
-        s.refresh().await;
+        // Reuse the results of the last check if they're recent enough, so restarts don't trigger a full check
+        match s
+            .history
+            .as_ref()
+            .and_then(|history| history.latest())
+            .filter(|run| is_fresh(run.timestamp, ctx, tz))
+        {
+            Some(run) => {
+                ctx.logger.info(format!(
+                    "Loaded results of previous check from {}",
+                    to_local(run.timestamp).format("%Y-%m-%d %H:%M:%S")
+                ));
+                s.update(run.updates, to_local(run.timestamp));
+            }
+            None => {
+                if let Some(updates) = s.refresh().await {
+                    notify(&updates, ctx).await;
+                }
+            }
+        }
         s
     }
-    async fn refresh(&mut self) {
+    /// Checks for updates and regenerates the data. Returns the results (or `None` if the check failed), so the caller can notify about them once the data is unlocked.
+    async fn refresh(&mut self) -> Option<Vec<Update>> {
         let start = now();
+        let started_at = timestamp();
         if !self.raw_updates.is_empty() {
             self.ctx.logger.info("Refreshing data");
         }
-        let updates = sort_update_vec(&get_updates(&None, true, &self.ctx).await);
+        let updates = match get_updates(&None, true, &self.ctx).await {
+            Ok(updates) => updates,
+            Err(e) => {
+                self.ctx.logger.warn(format!(
+                    "Failed to check for updates, keeping the previous results. {}",
+                    e
+                ));
+                return None;
+            }
+        };
         self.ctx.logger.info(format!(
             "✨ Checked {} images in {}ms",
             updates.len(),
             elapsed(start)
         ));
-        self.raw_updates = updates;
+        updates
+            .iter()
+            .filter(|update| !update.is_remote()) // Remote servers measure their own images
+            .for_each(|update| metrics::observe_check(update.time));
+        if let Some(history) = &self.history {
+            history.record(&CheckRun {
+                timestamp: started_at,
+                duration: elapsed(start),
+                updates: updates.clone(),
+            });
+        }
+        self.update(updates.clone(), Local::now());
+        Some(updates)
+    }
+    /// Returns the latest result for an image
+    fn find(&self, server: Option<&str>, reference: &str) -> Option<&Update> {
+        self.raw_updates
+            .iter()
+            .find(|update| update.server.as_deref() == server && update.reference == reference)
+    }
+    /// Changes an image's acknowledgement with `f` and regenerates the data, so the change is visible right away. Responds with the image's new result.
+    fn acknowledge(
+        &mut self,
+        server: Option<&str>,
+        reference: &str,
+        f: impl FnOnce(&Acknowledgements) -> bool,
+    ) -> WebResponse {
+        if !Acknowledgements::get(&self.ctx).is_some_and(f) {
+            return error_response(
+                500,
+                "Failed to save acknowledgement, see the logs for details",
+            );
+        }
+        self.update(self.raw_updates.clone(), self.last_updated);
+        WebResponse::builder()
+            .header("Content-Type", "application/json")
+            .body(ResponseBody::from(
+                serde_json::json!({ "image": self.find(server, reference) }).to_string(),
+            ))
+            .unwrap()
+    }
+    /// Regenerates JSON and HTML data from a list of updates
+    fn update(&mut self, mut updates: Vec<Update>, last_updated: DateTime<Local>) {
+        // Acknowledgements may have changed since the updates were checked, e.g. if a snooze ended
+        if let Some(acknowledgements) = Acknowledgements::get(&self.ctx) {
+            acknowledgements.apply(&mut updates);
+        }
+        self.raw_updates = sort_update_vec(&updates);
+        self.last_updated = last_updated;
         let template = liquid::ParserBuilder::with_stdlib()
             .build()
             .unwrap()
@@ -218,7 +564,17 @@ impl ServerData {
             .unwrap();
         self.simple_json = to_simple_json(&self.raw_updates);
         self.full_json = to_full_json(&self.raw_updates);
-        let last_updated = Local::now();
+        if let Some(history) = &self.history {
+            let first_seen = history.first_seen(&self.raw_updates);
+            if let Some(images) = self.full_json["images"].as_array_mut() {
+                images
+                    .iter_mut()
+                    .zip(first_seen)
+                    .for_each(|(image, first_seen)| {
+                        image["first_seen"] = first_seen.map(to_rfc3339).into()
+                    });
+            }
+        }
         self.simple_json["last_updated"] = last_updated
             .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
             .to_string()
@@ -269,6 +625,40 @@ impl ServerData {
     }
 }
 
+/// Results which aren't older than this are reused on startup when there's no refresh interval
+const MAX_AGE: u64 = 60 * 60 * 1000;
+
+/// Returns whether the results of a check started at `started_at` are still up to date, i.e. no scheduled refresh has been missed since. Without a refresh interval, they're up to date for `MAX_AGE`.
+fn is_fresh(started_at: u64, ctx: &Context, tz: Tz) -> bool {
+    let Some(interval) = &ctx.config.refresh_interval else {
+        return timestamp() < started_at + MAX_AGE;
+    };
+    let Ok(schedule) = Cron::new(interval)
+        .with_seconds_required()
+        .with_dom_and_dow()
+        .parse()
+    else {
+        return false;
+    };
+    let Some(checked_at) = DateTime::from_timestamp_millis(started_at as i64) else {
+        return false;
+    };
+    match schedule.find_next_occurrence(&checked_at.with_timezone(&tz), false) {
+        Ok(next) => Local::now() < next,
+        Err(_) => false,
+    }
+}
+
+fn to_local(timestamp: u64) -> DateTime<Local> {
+    DateTime::from_timestamp_millis(timestamp as i64)
+        .unwrap_or_default()
+        .with_timezone(&Local)
+}
+
+fn to_rfc3339(timestamp: u64) -> String {
+    to_local(timestamp).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
 async fn logger<S, C, B>(next: &S, ctx: WebContext<'_, C, B>) -> Result<WebResponse, Error<C>>
 where
     S: for<'r> Service<WebContext<'r, C, B>, Response = WebResponse, Error = Error<C>>,
@@ -277,9 +667,14 @@ where
     let request = ctx.req();
     let method = request.method().to_string();
     let url = request.uri().to_string();
+    let allowed = match method.as_str() {
+        "GET" => true,
+        "POST" => POST_ROUTES.contains(&request.uri().path()),
+        _ => false,
+    };
 
-    if &method != "GET" {
-        // We only allow GET requests
+    if !allowed {
+        // We only allow GET requests, and POST requests to the routes that need them
 
         log(&method, &url, 405, elapsed(start));
         Err(Error::from(StatusCode::METHOD_NOT_ALLOWED))
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+use super::update::Update;
+
+/// Why an update a user decided to skip isn't reported as one
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct Acknowledgement {
+    /// The tag or digest of the acknowledged update. A newer one is reported again.
+    pub version: Option<String>,
+    /// Unix timestamp (in milliseconds) all of the image's updates are snoozed until
+    pub until: Option<u64>,
+}
+
+impl Acknowledgement {
+    /// Whether the update is acknowledged at `now` (a Unix timestamp in milliseconds)
+    pub fn applies_to(&self, update: &Update, now: u64) -> bool {
+        self.until.is_some_and(|until| now < until)
+            || self
+                .version
+                .as_deref()
+                .is_some_and(|version| update.new_version() == Some(version))
+    }
+}
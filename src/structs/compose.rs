@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// A Docker Compose service, read from the labels Compose adds to the containers it creates
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ComposeService {
+    pub project: String,
+    pub service: String,
+    /// Paths of the compose files the project was created from
+    pub config_files: Vec<String>,
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The labels compared between the local and the remote image
+pub const LABELS: [&str; 3] = [
+    "org.opencontainers.image.version",
+    "org.opencontainers.image.revision",
+    "org.opencontainers.image.source",
+];
+
+/// What an image contains, read from a runtime or from the image's manifest and config in a registry
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+pub struct ImageDetails {
+    /// When the image was built, in RFC 3339 format
+    pub created: Option<String>,
+    /// The digests of the uncompressed layers (diff IDs), which are the same locally and in registries
+    pub layers: Vec<String>,
+    /// The compressed size of each layer. Only known for remote images.
+    pub layer_sizes: Vec<u64>,
+    /// The values of `LABELS` the image has
+    pub labels: BTreeMap<String, String>,
+}
+
+impl ImageDetails {
+    /// Keeps the labels in `LABELS` out of all of the image's labels
+    pub fn filter_labels<'a>(
+        labels: impl IntoIterator<Item = (&'a String, &'a String)>,
+    ) -> BTreeMap<String, String> {
+        labels
+            .into_iter()
+            .filter(|(name, _)| LABELS.contains(&name.as_str()))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+}
+
+/// What changed between the local image and the one a digest update points to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct UpdateDetails {
+    pub local_created: Option<String>,
+    pub remote_created: Option<String>,
+    /// `None` if the local image's layers aren't known
+    pub local_layers: Option<usize>,
+    pub remote_layers: usize,
+    /// The number of layers of the remote image which aren't available locally. `None` if the local image's layers aren't known.
+    pub new_layers: Option<usize>,
+    /// The compressed size of the remote image, in bytes
+    pub size: u64,
+    /// How much has to be downloaded to update (the compressed size of the new layers), in bytes. `None` if the local image's layers aren't known.
+    pub download_size: Option<u64>,
+    /// The labels whose values differ
+    pub labels: Vec<LabelChange>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LabelChange {
+    pub name: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl UpdateDetails {
+    pub fn new(local: Option<&ImageDetails>, remote: &ImageDetails) -> Self {
+        // Without the local layers, every layer would look new
+        let local = local.filter(|local| !local.layers.is_empty());
+        let new_layers: Option<Vec<usize>> = local.map(|local| {
+            remote
+                .layers
+                .iter()
+                .enumerate()
+                .filter(|(_, layer)| !local.layers.contains(layer))
+                .map(|(index, _)| index)
+                .collect()
+        });
+        let labels = match local {
+            Some(local) => LABELS
+                .iter()
+                .filter_map(|name| {
+                    let (old, new) = (local.labels.get(*name), remote.labels.get(*name));
+                    (old != new).then(|| LabelChange {
+                        name: name.to_string(),
+                        old: old.cloned(),
+                        new: new.cloned(),
+                    })
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        Self {
+            local_created: local.and_then(|local| local.created.clone()),
+            remote_created: remote.created.clone(),
+            local_layers: local.map(|local| local.layers.len()),
+            remote_layers: remote.layers.len(),
+            download_size: new_layers.as_ref().map(|new_layers| {
+                new_layers
+                    .iter()
+                    .filter_map(|index| remote.layer_sizes.get(*index))
+                    .sum()
+            }),
+            new_layers: new_layers.map(|new_layers| new_layers.len()),
+            size: remote.layer_sizes.iter().sum(),
+            labels,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test that only layers which aren't available locally count towards the download size
+    #[test]
+    fn update_details() {
+        let local = ImageDetails {
+            created: Some(String::from("2025-01-01T00:00:00Z")),
+            layers: vec![String::from("sha256:base"), String::from("sha256:app-1")],
+            layer_sizes: Vec::new(),
+            labels: BTreeMap::from([
+                (
+                    String::from("org.opencontainers.image.version"),
+                    String::from("1.0"),
+                ),
+                (
+                    String::from("org.opencontainers.image.source"),
+                    String::from("https://github.com/sergi0g/cup"),
+                ),
+            ]),
+        };
+        let remote = ImageDetails {
+            created: Some(String::from("2025-02-01T00:00:00Z")),
+            layers: vec![
+                String::from("sha256:base"),
+                String::from("sha256:app-2"),
+                String::from("sha256:config"),
+            ],
+            layer_sizes: vec![30_000_000, 5_000_000, 1_000],
+            labels: BTreeMap::from([
+                (
+                    String::from("org.opencontainers.image.version"),
+                    String::from("1.1"),
+                ),
+                (
+                    String::from("org.opencontainers.image.source"),
+                    String::from("https://github.com/sergi0g/cup"),
+                ),
+            ]),
+        };
+        assert_eq!(
+            UpdateDetails::new(Some(&local), &remote),
+            UpdateDetails {
+                local_created: Some(String::from("2025-01-01T00:00:00Z")),
+                remote_created: Some(String::from("2025-02-01T00:00:00Z")),
+                local_layers: Some(2),
+                remote_layers: 3,
+                new_layers: Some(2),
+                size: 35_001_000,
+                download_size: Some(5_001_000),
+                labels: vec![LabelChange {
+                    name: String::from("org.opencontainers.image.version"),
+                    old: Some(String::from("1.0")),
+                    new: Some(String::from("1.1")),
+                }],
+            }
+        );
+        let without_local = UpdateDetails::new(None, &remote);
+        assert_eq!(without_local.download_size, None);
+        assert!(without_local.labels.is_empty());
+    }
+}
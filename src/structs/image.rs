@@ -1,16 +1,22 @@
 use crate::{
-    error,
+    error::Error,
     http::Client,
     registry::{get_latest_digest, get_latest_tag},
-    structs::{status::Status, version::Version},
+    structs::{platform::Platform, status::Status, version::Version},
     utils::reference::split,
     Context,
 };
 
 use super::{
+    compose::ComposeService,
+    details::{ImageDetails, UpdateDetails},
     inspectdata::InspectData,
     parts::Parts,
-    update::{DigestUpdateInfo, Update, UpdateInfo, UpdateResult, VersionUpdateInfo},
+    release::Release,
+    update::{
+        DigestUpdateInfo, PlatformDigest, Update, UpdateInfo, UpdateResult, VersionUpdateInfo,
+    },
+    workload::Workload,
 };
 
 #[derive(Clone, PartialEq)]
@@ -18,6 +24,31 @@ use super::{
 pub struct DigestInfo {
     pub local_digests: Vec<String>,
     pub remote_digest: Option<String>,
+    /// The local image's ID, which is the digest of its config
+    pub image_id: Option<String>,
+    /// The local image's platform, if the runtime reports it
+    pub platform: Option<Platform>,
+    /// If the remote digest is of a manifest list, the manifest for each platform in it
+    pub platforms: Vec<PlatformManifest>,
+    /// If the remote digest is of a manifest list, whether the manifest for the local image's platform differs from the local image. `None` if that couldn't be determined.
+    pub platform_updated: Option<bool>,
+    /// What the local image contains, if the runtime reports it. Only read for images with an update.
+    pub local_details: Option<ImageDetails>,
+    /// What the image the remote digest points to contains. Only fetched if it differs from the local image.
+    pub remote_details: Option<ImageDetails>,
+}
+
+/// See `Image::check_key`
+pub type CheckKey<'a> = (
+    &'a str,
+    Option<(&'a [String], Option<&'a str>, Option<&'a Platform>)>,
+);
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub struct PlatformManifest {
+    pub platform: Platform,
+    pub digest: String,
 }
 
 #[derive(Clone, PartialEq)]
@@ -26,20 +57,33 @@ pub struct VersionInfo {
     pub current_tag: Version,
     pub latest_remote_tag: Option<Version>,
     pub format_str: String,
+    /// The releases between the current and the latest version, if release notes are enabled and the image's source is known
+    pub releases: Vec<Release>,
+    /// The page listing all of the source repository's releases
+    pub releases_url: Option<String>,
 }
 
 /// Image struct that contains all information that may be needed by a function working with an image.
 /// It's designed to be passed around between functions
 #[derive(Clone, PartialEq, Default)]
 #[cfg_attr(test, derive(Debug))]
+#[non_exhaustive]
 pub struct Image {
     pub reference: String,
     pub parts: Parts,
     pub url: Option<String>,
+    /// The URL of the image's source code, if the runtime reports its labels
+    pub source: Option<String>,
     pub digest_info: Option<DigestInfo>,
     pub version_info: Option<VersionInfo>,
     pub in_use: bool,
-    pub error: Option<String>,
+    pub compose: Vec<ComposeService>,
+    pub workloads: Vec<Workload>,
+    /// The configured Docker host the image was found on, if it isn't local
+    pub host: Option<String>,
+    pub error: Option<Error>,
+    /// Set when the image wasn't checked, because the registry's rate limit was nearly exhausted
+    pub deferred: bool,
     pub time_ms: u32,
 }
 
@@ -78,14 +122,23 @@ impl Image {
                     tag,
                 },
                 url: image.url(),
+                source: image.source(),
                 digest_info: Some(DigestInfo {
                     local_digests,
                     remote_digest: None,
+                    image_id: image.id(),
+                    platform: image.platform(),
+                    platforms: Vec::new(),
+                    platform_updated: None,
+                    local_details: None,
+                    remote_details: None,
                 }),
                 version_info: version_tag.map(|(vtag, format_str)| VersionInfo {
                     current_tag: vtag,
                     format_str,
                     latest_remote_tag: None,
+                    releases: Vec::new(),
+                    releases_url: None,
                 }),
                 ..Default::default()
             })
@@ -94,35 +147,60 @@ impl Image {
         }
     }
 
-    /// Creates and populates the fields of an Image object based on a reference. If the tag is not recognized as a version string, exits the program with an error.
+    /// Creates and populates the fields of an Image object based on a reference. If the tag is not recognized as a version string, the image can't be checked, so its error is set.
     pub fn from_reference(reference: &str) -> Self {
         let (registry, repository, tag) = split(reference);
         let version_tag = Version::from_tag(&tag);
-        match version_tag {
-            Some((version, format_str)) => Self {
-                reference: reference.to_string(),
-                parts: Parts {
-                    registry,
-                    repository,
-                    tag,
-                },
-                version_info: Some(VersionInfo {
+        let (version_info, error) = match version_tag {
+            Some((version, format_str)) => (
+                Some(VersionInfo {
                     current_tag: version,
                     format_str,
                     latest_remote_tag: None,
+                    releases: Vec::new(),
+                    releases_url: None,
                 }),
-                ..Default::default()
-            },
-            None => error!(
-                "Image {} is not available locally and does not have a recognizable tag format!",
-                reference
+                None,
             ),
+            None => (
+                None,
+                Some(Error::UnsupportedTag {
+                    reference: reference.to_string(),
+                }),
+            ),
+        };
+        Self {
+            reference: reference.to_string(),
+            parts: Parts {
+                registry,
+                repository,
+                tag,
+            },
+            version_info,
+            error,
+            ..Default::default()
         }
     }
 
+    /// Identifies what checking the image depends on: its reference and the local image the registry's answer is compared with. Images on different hosts with the same key get the same result.
+    pub fn check_key(&self) -> CheckKey<'_> {
+        (
+            &self.reference,
+            self.digest_info.as_ref().map(|digest_info| {
+                (
+                    digest_info.local_digests.as_slice(),
+                    digest_info.image_id.as_deref(),
+                    digest_info.platform.as_ref(),
+                )
+            }),
+        )
+    }
+
     pub fn has_update(&self) -> Status {
-        if self.error.is_some() {
-            Status::Unknown(self.error.clone().unwrap())
+        if self.deferred {
+            Status::Deferred
+        } else if let Some(error) = &self.error {
+            Status::Unknown(error.to_string())
         } else {
             match &self.version_info {
                 Some(data) => data
@@ -132,9 +210,11 @@ impl Image {
                     .to_status(&data.current_tag),
                 None => match &self.digest_info {
                     Some(data) => {
+                        // Manifest lists change whenever any platform's image does, but only our platform's matters
                         if data
                             .local_digests
                             .contains(data.remote_digest.as_ref().unwrap())
+                            || data.platform_updated == Some(false)
                         {
                             Status::UpToDate
                         } else {
@@ -162,7 +242,7 @@ impl Image {
             result: UpdateResult {
                 has_update: has_update.to_option_bool(),
                 info: match has_update {
-                    Status::Unknown(_) => UpdateInfo::None,
+                    Status::Unknown(_) | Status::Deferred => UpdateInfo::None,
                     _ => match update_type {
                         "version" => {
                             let (new_tag, format_str) = match &self.version_info {
@@ -181,10 +261,7 @@ impl Image {
                                     _ => unreachable!(),
                                 }
                                 .to_string(),
-                                new_tag: format_str
-                                    .replacen("{}", &new_tag.major.to_string(), 1)
-                                    .replacen("{}", &new_tag.minor.unwrap_or(0).to_string(), 1)
-                                    .replacen("{}", &new_tag.patch.unwrap_or(0).to_string(), 1),
+                                new_tag: new_tag.to_tag(&format_str),
                                 // Throwing these in, because they're useful for the CLI output, however we won't (de)serialize them
                                 current_version: self
                                     .version_info
@@ -200,47 +277,116 @@ impl Image {
                                     .as_ref()
                                     .unwrap()
                                     .to_string(),
+                                releases: self.version_info.as_ref().unwrap().releases.clone(),
+                                releases_url: self
+                                    .version_info
+                                    .as_ref()
+                                    .unwrap()
+                                    .releases_url
+                                    .clone(),
                             })
                         }
                         "digest" => {
-                            let (local_digests, remote_digest) = match &self.digest_info {
-                                Some(data) => {
-                                    (data.local_digests.clone(), data.remote_digest.clone())
-                                }
+                            let data = match &self.digest_info {
+                                Some(data) => data,
                                 _ => unreachable!(),
                             };
                             UpdateInfo::Digest(DigestUpdateInfo {
-                                local_digests,
-                                remote_digest,
+                                local_digests: data.local_digests.clone(),
+                                remote_digest: data.remote_digest.clone(),
+                                // The manifest list's name for the platform, which may include the variant
+                                platform: (!data.platforms.is_empty()).then(|| {
+                                    let platform =
+                                        data.platform.clone().unwrap_or_else(Platform::host);
+                                    data.platforms
+                                        .iter()
+                                        .find(|manifest| platform.matches(&manifest.platform))
+                                        .map_or(platform.to_string(), |manifest| {
+                                            manifest.platform.to_string()
+                                        })
+                                }),
+                                platform_updated: data.platform_updated,
+                                platforms: data
+                                    .platforms
+                                    .iter()
+                                    .map(|manifest| PlatformDigest {
+                                        platform: manifest.platform.to_string(),
+                                        digest: manifest.digest.clone(),
+                                    })
+                                    .collect(),
+                                details: data.remote_details.as_ref().map(|remote| {
+                                    UpdateDetails::new(data.local_details.as_ref(), remote)
+                                }),
                             })
                         }
                         "none" => UpdateInfo::None,
                         _ => unreachable!(),
                     },
                 },
-                error: self.error.clone(),
+                error: self.error.as_ref().map(Error::to_string),
+                error_code: self.error.as_ref().map(|error| error.code().to_string()),
+                deferred: self.deferred,
             },
             time: self.time_ms,
-            server: None,
+            server: self.host.clone(),
+            host: self.host.clone(),
             in_use: self.in_use,
+            compose: self.compose.clone(),
+            workloads: self.workloads.clone(),
+            acknowledgement: None,
             status: has_update,
         }
     }
 
-    /// Checks if the image has an update
+    /// Checks if the image has an update. `authorization` is the value of the Authorization header sent to the registry, if it needs one.
     pub async fn check(
         &self,
-        token: Option<&str>,
+        authorization: Option<&str>,
         ctx: &Context,
         client: &Client,
         excluded_tags: Vec<String>,
     ) -> Self {
+        // Images which already failed (e.g. because their registry couldn't be reached) can't be checked
+        if self.error.is_some() {
+            return self.clone();
+        }
         match &self.version_info {
             Some(data) => {
-                get_latest_tag(self, &data.current_tag, token, ctx, client, excluded_tags).await
+                // The tag was parsed before the config was known, so it has to be parsed again in case the image has a scheme or tag regex configured
+                let image = match ctx
+                    .config
+                    .images
+                    .parse_tag(&self.reference, &self.parts.tag)
+                {
+                    Some((current_tag, format_str)) => Self {
+                        version_info: Some(VersionInfo {
+                            current_tag,
+                            format_str,
+                            ..data.clone()
+                        }),
+                        ..self.clone()
+                    },
+                    None => {
+                        ctx.logger.warn(format!(
+                            "Tag of {} doesn't match its configured version scheme or tag regex",
+                            self.reference
+                        ));
+                        self.clone()
+                    }
+                };
+                let current_tag = &image.version_info.as_ref().unwrap().current_tag;
+                get_latest_tag(
+                    &image,
+                    current_tag,
+                    authorization,
+                    ctx,
+                    client,
+                    excluded_tags,
+                )
+                .await
             }
             None => match self.digest_info {
-                Some(_) => get_latest_digest(self, token, ctx, client).await,
+                Some(_) => get_latest_digest(self, authorization, ctx, client).await,
                 None => unreachable!(),
             },
         }
@@ -1,11 +1,30 @@
+#[cfg(feature = "docker")]
 use bollard::secret::{ImageInspect, ImageSummary};
 
+use super::platform::Platform;
+
 pub trait InspectData {
     fn tags(&self) -> Option<Vec<String>>;
     fn digests(&self) -> Option<Vec<String>>;
     fn url(&self) -> Option<String>;
+
+    /// The URL of the image's source code, from its `org.opencontainers.image.source` label
+    fn source(&self) -> Option<String> {
+        None
+    }
+
+    /// The image's ID, which is the digest of its config
+    fn id(&self) -> Option<String> {
+        None
+    }
+
+    /// The platform the image was built for
+    fn platform(&self) -> Option<Platform> {
+        None
+    }
 }
 
+#[cfg(feature = "docker")]
 impl InspectData for ImageInspect {
     fn tags(&self) -> Option<Vec<String>> {
         self.repo_tags.clone()
@@ -24,8 +43,30 @@ impl InspectData for ImageInspect {
             None => None,
         }
     }
+
+    fn source(&self) -> Option<String> {
+        self.config
+            .as_ref()?
+            .labels
+            .as_ref()?
+            .get("org.opencontainers.image.source")
+            .cloned()
+    }
+
+    fn id(&self) -> Option<String> {
+        self.id.clone()
+    }
+
+    fn platform(&self) -> Option<Platform> {
+        Some(Platform::new(
+            self.os.as_deref()?,
+            self.architecture.as_deref()?,
+            self.variant.as_deref(),
+        ))
+    }
 }
 
+#[cfg(feature = "docker")]
 impl InspectData for ImageSummary {
     fn tags(&self) -> Option<Vec<String>> {
         Some(self.repo_tags.clone())
@@ -38,6 +79,14 @@ impl InspectData for ImageSummary {
     fn url(&self) -> Option<String> {
         self.labels.get("org.opencontainers.image.url").cloned()
     }
+
+    fn source(&self) -> Option<String> {
+        self.labels.get("org.opencontainers.image.source").cloned()
+    }
+
+    fn id(&self) -> Option<String> {
+        Some(self.id.clone())
+    }
 }
 
 impl InspectData for &String {
@@ -1,6 +1,13 @@
+pub mod acknowledgement;
+pub mod compose;
+pub mod details;
 pub mod image;
 pub mod inspectdata;
 pub mod parts;
+pub mod platform;
+pub mod release;
+pub mod scheme;
 pub mod status;
 pub mod update;
 pub mod version;
+pub mod workload;
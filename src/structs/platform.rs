@@ -0,0 +1,57 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// The platform an image is built for, as listed in manifest lists
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Platform {
+    pub os: String,
+    pub architecture: String,
+    #[serde(default)]
+    pub variant: Option<String>,
+}
+
+impl Platform {
+    pub fn new(os: &str, architecture: &str, variant: Option<&str>) -> Self {
+        Self {
+            os: os.to_string(),
+            architecture: architecture.to_string(),
+            variant: variant
+                .filter(|variant| !variant.is_empty())
+                .map(str::to_string),
+        }
+    }
+
+    /// The platform Cup is running on. Local images are assumed to be for it if the runtime doesn't say otherwise. Containers run on Linux even on other operating systems (in a VM), so the OS is always `linux`.
+    pub fn host() -> Self {
+        let (architecture, variant) = match std::env::consts::ARCH {
+            "x86_64" => ("amd64", None),
+            "x86" => ("386", None),
+            "aarch64" => ("arm64", None),
+            "arm" => ("arm", Some("v7")),
+            "powerpc64" => ("ppc64le", None),
+            architecture => (architecture, None),
+        };
+        Self::new("linux", architecture, variant)
+    }
+
+    /// Whether an image built for `other` is the one used on this platform. Variants are only compared if both are known, since runtimes often omit them (e.g. `v8` for `arm64`).
+    pub fn matches(&self, other: &Platform) -> bool {
+        self.os == other.os
+            && self.architecture == other.architecture
+            && match (&self.variant, &other.variant) {
+                (Some(variant), Some(other_variant)) => variant == other_variant,
+                _ => true,
+            }
+    }
+}
+
+impl Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{}", variant)?;
+        }
+        Ok(())
+    }
+}
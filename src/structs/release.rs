@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+/// A release published on the forge hosting an image's source, between the local version and the one it can be updated to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Release {
+    pub tag: String,
+    pub title: String,
+    pub url: String,
+    /// Whether the release starts a new major version or its notes mention breaking changes
+    pub breaking: bool,
+}
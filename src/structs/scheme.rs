@@ -0,0 +1,135 @@
+use super::status::Status;
+
+/// How the numbers in a tag are interpreted, which determines how versions are displayed and what kind of update a newer one is
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub enum Scheme {
+    /// Major, minor and patch numbers (e.g. `1.2.3`)
+    #[default]
+    Semver,
+    /// Calendar versioning (e.g. `2024.05.1` or `24.04`), with the meaning of each number
+    Calver(Vec<CalverComponent>),
+    /// A date and possibly a time (e.g. `20241017` or `2024-10-13T13-34-11`). The layout is the timestamp with `#` in place of each digit.
+    Timestamp(String),
+    /// A number that grows with every build (e.g. `build-1234`)
+    Build,
+}
+
+/// A part of a calendar versioning pattern, as described at https://calver.org
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CalverComponent {
+    /// `YYYY`
+    FullYear,
+    /// `YY`
+    ShortYear,
+    /// `0Y`
+    PaddedYear,
+    /// `MM`
+    Month,
+    /// `0M`
+    PaddedMonth,
+    /// `WW`
+    Week,
+    /// `0W`
+    PaddedWeek,
+    /// `DD`
+    Day,
+    /// `0D`
+    PaddedDay,
+    /// `MAJOR`
+    Major,
+    /// `MINOR`
+    Minor,
+    /// `MICRO`
+    Micro,
+}
+
+impl CalverComponent {
+    /// Parses a pattern like `YYYY.0M.MICRO`. Separators don't matter, since the tag's format is kept anyway.
+    pub fn parse_pattern(pattern: &str) -> Result<Vec<Self>, String> {
+        let components = pattern
+            .split(['.', '-', '_'])
+            .map(|token| match token {
+                "YYYY" => Ok(Self::FullYear),
+                "YY" => Ok(Self::ShortYear),
+                "0Y" => Ok(Self::PaddedYear),
+                "MM" => Ok(Self::Month),
+                "0M" => Ok(Self::PaddedMonth),
+                "WW" => Ok(Self::Week),
+                "0W" => Ok(Self::PaddedWeek),
+                "DD" => Ok(Self::Day),
+                "0D" => Ok(Self::PaddedDay),
+                "MAJOR" => Ok(Self::Major),
+                "MINOR" => Ok(Self::Minor),
+                "MICRO" => Ok(Self::Micro),
+                _ => Err(format!(
+                    "Unknown component {} in calendar versioning pattern {}",
+                    token, pattern
+                )),
+            })
+            .collect::<Result<Vec<Self>, String>>()?;
+        if components.len() > 3 {
+            return Err(format!(
+                "Calendar versioning pattern {} has more than 3 components",
+                pattern
+            ));
+        }
+        Ok(components)
+    }
+
+    /// Guesses the pattern of a tag's numbers: a year, a month and a day or counter
+    pub fn detect(numbers: &[&str]) -> Vec<Self> {
+        let padded = |number: &&str| number.len() > 1 && number.starts_with('0');
+        numbers
+            .iter()
+            .enumerate()
+            .map(|(index, number)| match index {
+                0 if number.len() == 4 => Self::FullYear,
+                0 if padded(number) => Self::PaddedYear,
+                0 => Self::ShortYear,
+                1 if padded(number) => Self::PaddedMonth,
+                1 => Self::Month,
+                _ if padded(number) => Self::PaddedDay,
+                _ => Self::Micro,
+            })
+            .collect()
+    }
+
+    /// The number of digits the component is padded to with zeros
+    pub fn width(&self) -> usize {
+        match self {
+            Self::PaddedYear | Self::PaddedMonth | Self::PaddedWeek | Self::PaddedDay => 2,
+            _ => 0,
+        }
+    }
+
+    /// The kind of update a change in this component is. Years are like majors, months and weeks like minors and days like patches.
+    pub fn status(&self) -> Status {
+        match self {
+            Self::FullYear | Self::ShortYear | Self::PaddedYear | Self::Major => {
+                Status::UpdateMajor
+            }
+            Self::Month | Self::PaddedMonth | Self::Week | Self::PaddedWeek | Self::Minor => {
+                Status::UpdateMinor
+            }
+            Self::Day | Self::PaddedDay | Self::Micro => Status::UpdatePatch,
+        }
+    }
+}
+
+impl Scheme {
+    /// Guesses the scheme of a tag from its numbers. Versions starting with a year or with zero-padded numbers use calendar versioning, while single large numbers are build numbers.
+    pub fn detect(numbers: &[&str]) -> Self {
+        let padded = numbers
+            .iter()
+            .any(|number| number.len() > 1 && number.starts_with('0'));
+        let year =
+            numbers[0].len() == 4 && (numbers[0].starts_with("19") || numbers[0].starts_with("20"));
+        if numbers.len() > 1 && (padded || year) {
+            Self::Calver(CalverComponent::detect(numbers))
+        } else if numbers.len() == 1 && numbers[0].len() >= 3 {
+            Self::Build
+        } else {
+            Self::Semver
+        }
+    }
+}
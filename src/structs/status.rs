@@ -2,12 +2,17 @@ use std::fmt::Display;
 
 /// Enum for image status
 #[derive(Ord, Eq, PartialEq, PartialOrd, Clone, Debug)]
+#[non_exhaustive]
 pub enum Status {
     UpdateMajor,
     UpdateMinor,
     UpdatePatch,
     UpdateAvailable,
+    /// An update the user decided to skip, or any update of a snoozed image
+    Acknowledged,
     UpToDate,
+    /// Not checked, because the registry's rate limit was nearly exhausted
+    Deferred,
     Unknown(String),
 }
 
@@ -19,6 +24,8 @@ impl Display for Status {
             Self::UpdateMajor => "Major update",
             Self::UpdateMinor => "Minor update",
             Self::UpdatePatch => "Patch update",
+            Self::Acknowledged => "Acknowledged",
+            Self::Deferred => "Deferred",
             Self::Unknown(_) => "Unknown",
         })
     }
@@ -28,8 +35,9 @@ impl Status {
     // Converts the Status into an Option<bool> (useful for JSON serialization)
     pub fn to_option_bool(&self) -> Option<bool> {
         match &self {
-            Self::UpToDate => Some(false),
-            Self::Unknown(_) => None,
+            // Acknowledged updates aren't reported as available
+            Self::UpToDate | Self::Acknowledged => Some(false),
+            Self::Unknown(_) | Self::Deferred => None,
             _ => Some(true),
         }
     }
@@ -1,9 +1,15 @@
 use serde::{ser::SerializeStruct, Deserialize, Serialize};
 
-use super::{parts::Parts, status::Status};
+use crate::{error::Error, utils::reference::split};
+
+use super::{
+    acknowledgement::Acknowledgement, compose::ComposeService, details::UpdateDetails,
+    parts::Parts, release::Release, status::Status, workload::Workload,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[cfg_attr(test, derive(PartialEq, Default))]
+#[non_exhaustive]
 pub struct Update {
     pub reference: String,
     pub parts: Parts,
@@ -11,17 +17,58 @@ pub struct Update {
     pub result: UpdateResult,
     pub time: u32,
     pub server: Option<String>,
+    /// The Docker host the image was found on, if it isn't the local one. `server` is set to the host's name too, so it's shown like a server's images.
+    #[serde(default)]
+    pub host: Option<String>,
     pub in_use: bool,
+    #[serde(default)] // Older servers don't send this
+    pub compose: Vec<ComposeService>,
+    #[serde(default)]
+    pub workloads: Vec<Workload>,
+    /// Set if the update was acknowledged or the image snoozed by the user
+    #[serde(default)]
+    pub acknowledgement: Option<Acknowledgement>,
     #[serde(skip_serializing, skip_deserializing)]
     pub status: Status,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[cfg_attr(test, derive(PartialEq, Default))]
+#[non_exhaustive]
 pub struct UpdateResult {
     pub has_update: Option<bool>,
     pub info: UpdateInfo,
     pub error: Option<String>,
+    /// A machine-readable code for the error, e.g. `not_found` or `unauthorized`
+    #[serde(default)]
+    pub error_code: Option<String>,
+    /// Whether the check was deferred to the next one, because the registry's rate limit was nearly exhausted
+    #[serde(default)]
+    pub deferred: bool,
+}
+
+impl UpdateResult {
+    /// Creates the result of a check that succeeded. `has_update` is `Some(false)` if the image is up to date.
+    pub fn new(has_update: bool, info: UpdateInfo) -> Self {
+        Self {
+            has_update: Some(has_update),
+            info,
+            error: None,
+            error_code: None,
+            deferred: false,
+        }
+    }
+
+    /// Creates the result of a check that failed with `error`
+    pub fn from_error(error: &Error) -> Self {
+        Self {
+            has_update: None,
+            info: UpdateInfo::None,
+            error: Some(error.to_string()),
+            error_code: Some(error.code().to_string()),
+            deferred: false,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -41,6 +88,12 @@ pub struct VersionUpdateInfo {
     pub new_tag: String,
     pub current_version: String,
     pub new_version: String,
+    /// The releases between the current and the new version, newest first
+    #[serde(default)]
+    pub releases: Vec<Release>,
+    /// The page listing all of the image's releases, if its source is known
+    #[serde(default)]
+    pub releases_url: Option<String>,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -48,6 +101,25 @@ pub struct VersionUpdateInfo {
 pub struct DigestUpdateInfo {
     pub local_digests: Vec<String>,
     pub remote_digest: Option<String>,
+    /// The local image's platform, if the remote digest is of a manifest list
+    #[serde(default)]
+    pub platform: Option<String>,
+    /// Whether the manifest for the local image's platform changed. `None` if the remote digest isn't of a manifest list or the local image's ID isn't known.
+    #[serde(default)]
+    pub platform_updated: Option<bool>,
+    /// The digest of each platform's manifest in the manifest list
+    #[serde(default)]
+    pub platforms: Vec<PlatformDigest>,
+    /// What changed between the local image and the remote one. `None` if the remote image couldn't be fetched.
+    #[serde(default)]
+    pub details: Option<UpdateDetails>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct PlatformDigest {
+    pub platform: String,
+    pub digest: String,
 }
 
 impl Serialize for VersionUpdateInfo {
@@ -55,12 +127,14 @@ impl Serialize for VersionUpdateInfo {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("VersionUpdateInfo", 5)?;
+        let mut state = serializer.serialize_struct("VersionUpdateInfo", 7)?;
         let _ = state.serialize_field("type", "version");
         let _ = state.serialize_field("version_update_type", &self.version_update_type);
         let _ = state.serialize_field("new_tag", &self.new_tag);
         let _ = state.serialize_field("current_version", &self.current_version);
         let _ = state.serialize_field("new_version", &self.new_version);
+        let _ = state.serialize_field("releases", &self.releases);
+        let _ = state.serialize_field("releases_url", &self.releases_url);
         state.end()
     }
 }
@@ -70,20 +144,60 @@ impl Serialize for DigestUpdateInfo {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("DigestUpdateInfo", 3)?;
+        let mut state = serializer.serialize_struct("DigestUpdateInfo", 7)?;
         let _ = state.serialize_field("type", "digest");
         let _ = state.serialize_field("local_digests", &self.local_digests);
         let _ = state.serialize_field("remote_digest", &self.remote_digest);
+        let _ = state.serialize_field("platform", &self.platform);
+        let _ = state.serialize_field("platform_updated", &self.platform_updated);
+        let _ = state.serialize_field("platforms", &self.platforms);
+        let _ = state.serialize_field("details", &self.details);
         state.end()
     }
 }
 
 impl Update {
+    /// Creates an update for `reference` with the given result. Everything else starts out empty and can be set afterwards.
+    pub fn new(reference: &str, result: UpdateResult) -> Self {
+        let (registry, repository, tag) = split(reference);
+        Self {
+            reference: reference.to_string(),
+            parts: Parts {
+                registry,
+                repository,
+                tag,
+            },
+            url: None,
+            result,
+            time: 0,
+            server: None,
+            host: None,
+            in_use: false,
+            compose: Vec::new(),
+            workloads: Vec::new(),
+            acknowledgement: None,
+            status: Status::default(),
+        }
+    }
+
+    /// Returns the tag or digest this image can be updated to, if an update is available
+    pub fn new_version(&self) -> Option<&str> {
+        match &self.result.info {
+            UpdateInfo::Version(info) => Some(&info.new_tag),
+            UpdateInfo::Digest(info) => info.remote_digest.as_deref(),
+            UpdateInfo::None => None,
+        }
+    }
+
     pub fn get_status(&self) -> Status {
         match &self.status {
             Status::Unknown(s) => {
                 if s.is_empty() {
+                    if self.result.deferred {
+                        return Status::Deferred;
+                    }
                     match self.result.has_update {
+                        Some(true) if self.acknowledgement.is_some() => Status::Acknowledged,
                         Some(true) => match &self.result.info {
                             UpdateInfo::Version(info) => match info.version_update_type.as_str() {
                                 "major" => Status::UpdateMajor,
@@ -104,4 +218,86 @@ impl Update {
             status => status.clone(),
         }
     }
+
+    /// Identifies the image, including the server it's on. `update_key` starts with it.
+    pub fn image_key(&self) -> String {
+        format!(
+            "{}\0{}\0",
+            self.server.as_deref().unwrap_or(""),
+            self.reference
+        )
+    }
+
+    /// Identifies the update that's available for the image, if there is one
+    pub fn update_key(&self) -> Option<String> {
+        self.new_version()
+            .map(|new_version| format!("{}{}", self.image_key(), new_version))
+    }
+
+    /// Whether the image couldn't be checked this time (e.g. because of an error or the rate limit), so its previous result still stands
+    pub fn is_unchecked(&self) -> bool {
+        self.result.has_update.is_none() || self.result.deferred
+    }
+
+    /// Whether the image was checked by another Cup server, rather than by this one (locally or on one of its hosts)
+    pub fn is_remote(&self) -> bool {
+        self.server.is_some() && self.host.is_none()
+    }
+
+    /// Sets (or clears) the update's acknowledgement and updates its status accordingly
+    pub fn set_acknowledgement(&mut self, acknowledgement: Option<Acknowledgement>) {
+        self.acknowledgement = acknowledgement;
+        self.status = Status::default();
+        self.status = self.get_status();
+    }
+}
+
+/// Updates for tests of code that works with check results
+#[cfg(test)]
+pub mod fixtures {
+    use super::*;
+
+    /// A major update from version 15 to `new_tag`
+    pub fn create_version_update(reference: &str, new_tag: &str) -> Update {
+        Update {
+            reference: reference.to_string(),
+            result: UpdateResult {
+                has_update: Some(true),
+                info: UpdateInfo::Version(VersionUpdateInfo {
+                    version_update_type: String::from("major"),
+                    new_tag: new_tag.to_string(),
+                    current_version: String::from("15"),
+                    new_version: new_tag.to_string(),
+                    releases: Vec::new(),
+                    releases_url: None,
+                }),
+                error: None,
+                error_code: None,
+                deferred: false,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// An update from the local digest `sha256:0` to `remote_digest`
+    pub fn create_digest_update(reference: &str, remote_digest: &str) -> Update {
+        Update {
+            reference: reference.to_string(),
+            result: UpdateResult {
+                has_update: Some(true),
+                info: UpdateInfo::Digest(DigestUpdateInfo {
+                    local_digests: vec![String::from("sha256:0")],
+                    remote_digest: Some(remote_digest.to_string()),
+                    platform: None,
+                    platform_updated: None,
+                    platforms: Vec::new(),
+                    details: None,
+                }),
+                error: None,
+                error_code: None,
+                deferred: false,
+            },
+            ..Default::default()
+        }
+    }
 }
@@ -3,25 +3,62 @@ use std::{cmp::Ordering, fmt::Display};
 use once_cell::sync::Lazy;
 use regex::Regex;
 
-use super::status::Status;
+use crate::config::VersionScheme;
+
+use super::{
+    scheme::{CalverComponent, Scheme},
+    status::Status,
+};
 
 /// Semver-like version struct
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, Eq, Clone)]
 pub struct Version {
-    pub major: u32,
-    pub minor: Option<u32>,
-    pub patch: Option<u32>,
+    pub major: u64,
+    pub minor: Option<u64>,
+    pub patch: Option<u64>,
+    /// Pre-release identifiers as they appear in the tag, including the separator before them (e.g. `-rc.1`)
+    pub pre_release: Option<String>,
+    /// Build metadata after a `+`. Like in semver, it's ignored when comparing versions.
+    pub build: Option<String>,
+    /// How the numbers are interpreted. Timestamps are stored in `major` as all of their digits.
+    pub scheme: Scheme,
+}
+
+/// A part of a pre-release. Numeric identifiers have lower precedence than alphanumeric ones, so the order of the variants matters.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Identifier {
+    Numeric(u64),
+    Alphanumeric(String),
 }
 
 impl Version {
-    /// Tries to parse the tag into semver-like parts. Returns a Version object and a string usable in format! with {} in the positions matches were found
+    /// Tries to parse the tag into semver-like parts, detecting its scheme. Returns a Version object and a string usable in format! with {} in the positions matches were found
     pub fn from_tag(tag: &str) -> Option<(Self, String)> {
-        /// Heavily modified version of the official semver regex based on common tagging schemes for container images. Sometimes it matches more than once, but we'll try to select the best match.
+        Self::parse(tag, None)
+    }
+
+    /// Like `from_tag`, but tags are only parsed as versions of the given scheme if one is set
+    pub fn parse(tag: &str, scheme: Option<&VersionScheme>) -> Option<(Self, String)> {
+        match scheme {
+            Some(VersionScheme::Timestamp) => Self::parse_timestamp(tag),
+            // A lone number could also be the date part of a timestamp
+            None => match Self::parse_numbers(tag, None) {
+                Some(version) if version.0.minor.is_some() => Some(version),
+                version => Self::parse_timestamp(tag).or(version),
+            },
+            scheme => Self::parse_numbers(tag, scheme),
+        }
+    }
+
+    fn parse_numbers(tag: &str, scheme: Option<&VersionScheme>) -> Option<(Self, String)> {
+        /// Heavily modified version of the official semver regex based on common tagging schemes for container images. Sometimes it matches more than once, but we'll try to select the best match. Numbers may be padded with zeros for calendar versioning.
         static VERSION_REGEX: Lazy<Regex> = Lazy::new(|| {
-            Regex::new(
-                r"(?P<major>0|[1-9][0-9]*)(?:\.(?P<minor>0|[1-9][0-9]*))?(?:\.(?P<patch>0|[1-9][0-9]*))?",
-            )
-            .unwrap()
+            Regex::new(r"(?P<major>[0-9]+)(?:\.(?P<minor>[0-9]+))?(?:\.(?P<patch>[0-9]+))?")
+                .unwrap()
+        });
+        /// Pre-releases directly following the version. Unlike semver, only common pre-release names count, since images use suffixes for variants too (e.g. `-alpine`).
+        static PRE_RELEASE_REGEX: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(r"^[-.]?(?i:alpha|beta|rc|preview|pre|dev|canary)(?:[.-]?[0-9]+)*").unwrap()
         });
         let captures = VERSION_REGEX.captures_iter(tag);
         // And now... terrible best match selection for everyone!
@@ -44,7 +81,7 @@ impl Version {
         match best_match {
             Some(c) => {
                 let mut positions = Vec::new();
-                let major: u32 = match c.name("major") {
+                let major: u64 = match c.name("major") {
                     Some(major) => {
                         positions.push((major.start(), major.end()));
                         match major.as_str().parse() {
@@ -54,21 +91,58 @@ impl Version {
                     }
                     None => return None,
                 };
-                let minor: Option<u32> = c.name("minor").map(|minor| {
+                let minor: Option<u64> = c.name("minor").map(|minor| {
                     positions.push((minor.start(), minor.end()));
                     minor
                         .as_str()
                         .parse()
                         .unwrap_or_else(|_| panic!("Minor version invalid in tag {}", tag))
                 });
-                let patch: Option<u32> = c.name("patch").map(|patch| {
+                let patch: Option<u64> = c.name("patch").map(|patch| {
                     positions.push((patch.start(), patch.end()));
                     patch
                         .as_str()
                         .parse()
                         .unwrap_or_else(|_| panic!("Patch version invalid in tag {}", tag))
                 });
+                let numbers: Vec<&str> = positions
+                    .iter()
+                    .map(|(start, end)| &tag[*start..*end])
+                    .collect();
+                let scheme = match scheme {
+                    None => Scheme::detect(&numbers),
+                    Some(VersionScheme::Semver) => Scheme::Semver,
+                    Some(VersionScheme::Calver(None)) => {
+                        Scheme::Calver(CalverComponent::detect(&numbers))
+                    }
+                    Some(VersionScheme::Calver(Some(pattern))) => {
+                        if pattern.len() != numbers.len() {
+                            return None;
+                        }
+                        Scheme::Calver(pattern.clone())
+                    }
+                    Some(VersionScheme::Build) if numbers.len() == 1 => Scheme::Build,
+                    Some(VersionScheme::Build | VersionScheme::Timestamp) => return None,
+                };
                 let mut format_str = tag.to_string();
+                // The pre-release and build metadata aren't part of the format, so pre-releases can be compared with releases
+                let end = positions.last().unwrap().1;
+                let pre_release = PRE_RELEASE_REGEX
+                    .find(&tag[end..])
+                    .filter(|pre_release| {
+                        tag[end + pre_release.end()..]
+                            .chars()
+                            .next()
+                            .is_none_or(|c| matches!(c, '-' | '.' | '_' | '+'))
+                    })
+                    .map(|pre_release| pre_release.as_str().to_string());
+                let build_start = end + pre_release.as_ref().map_or(0, String::len);
+                let build = tag[build_start..].strip_prefix('+').map(str::to_string);
+                let suffix_end = match &build {
+                    Some(_) => tag.len(),
+                    None => build_start,
+                };
+                format_str.replace_range(end..suffix_end, "");
                 positions.reverse();
                 positions.iter().for_each(|(start, end)| {
                     format_str.replace_range(*start..*end, "{}");
@@ -78,6 +152,9 @@ impl Version {
                         major,
                         minor,
                         patch,
+                        pre_release,
+                        build,
+                        scheme,
                     },
                     format_str,
                 ))
@@ -86,34 +163,267 @@ impl Version {
         }
     }
 
+    /// Parses a tag with a regex whose `major`, `minor` and `patch` named groups capture the version's numbers and `pre_release` group its pre-release
+    pub fn from_regex(tag: &str, regex: &Regex) -> Option<(Self, String)> {
+        let captures = regex.captures(tag)?;
+        let mut positions = Vec::new();
+        let mut numbers = Vec::new();
+        for name in ["major", "minor", "patch"] {
+            match captures.name(name) {
+                Some(number) => {
+                    positions.push(number.range());
+                    numbers.push(number.as_str().parse::<u64>().ok()?);
+                }
+                None => break,
+            }
+        }
+        let pre_release = captures
+            .name("pre_release")
+            .filter(|pre_release| !pre_release.is_empty());
+        let scheme = Scheme::detect(
+            &positions
+                .iter()
+                .map(|range| &tag[range.clone()])
+                .collect::<Vec<&str>>(),
+        );
+        let mut format_str = tag.to_string();
+        let mut replacements: Vec<(std::ops::Range<usize>, &str)> =
+            positions.into_iter().map(|range| (range, "{}")).collect();
+        if let Some(pre_release) = pre_release {
+            replacements.push((pre_release.range(), ""));
+        }
+        replacements.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+        for (range, replacement) in replacements {
+            format_str.replace_range(range, replacement);
+        }
+        Some((
+            Version {
+                major: *numbers.first()?,
+                minor: numbers.get(1).copied(),
+                patch: numbers.get(2).copied(),
+                pre_release: pre_release.map(|pre_release| pre_release.as_str().to_string()),
+                build: None,
+                scheme,
+            },
+            format_str,
+        ))
+    }
+
+    /// Parses tags containing a date, optionally followed by a time, like `20241017` or `RELEASE.2024-10-13T13-34-11Z`
+    fn parse_timestamp(tag: &str) -> Option<(Self, String)> {
+        static TIMESTAMP_REGEX: Lazy<Regex> = Lazy::new(|| {
+            Regex::new(
+                r"(?:19|20)[0-9]{2}(?:-[01][0-9]-[0-3][0-9](?:T[0-9]{2}[-:]?[0-9]{2}[-:]?[0-9]{2})?|[01][0-9][0-3][0-9](?:T?[0-9]{6})?)",
+            )
+            .unwrap()
+        });
+        let timestamp = TIMESTAMP_REGEX.find_iter(tag).find(|timestamp| {
+            // Part of a longer number
+            !tag[..timestamp.start()].ends_with(|c: char| c.is_ascii_digit())
+                && !tag[timestamp.end()..].starts_with(|c: char| c.is_ascii_digit())
+        })?;
+        let digits: String = timestamp
+            .as_str()
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect();
+        let layout = timestamp
+            .as_str()
+            .chars()
+            .map(|c| if c.is_ascii_digit() { '#' } else { c })
+            .collect();
+        let mut format_str = tag.to_string();
+        format_str.replace_range(timestamp.range(), "{}");
+        Some((
+            Version {
+                major: digits.parse().ok()?,
+                minor: None,
+                patch: None,
+                pre_release: None,
+                build: None,
+                scheme: Scheme::Timestamp(layout),
+            },
+            format_str,
+        ))
+    }
+
+    /// The version's numbers
+    fn numbers(&self) -> Vec<u64> {
+        [Some(self.major), self.minor, self.patch]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// The version's numbers as they appear in a tag, padded with zeros where the scheme needs it
+    fn components(&self) -> Vec<String> {
+        match &self.scheme {
+            Scheme::Timestamp(layout) => {
+                let mut digits = format!(
+                    "{:0width$}",
+                    self.major,
+                    width = layout.matches('#').count()
+                )
+                .into_bytes()
+                .into_iter();
+                vec![layout
+                    .chars()
+                    .map(|c| match c {
+                        '#' => digits.next().map_or('0', char::from),
+                        c => c,
+                    })
+                    .collect()]
+            }
+            Scheme::Calver(pattern) => self
+                .numbers()
+                .into_iter()
+                .enumerate()
+                .map(|(index, number)| {
+                    let width = pattern.get(index).map_or(0, |component| component.width());
+                    format!("{:0width$}", number, width = width)
+                })
+                .collect(),
+            Scheme::Semver | Scheme::Build => self
+                .numbers()
+                .into_iter()
+                .map(|number| number.to_string())
+                .collect(),
+        }
+    }
+
+    /// Returns the kind of update from `base` to this version, according to the scheme of `base`
     pub fn to_status(&self, base: &Self) -> Status {
-        match self.major.cmp(&base.major) {
-            Ordering::Greater => Status::UpdateMajor,
-            Ordering::Equal => match (self.minor, base.minor) {
-                (Some(a_minor), Some(b_minor)) => match a_minor.cmp(&b_minor) {
-                    Ordering::Greater => Status::UpdateMinor,
-                    Ordering::Equal => match (self.patch, base.patch) {
-                        (Some(a_patch), Some(b_patch)) => match a_patch.cmp(&b_patch) {
-                            Ordering::Greater => Status::UpdatePatch,
-                            Ordering::Equal => Status::UpToDate,
-                            Ordering::Less => {
-                                Status::Unknown(format!("Tag {} does not exist", base))
-                            }
+        // Versions with the same numbers differ by their pre-release. Moving to a newer one (or from a pre-release to the release) is reported like a patch.
+        let pre_release_status = || match compare_pre_releases(&self.pre_release, &base.pre_release)
+        {
+            Ordering::Greater => Status::UpdatePatch,
+            Ordering::Equal => Status::UpToDate,
+            Ordering::Less => Status::Unknown(format!("Tag {} does not exist", base)),
+        };
+        match &base.scheme {
+            // Calendar versions are classified by the meaning of the first number that changed
+            Scheme::Calver(pattern) => {
+                let changed = self
+                    .numbers()
+                    .into_iter()
+                    .zip(base.numbers())
+                    .enumerate()
+                    .find(|(_, (new, old))| new != old);
+                match changed {
+                    Some((index, (new, old))) if new > old => pattern
+                        .get(index)
+                        .map_or(Status::UpdatePatch, |component| component.status()),
+                    Some(_) => Status::Unknown(format!("Tag {} does not exist", base)),
+                    None => pre_release_status(),
+                }
+            }
+            // Timestamps and build numbers don't tell what changed, so any newer version is reported like the smallest update
+            Scheme::Timestamp(_) | Scheme::Build => match self.cmp(base) {
+                Ordering::Greater => Status::UpdatePatch,
+                Ordering::Equal => Status::UpToDate,
+                Ordering::Less => Status::Unknown(format!("Tag {} does not exist", base)),
+            },
+            Scheme::Semver => match self.major.cmp(&base.major) {
+                Ordering::Greater => Status::UpdateMajor,
+                Ordering::Equal => match (self.minor, base.minor) {
+                    (Some(a_minor), Some(b_minor)) => match a_minor.cmp(&b_minor) {
+                        Ordering::Greater => Status::UpdateMinor,
+                        Ordering::Equal => match (self.patch, base.patch) {
+                            (Some(a_patch), Some(b_patch)) => match a_patch.cmp(&b_patch) {
+                                Ordering::Greater => Status::UpdatePatch,
+                                Ordering::Equal => pre_release_status(),
+                                Ordering::Less => {
+                                    Status::Unknown(format!("Tag {} does not exist", base))
+                                }
+                            },
+                            (None, None) => pre_release_status(),
+                            _ => unreachable!(),
                         },
-                        (None, None) => Status::UpToDate,
-                        _ => unreachable!(),
+                        Ordering::Less => Status::Unknown(format!("Tag {} does not exist", base)),
                     },
-                    Ordering::Less => Status::Unknown(format!("Tag {} does not exist", base)),
+                    (None, None) => pre_release_status(),
+                    _ => unreachable!(
+                        "Version error: {} and {} should either both be Some or None",
+                        self, base
+                    ),
                 },
-                (None, None) => Status::UpToDate,
-                _ => unreachable!(
-                    "Version error: {} and {} should either both be Some or None",
-                    self, base
-                ),
+                Ordering::Less => Status::Unknown(format!("Tag {} does not exist", base)),
             },
-            Ordering::Less => Status::Unknown(format!("Tag {} does not exist", base)),
         }
     }
+
+    /// The name of the pre-release channel the version belongs to (e.g. `beta` for `1.0.0-beta.2`), in lowercase. `None` for releases.
+    pub fn channel(&self) -> Option<String> {
+        identifiers(self.pre_release.as_deref()?)
+            .into_iter()
+            .find_map(|identifier| match identifier {
+                Identifier::Alphanumeric(name) => Some(name),
+                Identifier::Numeric(_) => None,
+            })
+    }
+
+    /// Builds the tag for this version from the format string of a tag with the same scheme
+    pub fn to_tag(&self, format_str: &str) -> String {
+        let mut tag = String::new();
+        let mut rest = format_str;
+        for component in self.components() {
+            if let Some((before, after)) = rest.split_once("{}") {
+                tag.push_str(before);
+                tag.push_str(&component);
+                rest = after;
+            }
+        }
+        if let Some(pre_release) = &self.pre_release {
+            tag.push_str(pre_release);
+        }
+        if let Some(build) = &self.build {
+            tag.push('+');
+            tag.push_str(build);
+        }
+        tag.push_str(rest);
+        tag
+    }
+}
+
+/// Splits a pre-release into identifiers at separators and where letters meet digits (e.g. `rc1` is `rc` and `1`)
+fn identifiers(pre_release: &str) -> Vec<Identifier> {
+    let mut identifiers = Vec::new();
+    let mut current = String::new();
+    let mut push = |current: &mut String| {
+        if !current.is_empty() {
+            identifiers.push(match current.parse() {
+                Ok(number) => Identifier::Numeric(number),
+                Err(_) => Identifier::Alphanumeric(current.to_lowercase()),
+            });
+            current.clear();
+        }
+    };
+    for c in pre_release.chars() {
+        if !c.is_ascii_alphanumeric() {
+            push(&mut current);
+            continue;
+        }
+        if current
+            .chars()
+            .last()
+            .is_some_and(|last| last.is_ascii_digit() != c.is_ascii_digit())
+        {
+            push(&mut current);
+        }
+        current.push(c);
+    }
+    push(&mut current);
+    identifiers
+}
+
+/// Orders pre-releases like semver: a release is newer than its pre-releases, which are compared identifier by identifier
+fn compare_pre_releases(a: &Option<String>, b: &Option<String>) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => identifiers(a).cmp(&identifiers(b)),
+    }
 }
 
 impl Ord for Version {
@@ -135,6 +445,17 @@ impl Ord for Version {
             },
             _ => major_ordering,
         }
+        .then_with(|| compare_pre_releases(&self.pre_release, &other.pre_release))
+    }
+}
+
+/// Versions are equal if they have the same numbers and pre-release, even if their schemes were detected differently (e.g. `24.04` and `24.10`). Like ordering, this ignores build metadata.
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.major == other.major
+            && self.minor == other.minor
+            && self.patch == other.patch
+            && self.pre_release == other.pre_release
     }
 }
 
@@ -146,18 +467,14 @@ impl PartialOrd for Version {
 
 impl Display for Version {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&format!(
-            "{}{}{}",
-            self.major,
-            match self.minor {
-                Some(minor) => format!(".{}", minor),
-                None => String::new(),
-            },
-            match self.patch {
-                Some(patch) => format!(".{}", patch),
-                None => String::new(),
-            }
-        ))
+        f.write_str(&self.components().join("."))?;
+        if let Some(pre_release) = &self.pre_release {
+            write!(f, "-{}", pre_release.trim_start_matches(['-', '.']))?;
+        }
+        if let Some(build) = &self.build {
+            write!(f, "+{}", build)?;
+        }
+        Ok(())
     }
 }
 
@@ -168,21 +485,228 @@ mod tests {
     #[test]
     #[rustfmt::skip]
     fn version() {
-        assert_eq!(Version::from_tag("5.3.2"                   ), Some((Version { major: 5,  minor: Some(3),   patch: Some(2)  }, String::from("{}.{}.{}"                  ))));
-        assert_eq!(Version::from_tag("14"                      ), Some((Version { major: 14, minor: None,      patch: None     }, String::from("{}"                        ))));
-        assert_eq!(Version::from_tag("v0.107.53"               ), Some((Version { major: 0,  minor: Some(107), patch: Some(53) }, String::from("v{}.{}.{}"                 ))));
-        assert_eq!(Version::from_tag("12-alpine"               ), Some((Version { major: 12, minor: None,      patch: None     }, String::from("{}-alpine"                 ))));
-        assert_eq!(Version::from_tag("0.9.5-nginx"             ), Some((Version { major: 0,  minor: Some(9),   patch: Some(5)  }, String::from("{}.{}.{}-nginx"            ))));
-        assert_eq!(Version::from_tag("v27.0"                   ), Some((Version { major: 27, minor: Some(0),   patch: None     }, String::from("v{}.{}"                    ))));
-        assert_eq!(Version::from_tag("16.1"                    ), Some((Version { major: 16, minor: Some(1),   patch: None     }, String::from("{}.{}"                     ))));
-        assert_eq!(Version::from_tag("version-1.5.6"           ), Some((Version { major: 1,  minor: Some(5),   patch: Some(6)  }, String::from("version-{}.{}.{}"          ))));
-        assert_eq!(Version::from_tag("15.4-alpine"             ), Some((Version { major: 15, minor: Some(4),   patch: None     }, String::from("{}.{}-alpine"              ))));
-        assert_eq!(Version::from_tag("pg14-v0.2.0"             ), Some((Version { major: 0,  minor: Some(2),   patch: Some(0)  }, String::from("pg14-v{}.{}.{}"            ))));
-        assert_eq!(Version::from_tag("18-jammy-full.s6-v0.88.0"), Some((Version { major: 0,  minor: Some(88),  patch: Some(0)  }, String::from("18-jammy-full.s6-v{}.{}.{}"))));
-        assert_eq!(Version::from_tag("fpm-2.1.0-prod"          ), Some((Version { major: 2,  minor: Some(1),   patch: Some(0)  }, String::from("fpm-{}.{}.{}-prod"         ))));
-        assert_eq!(Version::from_tag("7.3.3.50"                ), Some((Version { major: 7,  minor: Some(3),   patch: Some(3)  }, String::from("{}.{}.{}.50"               ))));
-        assert_eq!(Version::from_tag("1.21.11-0"               ), Some((Version { major: 1,  minor: Some(21),  patch: Some(11) }, String::from("{}.{}.{}-0"                ))));
-        assert_eq!(Version::from_tag("4.1.2.1-full"            ), Some((Version { major: 4,  minor: Some(1),   patch: Some(2)  }, String::from("{}.{}.{}.1-full"           ))));
-        assert_eq!(Version::from_tag("v4.0.3-ls215"            ), Some((Version { major: 4,  minor: Some(0),   patch: Some(3)  }, String::from("v{}.{}.{}-ls215"           ))));
+        assert_eq!(Version::from_tag("5.3.2"                   ), Some((Version { major: 5,  minor: Some(3),   patch: Some(2),  pre_release: None, build: None, scheme: Scheme::Semver }, String::from("{}.{}.{}"                  ))));
+        assert_eq!(Version::from_tag("14"                      ), Some((Version { major: 14, minor: None,      patch: None,     pre_release: None, build: None, scheme: Scheme::Semver }, String::from("{}"                        ))));
+        assert_eq!(Version::from_tag("v0.107.53"               ), Some((Version { major: 0,  minor: Some(107), patch: Some(53), pre_release: None, build: None, scheme: Scheme::Semver }, String::from("v{}.{}.{}"                 ))));
+        assert_eq!(Version::from_tag("12-alpine"               ), Some((Version { major: 12, minor: None,      patch: None,     pre_release: None, build: None, scheme: Scheme::Semver }, String::from("{}-alpine"                 ))));
+        assert_eq!(Version::from_tag("0.9.5-nginx"             ), Some((Version { major: 0,  minor: Some(9),   patch: Some(5),  pre_release: None, build: None, scheme: Scheme::Semver }, String::from("{}.{}.{}-nginx"            ))));
+        assert_eq!(Version::from_tag("v27.0"                   ), Some((Version { major: 27, minor: Some(0),   patch: None,     pre_release: None, build: None, scheme: Scheme::Semver }, String::from("v{}.{}"                    ))));
+        assert_eq!(Version::from_tag("16.1"                    ), Some((Version { major: 16, minor: Some(1),   patch: None,     pre_release: None, build: None, scheme: Scheme::Semver }, String::from("{}.{}"                     ))));
+        assert_eq!(Version::from_tag("version-1.5.6"           ), Some((Version { major: 1,  minor: Some(5),   patch: Some(6),  pre_release: None, build: None, scheme: Scheme::Semver }, String::from("version-{}.{}.{}"          ))));
+        assert_eq!(Version::from_tag("15.4-alpine"             ), Some((Version { major: 15, minor: Some(4),   patch: None,     pre_release: None, build: None, scheme: Scheme::Semver }, String::from("{}.{}-alpine"              ))));
+        assert_eq!(Version::from_tag("pg14-v0.2.0"             ), Some((Version { major: 0,  minor: Some(2),   patch: Some(0),  pre_release: None, build: None, scheme: Scheme::Semver }, String::from("pg14-v{}.{}.{}"            ))));
+        assert_eq!(Version::from_tag("18-jammy-full.s6-v0.88.0"), Some((Version { major: 0,  minor: Some(88),  patch: Some(0),  pre_release: None, build: None, scheme: Scheme::Semver }, String::from("18-jammy-full.s6-v{}.{}.{}"))));
+        assert_eq!(Version::from_tag("fpm-2.1.0-prod"          ), Some((Version { major: 2,  minor: Some(1),   patch: Some(0),  pre_release: None, build: None, scheme: Scheme::Semver }, String::from("fpm-{}.{}.{}-prod"         ))));
+        assert_eq!(Version::from_tag("7.3.3.50"                ), Some((Version { major: 7,  minor: Some(3),   patch: Some(3),  pre_release: None, build: None, scheme: Scheme::Semver }, String::from("{}.{}.{}.50"               ))));
+        assert_eq!(Version::from_tag("1.21.11-0"               ), Some((Version { major: 1,  minor: Some(21),  patch: Some(11), pre_release: None, build: None, scheme: Scheme::Semver }, String::from("{}.{}.{}-0"                ))));
+        assert_eq!(Version::from_tag("4.1.2.1-full"            ), Some((Version { major: 4,  minor: Some(1),   patch: Some(2),  pre_release: None, build: None, scheme: Scheme::Semver }, String::from("{}.{}.{}.1-full"           ))));
+        assert_eq!(Version::from_tag("v4.0.3-ls215"            ), Some((Version { major: 4,  minor: Some(0),   patch: Some(3),  pre_release: None, build: None, scheme: Scheme::Semver }, String::from("v{}.{}.{}-ls215"           ))));
+    }
+
+    /// Test that pre-releases are split from the format, so they can be compared with releases, and suffixes which aren't pre-releases are kept
+    #[test]
+    fn pre_releases() {
+        let parse = |tag: &str| {
+            let (version, format_str) = Version::from_tag(tag).unwrap();
+            (version.pre_release, version.build, format_str)
+        };
+        assert_eq!(
+            parse("1.2.0-rc1"),
+            (Some(String::from("-rc1")), None, String::from("{}.{}.{}"))
+        );
+        assert_eq!(
+            parse("v2.0.0-beta.2-alpine"),
+            (
+                Some(String::from("-beta.2")),
+                None,
+                String::from("v{}.{}.{}-alpine")
+            )
+        );
+        assert_eq!(
+            parse("3.1rc2"),
+            (Some(String::from("rc2")), None, String::from("{}.{}"))
+        );
+        assert_eq!(
+            parse("1.0.0-alpha+001"),
+            (
+                Some(String::from("-alpha")),
+                Some(String::from("001")),
+                String::from("{}.{}.{}")
+            )
+        );
+        assert_eq!(
+            parse("1.0.0-development"),
+            (None, None, String::from("{}.{}.{}-development"))
+        );
+        assert_eq!(
+            parse("15.4-alpine"),
+            (None, None, String::from("{}.{}-alpine"))
+        );
+
+        let version = |tag: &str| Version::from_tag(tag).unwrap().0;
+        let mut versions = [
+            "1.0.0",
+            "1.0.0-rc.11",
+            "1.0.0-beta.2",
+            "1.0.0-alpha",
+            "1.0.0-rc.1",
+            "1.0.0-alpha.1",
+            "0.9.0",
+        ]
+        .map(version);
+        versions.sort();
+        assert_eq!(
+            versions.map(|version| version.to_string()),
+            [
+                "0.9.0",
+                "1.0.0-alpha",
+                "1.0.0-alpha.1",
+                "1.0.0-beta.2",
+                "1.0.0-rc.1",
+                "1.0.0-rc.11",
+                "1.0.0"
+            ]
+        );
+        assert_eq!(
+            version("1.0.0-rc1"),
+            Version {
+                major: 1,
+                minor: Some(0),
+                patch: Some(0),
+                pre_release: Some(String::from("-rc1")),
+                build: None,
+                scheme: Scheme::Semver
+            }
+        );
+        assert_eq!(version("1.0.0+001"), version("1.0.0+002"));
+        assert_eq!(
+            version("1.0.0+001").cmp(&version("1.0.0+002")),
+            Ordering::Equal
+        );
+        assert_eq!(
+            version("1.0.0").to_status(&version("1.0.0-rc1")),
+            Status::UpdatePatch
+        );
+        assert_eq!(
+            version("1.0.0-rc2").to_status(&version("1.0.0-rc1")),
+            Status::UpdatePatch
+        );
+        assert_eq!(
+            version("1.1.0-beta").to_status(&version("1.0.0")),
+            Status::UpdateMinor
+        );
+        assert_eq!(
+            version("1.0.0-beta.3").channel(),
+            Some(String::from("beta"))
+        );
+        assert_eq!(version("1.0.0").channel(), None);
+        assert_eq!(
+            version("2.1.0-RC1").to_tag("v{}.{}.{}-alpine"),
+            "v2.1.0-RC1-alpine"
+        );
+    }
+
+    /// Test that calendar versions, timestamps and build numbers are detected, compared and formatted like in their tags
+    #[test]
+    fn schemes() {
+        let version = |tag: &str| Version::from_tag(tag).unwrap();
+        let scheme = |tag: &str| version(tag).0.scheme;
+        assert_eq!(
+            scheme("2024.05.1"),
+            Scheme::Calver(vec![
+                CalverComponent::FullYear,
+                CalverComponent::PaddedMonth,
+                CalverComponent::Micro
+            ])
+        );
+        assert_eq!(
+            scheme("24.04"),
+            Scheme::Calver(vec![
+                CalverComponent::ShortYear,
+                CalverComponent::PaddedMonth
+            ])
+        );
+        assert_eq!(
+            scheme("20241017"),
+            Scheme::Timestamp(String::from("########"))
+        );
+        assert_eq!(
+            scheme("RELEASE.2024-10-13T13-34-11Z"),
+            Scheme::Timestamp(String::from("####-##-##T##-##-##"))
+        );
+        assert_eq!(scheme("build-1234"), Scheme::Build);
+        assert_eq!(scheme("14"), Scheme::Semver);
+        assert_eq!(scheme("v1.2.3-20240101"), Scheme::Semver);
+
+        let (calver, format_str) = version("2024.05.1");
+        assert_eq!(format_str, "{}.{}.{}");
+        assert_eq!(calver.to_string(), "2024.05.1");
+        assert_eq!(
+            version("2024.10.3").0.to_status(&calver),
+            Status::UpdateMinor
+        );
+        assert_eq!(
+            version("2025.01.0").0.to_status(&calver),
+            Status::UpdateMajor
+        );
+        assert_eq!(
+            version("2024.05.2").0.to_status(&calver),
+            Status::UpdatePatch
+        );
+        assert_eq!(
+            version("24.10").0.to_status(&version("24.04").0),
+            Status::UpdateMinor
+        );
+
+        let (minio, format_str) = version("RELEASE.2024-10-13T13-34-11Z");
+        assert_eq!(format_str, "RELEASE.{}Z");
+        let newer = version("RELEASE.2024-10-29T16-01-48Z").0;
+        assert!(newer > minio);
+        assert_eq!(newer.to_status(&minio), Status::UpdatePatch);
+        assert_eq!(newer.to_tag(&format_str), "RELEASE.2024-10-29T16-01-48Z");
+        assert_eq!(
+            version("20241101").0.to_status(&version("20241017").0),
+            Status::UpdatePatch
+        );
+
+        let patterned = |tag: &str, pattern: &str| {
+            let scheme = VersionScheme::try_from(pattern.to_string()).unwrap();
+            Version::parse(tag, Some(&scheme)).map(|(version, _)| version)
+        };
+        let base = patterned("2024.1.5", "YYYY.MINOR.MICRO").unwrap();
+        assert_eq!(
+            patterned("2025.1.5", "YYYY.MINOR.MICRO")
+                .unwrap()
+                .to_status(&base),
+            Status::UpdateMajor
+        );
+        assert_eq!(
+            patterned("2024.2.0", "YYYY.MINOR.MICRO")
+                .unwrap()
+                .to_status(&base),
+            Status::UpdateMinor
+        );
+        assert_eq!(patterned("2024.1", "YYYY.MINOR.MICRO"), None);
+        assert_eq!(patterned("1234", "build").unwrap().scheme, Scheme::Build);
+        assert_eq!(patterned("v1.2.3", "timestamp"), None);
+        assert!(VersionScheme::try_from(String::from("YYYY.MONTH")).is_err());
+    }
+
+    /// Test that tag regexes define the version and format of tags
+    #[test]
+    fn from_regex() {
+        let regex = Regex::new(
+            r"^r(?P<major>[0-9]+)_(?P<minor>[0-9]+)(?P<pre_release>-beta[0-9]*)?-(?:slim|full)$",
+        )
+        .unwrap();
+        let (version, format_str) = Version::from_regex("r12_4-beta2-slim", &regex).unwrap();
+        assert_eq!(format_str, "r{}_{}-slim");
+        assert_eq!(version.to_string(), "12.4-beta2");
+        assert_eq!(version.to_tag(&format_str), "r12_4-beta2-slim");
+        assert_eq!(
+            Version::from_regex("r12_4-full", &regex).unwrap().1,
+            "r{}_{}-full"
+        );
+        assert_eq!(Version::from_regex("12.4", &regex), None);
     }
 }
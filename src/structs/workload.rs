@@ -0,0 +1,9 @@
+use serde::{Deserialize, Serialize};
+
+/// A Kubernetes workload (e.g. a Deployment) running an image
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Workload {
+    pub namespace: String,
+    pub kind: String,
+    pub name: String,
+}
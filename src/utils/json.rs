@@ -2,7 +2,10 @@
 
 use serde_json::{json, Map, Value};
 
-use crate::structs::{status::Status, update::Update};
+use crate::{
+    http::rate_limits,
+    structs::{status::Status, update::Update},
+};
 
 /// Helper function to get metrics used in JSON output
 pub fn get_metrics(updates: &[Update]) -> Value {
@@ -11,6 +14,7 @@ pub fn get_metrics(updates: &[Update]) -> Value {
     let mut minor_updates = 0;
     let mut patch_updates = 0;
     let mut other_updates = 0;
+    let mut acknowledged = 0;
     let mut unknown = 0;
     updates.iter().for_each(|image| {
         let has_update = image.get_status();
@@ -27,10 +31,15 @@ pub fn get_metrics(updates: &[Update]) -> Value {
             Status::UpdateAvailable => {
                 other_updates += 1;
             }
+            // Acknowledged updates aren't counted as available, so they don't show up in dashboards and alerts
+            Status::Acknowledged => {
+                acknowledged += 1;
+            }
             Status::UpToDate => {
                 up_to_date += 1;
             }
-            Status::Unknown(_) => {
+            // Deferred images are counted as unknown, since they weren't checked
+            Status::Unknown(_) | Status::Deferred => {
                 unknown += 1;
             }
         };
@@ -42,6 +51,7 @@ pub fn get_metrics(updates: &[Update]) -> Value {
         "minor_updates": minor_updates,
         "patch_updates": patch_updates,
         "other_updates": other_updates,
+        "acknowledged": acknowledged,
         "up_to_date": up_to_date,
         "unknown": unknown
     })
@@ -53,7 +63,7 @@ pub fn to_simple_json(updates: &[Update]) -> Value {
     updates.iter().for_each(|update| {
         let _ = update_map.insert(
             update.reference.clone(),
-            match update.result.has_update {
+            match update.get_status().to_option_bool() {
                 Some(has_update) => Value::Bool(has_update),
                 None => Value::Null,
             },
@@ -62,6 +72,7 @@ pub fn to_simple_json(updates: &[Update]) -> Value {
     let json_data: Value = json!({
         "metrics": get_metrics(updates),
         "images": updates,
+        "rate_limits": rate_limits(),
     });
     json_data
 }
@@ -71,5 +82,36 @@ pub fn to_full_json(updates: &[Update]) -> Value {
     json!({
         "metrics": get_metrics(updates),
         "images": updates.iter().map(|update| serde_json::to_value(update).unwrap()).collect::<Vec<Value>>(),
+        "projects": get_projects(updates),
+        "rate_limits": rate_limits(),
     })
 }
+
+/// Groups images by the Compose project and service using them. Projects are identified by their server and name, since different servers may have projects with the same name.
+pub fn get_projects(updates: &[Update]) -> Value {
+    let mut projects: Vec<Value> = Vec::new();
+    for update in updates {
+        for service in &update.compose {
+            let index = match projects.iter().position(|project| {
+                project["server"].as_str() == update.server.as_deref()
+                    && project["name"] == service.project.as_str()
+            }) {
+                Some(index) => index,
+                None => {
+                    projects.push(json!({
+                        "server": update.server,
+                        "name": service.project,
+                        "config_files": service.config_files,
+                        "services": {},
+                    }));
+                    projects.len() - 1
+                }
+            };
+            projects[index]["services"][&service.service] = update.reference.clone().into();
+        }
+    }
+    projects.sort_unstable_by(|a, b| {
+        (a["server"].as_str(), a["name"].as_str()).cmp(&(b["server"].as_str(), b["name"].as_str()))
+    });
+    Value::Array(projects)
+}
@@ -3,11 +3,14 @@ use std::str::FromStr;
 use http_link::parse_link_header;
 use reqwest::Url;
 
-use crate::error;
+use crate::error::Error;
 
-pub fn parse_link(link: &str, base: &str) -> String {
+pub fn parse_link(link: &str, base: &str) -> Result<String, Error> {
     match parse_link_header(link, &Url::from_str(base).unwrap()) {
-        Ok(l) => l[0].target.to_string(),
-        Err(e) => error!("Failed to parse link! {}", e),
+        Ok(l) => Ok(l[0].target.to_string()),
+        Err(e) => Err(Error::invalid_response(format!(
+            "Failed to parse link {}: {}",
+            link, e
+        ))),
     }
 }
@@ -3,4 +3,6 @@ pub mod link;
 pub mod reference;
 pub mod request;
 pub mod sort_update_vec;
+#[cfg(test)]
+pub mod test_server;
 pub mod time;
@@ -1,4 +1,4 @@
-const DEFAULT_REGISTRY: &str = "registry-1.docker.io";
+pub const DEFAULT_REGISTRY: &str = "registry-1.docker.io";
 
 /// Takes an image and splits it into registry, repository and tag, based on the reference.
 /// For example, `ghcr.io/sergi0g/cup:latest` becomes `['ghcr.io', 'sergi0g/cup', 'latest']`.
@@ -54,6 +54,18 @@ pub fn split(reference: &str) -> (String, String, String) {
     (registry.to_string(), repository, tag.to_string())
 }
 
+/// Replaces the tag of a reference (or adds one if it's missing). For example, `postgres:15` with `17` becomes `postgres:17`.
+pub fn with_tag(reference: &str, tag: &str) -> String {
+    let reference = reference.split('@').next().unwrap();
+    // The last component is the only one that can contain a tag, others may contain a port
+    let name_start = reference.rfind('/').map_or(0, |i| i + 1);
+    let name = match reference[name_start..].split_once(':') {
+        Some((name, _)) => name,
+        None => &reference[name_start..],
+    };
+    format!("{}{}:{}", &reference[..name_start], name, tag)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -75,4 +87,14 @@ mod tests {
         assert_eq!(split("docker.example.com:5000/examplerepo/alpine:latest"    ), (String::from("docker.example.com:5000"), String::from("examplerepo/alpine"            ), String::from("latest")));
         assert_eq!(split("portainer/portainer:latest"                           ), (String::from(DEFAULT_REGISTRY         ), String::from("portainer/portainer"           ), String::from("latest")));
     }
+
+    #[test]
+    fn tag_replacement() {
+        assert_eq!(with_tag("postgres:15", "17"), "postgres:17");
+        assert_eq!(with_tag("alpine", "3.21"), "alpine:3.21");
+        assert_eq!(
+            with_tag("localhost:5000/test/app:1.0@sha256:abc", "1.1"),
+            "localhost:5000/test/app:1.1"
+        );
+    }
 }
@@ -3,18 +3,28 @@ use reqwest::Response;
 use rustc_hash::FxHashMap;
 use serde_json::Value;
 
-use crate::{config::RegistryConfig, error};
+use crate::{config::RegistryConfig, error::Error};
 
-/// Parses the www-authenticate header the registry sends into a challenge URL
-pub fn parse_www_authenticate(www_auth: &str) -> String {
-    let challenges = parse_challenges(www_auth).unwrap();
+/// How a registry wants requests to be authenticated
+#[derive(Clone, Debug, PartialEq)]
+pub enum Challenge {
+    /// With a token from the URL of a token server
+    Bearer(String),
+    /// With the credentials themselves (e.g. Amazon ECR)
+    Basic,
+}
+
+/// Parses the www-authenticate header the registry sends into a challenge
+pub fn parse_www_authenticate(www_auth: &str) -> Result<Challenge, Error> {
+    let challenges = parse_challenges(www_auth).map_err(|e| {
+        Error::invalid_response(format!("Failed to parse challenge {}: {}", www_auth, e))
+    })?;
     if !challenges.is_empty() {
         let challenge = &challenges[0];
         if challenge.scheme == "Bearer" {
-            challenge
-                .params
-                .iter()
-                .fold(String::new(), |acc, (key, value)| {
+            Ok(Challenge::Bearer(challenge.params.iter().fold(
+                String::new(),
+                |acc, (key, value)| {
                     if *key == "realm" {
                         acc.to_owned() + value.as_escaped() + "?"
                     } else if value.unescaped_len() != 0 {
@@ -22,12 +32,19 @@ pub fn parse_www_authenticate(www_auth: &str) -> String {
                     } else {
                         acc
                     }
-                })
+                },
+            )))
+        } else if challenge.scheme == "Basic" {
+            Ok(Challenge::Basic)
         } else {
-            error!("Unsupported scheme {}", &challenge.scheme)
+            Err(Error::UnsupportedScheme {
+                scheme: challenge.scheme.to_string(),
+            })
         }
     } else {
-        error!("No challenge provided by the server");
+        Err(Error::invalid_response(
+            "No challenge provided by the server",
+        ))
     }
 }
 
@@ -47,24 +64,13 @@ pub fn get_protocol(
     }
 }
 
-pub fn to_bearer_string(token: &Option<&str>) -> Option<String> {
-    token.as_ref().map(|t| format!("Bearer {}", t))
-}
-
-pub async fn get_response_body(response: Response) -> String {
-    match response.text().await {
-        Ok(res) => res,
-        Err(e) => {
-            error!("Failed to parse registry response into string!\n{}", e)
-        }
-    }
+pub async fn get_response_body(response: Response) -> Result<String, Error> {
+    response.text().await.map_err(|e| {
+        Error::invalid_response(format!("Failed to parse response into string: {}", e))
+    })
 }
 
-pub fn parse_json(body: &str) -> Value {
-    match serde_json::from_str(body) {
-        Ok(parsed) => parsed,
-        Err(e) => {
-            error!("Failed to parse server response\n{}", e)
-        }
-    }
+pub fn parse_json(body: &str) -> Result<Value, Error> {
+    serde_json::from_str(body)
+        .map_err(|e| Error::invalid_response(format!("Failed to parse JSON: {}", e)))
 }
@@ -83,6 +83,8 @@ mod tests {
                 has_update: None,
                 info: Default::default(),
                 error: Some("Error".to_string()),
+                error_code: None,
+                deferred: false,
             },
             ..Default::default()
         }
@@ -0,0 +1,135 @@
+// A tiny HTTP server for tests that need something to talk to. It only understands what our client sends.
+
+use std::{path::Path, sync::Arc};
+
+use rustc_hash::FxHashMap;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, UnixListener},
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+};
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: FxHashMap<String, String>,
+    pub body: String,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// Starts a server on a random local port which answers every request with whatever `handler` returns. Returns the server's base URL and a channel receiving every request made.
+pub async fn serve<F>(handler: F) -> (String, UnboundedReceiver<Request>)
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let (sender, receiver) = unbounded_channel();
+    let handler = Arc::new(handler);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(respond(stream, handler.clone(), sender.clone()));
+        }
+    });
+    (format!("http://{}", address), receiver)
+}
+
+/// Like `serve`, but listens on a Unix socket at `path` (e.g. to stand in for a container runtime)
+pub async fn serve_unix<F>(path: &Path, handler: F) -> UnboundedReceiver<Request>
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let _ = std::fs::remove_file(path);
+    let listener = UnixListener::bind(path).unwrap();
+    let (sender, receiver) = unbounded_channel();
+    let handler = Arc::new(handler);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(respond(stream, handler.clone(), sender.clone()));
+        }
+    });
+    receiver
+}
+
+/// Answers the request sent over `stream` and closes the connection
+async fn respond<S, F>(mut stream: S, handler: Arc<F>, sender: UnboundedSender<Request>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: Fn(&Request) -> Response,
+{
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 4096];
+    // Read until we have the headers and the whole body
+    let request = loop {
+        let read = stream.read(&mut chunk).await.unwrap_or(0);
+        if read == 0 {
+            return;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(request) = parse_request(&buffer) {
+            break request;
+        }
+    };
+    let response = handler(&request);
+    let mut raw = format!(
+        "HTTP/1.1 {} Test\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        raw.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    raw.push_str("\r\n");
+    if request.method != "HEAD" {
+        raw.push_str(&response.body);
+    }
+    let _ = sender.send(request);
+    let _ = stream.write_all(raw.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+fn parse_request(buffer: &[u8]) -> Option<Request> {
+    let text = String::from_utf8_lossy(buffer);
+    let (head, body) = text.split_once("\r\n\r\n")?;
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let headers: FxHashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.to_lowercase(), value.trim().to_string()))
+        .collect();
+    let length = headers
+        .get("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    if body.len() < length {
+        return None;
+    }
+    Some(Request {
+        method,
+        path,
+        headers,
+        body: body.to_string(),
+    })
+}
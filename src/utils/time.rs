@@ -1,6 +1,6 @@
 // When you're too bored to type some things, you get this...
 
-use std::time::SystemTime;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn elapsed(start: SystemTime) -> u32 {
     start.elapsed().unwrap().as_millis() as u32
@@ -9,3 +9,11 @@ pub fn elapsed(start: SystemTime) -> u32 {
 pub fn now() -> SystemTime {
     SystemTime::now()
 }
+
+/// Returns the current Unix timestamp in milliseconds
+pub fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
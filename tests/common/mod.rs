@@ -0,0 +1,553 @@
+// An in-process stand-in for an OCI distribution registry. It implements just enough of the spec to check images against: token and basic authentication, paginated tag lists, manifests (including manifest lists) and image configs, plus injectable failures.
+
+#![allow(dead_code)] // Each test file uses a different part of this
+
+use std::sync::{Arc, Mutex};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use cup::{
+    config::{RegistryConfig, RuntimeType},
+    logging::Logger,
+    structs::{
+        image::{DigestInfo, Image},
+        platform::Platform,
+    },
+    Config, Context,
+};
+use reqwest::Url;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// The media types of manifest lists. Registries only return a list if the client accepts one, otherwise they pick a manifest.
+const LIST_TYPES: [&str; 2] = [
+    "application/vnd.oci.image.index.v1+json",
+    "application/vnd.docker.distribution.manifest.list.v2+json",
+];
+const MANIFEST_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+
+/// The platforms (os, architecture, variant) of manifest lists
+const PLATFORMS: [(&str, &str, Option<&str>); 2] =
+    [("linux", "amd64", None), ("linux", "arm64", Some("v8"))];
+
+/// The first layer of every image, like a shared base image
+pub const BASE_LAYER: &str = "sha256:base";
+/// The compressed sizes of an image's layers: the base layer and its own
+pub const LAYER_SIZES: [u64; 2] = [30_000_000, 5_000];
+/// When every image was built
+pub const CREATED: &str = "2025-01-01T00:00:00Z";
+
+#[derive(Clone, Default)]
+pub enum Auth {
+    #[default]
+    Anonymous,
+    /// Anonymous tokens from the registry's `/token` endpoint, scoped to repositories
+    Bearer,
+    /// The base64 encoded `user:pass` the registry accepts
+    Basic(String),
+}
+
+#[derive(Clone)]
+struct Tag {
+    name: String,
+    digest: String,
+    /// Whether the tag points to a manifest list for `PLATFORMS`. The manifest for each platform has the digest `<digest>-<architecture>`. Clients which don't accept lists get the first one.
+    list: bool,
+}
+
+#[derive(Clone)]
+struct Failure {
+    path: String,
+    status: u16,
+    /// How many more requests fail. `None` means all of them.
+    remaining: Option<usize>,
+}
+
+#[derive(Default)]
+struct State {
+    auth: Auth,
+    repositories: Vec<(String, Vec<Tag>)>,
+    page_size: Option<usize>,
+    failures: Vec<Failure>,
+    /// Tokens handed out, with the repositories they grant access to
+    tokens: Vec<(String, Vec<String>)>,
+    /// `METHOD path` of every request received
+    requests: Vec<String>,
+}
+
+/// Configures a `FakeRegistry` before starting it
+#[derive(Default)]
+pub struct Builder {
+    state: State,
+}
+
+impl Builder {
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.state.auth = auth;
+        self
+    }
+
+    /// Adds a repository with tags pointing to single manifests. Each tag's digest is `sha256:<repository>:<tag>`.
+    pub fn repository(mut self, name: &str, tags: &[&str]) -> Self {
+        let tags = tags
+            .iter()
+            .map(|tag| Tag {
+                name: tag.to_string(),
+                digest: digest(name, tag),
+                list: false,
+            })
+            .collect();
+        self.state.repositories.push((name.to_string(), tags));
+        self
+    }
+
+    /// Makes a tag point to a manifest list with the given digest instead. See `platform_digest` and `config_digest` for the digests of what it points to.
+    pub fn manifest_list(mut self, repository: &str, tag: &str, digest: &str) -> Self {
+        let (_, tags) = self
+            .state
+            .repositories
+            .iter_mut()
+            .find(|(name, _)| name == repository)
+            .expect("Add the repository first");
+        let tag = tags.iter_mut().find(|t| t.name == tag).unwrap();
+        tag.digest = digest.to_string();
+        tag.list = true;
+        self
+    }
+
+    /// Splits tag lists into pages of this size, linked with `Link` headers
+    pub fn page_size(mut self, size: usize) -> Self {
+        self.state.page_size = Some(size);
+        self
+    }
+
+    /// Answers every request whose path starts with `path` with `status`
+    pub fn fail(mut self, path: &str, status: u16) -> Self {
+        self.state.failures.push(Failure {
+            path: path.to_string(),
+            status,
+            remaining: None,
+        });
+        self
+    }
+
+    /// Answers the next `times` requests whose path starts with `path` with `status`, then behaves normally
+    pub fn fail_times(mut self, path: &str, status: u16, times: usize) -> Self {
+        self.state.failures.push(Failure {
+            path: path.to_string(),
+            status,
+            remaining: Some(times),
+        });
+        self
+    }
+
+    pub async fn start(self) -> FakeRegistry {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = listener.local_addr().unwrap().to_string();
+        let url = format!("http://{}", host);
+        let state = Arc::new(Mutex::new(self.state));
+        let server_state = state.clone();
+        let server_url = url.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let state = server_state.clone();
+                let url = server_url.clone();
+                tokio::spawn(async move {
+                    let mut buffer = Vec::new();
+                    let mut chunk = [0; 4096];
+                    let request = loop {
+                        let read = stream.read(&mut chunk).await.unwrap_or(0);
+                        if read == 0 {
+                            return;
+                        }
+                        buffer.extend_from_slice(&chunk[..read]);
+                        if let Some(request) = Request::parse(&buffer) {
+                            break request;
+                        }
+                    };
+                    let response = state.lock().unwrap().handle(&request, &url);
+                    let mut raw = format!(
+                        "HTTP/1.1 {} Fake\r\nContent-Length: {}\r\nConnection: close\r\n",
+                        response.status,
+                        response.body.len()
+                    );
+                    for (name, value) in &response.headers {
+                        raw.push_str(&format!("{}: {}\r\n", name, value));
+                    }
+                    raw.push_str("\r\n");
+                    if request.method != "HEAD" {
+                        raw.push_str(&response.body);
+                    }
+                    let _ = stream.write_all(raw.as_bytes()).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        FakeRegistry { url, host, state }
+    }
+}
+
+pub struct FakeRegistry {
+    /// The base URL, e.g. `http://127.0.0.1:12345`
+    pub url: String,
+    /// The registry part of image references, e.g. `127.0.0.1:12345`
+    pub host: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeRegistry {
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Returns `METHOD path` of every request received so far
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Returns a full reference to an image in this registry
+    pub fn reference(&self, image: &str) -> String {
+        format!("{}/{}", self.host, image)
+    }
+
+    /// Returns a context for checking images in this registry without touching the local machine: no container runtime, no cache and the registry configured as insecure (since it speaks plain HTTP)
+    pub fn context(&self, registry_config: RegistryConfig) -> Context {
+        let mut config = Config::new();
+        config.runtime = RuntimeType::None;
+        config.cache.enabled = false;
+        // Keep acknowledgements out of the user's data directory
+        config.data_directory = Some(
+            std::env::temp_dir()
+                .join(format!("cup-tests-{}", std::process::id()))
+                .to_string_lossy()
+                .to_string(),
+        );
+        config.registries.insert(
+            self.host.clone(),
+            RegistryConfig {
+                insecure: true,
+                ..registry_config
+            },
+        );
+        Context::new(config, Logger::new(false, false))
+    }
+}
+
+/// The digest the fake registry reports for a tag added with `Builder::repository`
+pub fn digest(repository: &str, tag: &str) -> String {
+    format!("sha256:{}:{}", repository, tag)
+}
+
+/// The digest of the manifest for a platform in a manifest list
+pub fn platform_digest(list_digest: &str, architecture: &str) -> String {
+    format!("{}-{}", list_digest, architecture)
+}
+
+/// The digest of the config a manifest points to, which is the ID of the image once pulled
+pub fn config_digest(manifest_digest: &str) -> String {
+    format!("{}-config", manifest_digest)
+}
+
+/// The diff ID of the layer only the image a manifest describes has
+pub fn layer_digest(manifest_digest: &str) -> String {
+    format!("{}-layer", manifest_digest)
+}
+
+/// Encodes credentials the way they're configured in `cup.json`
+pub fn basic(user: &str, password: &str) -> String {
+    STANDARD.encode(format!("{}:{}", user, password))
+}
+
+/// Returns an image which is available locally with the given digest and ID, so it's checked by comparing digests
+pub fn local_image(
+    reference: &str,
+    local_digest: &str,
+    image_id: Option<&str>,
+    platform: Option<Platform>,
+) -> Image {
+    let mut image = Image::from_reference(reference);
+    image.version_info = None;
+    image.error = None; // Only images which aren't available locally need a version tag
+    image.digest_info = Some(DigestInfo {
+        local_digests: vec![local_digest.to_string()],
+        remote_digest: None,
+        image_id: image_id.map(str::to_string),
+        platform,
+        platforms: Vec::new(),
+        platform_updated: None,
+        local_details: None,
+        remote_details: None,
+    });
+    image
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn parse(buffer: &[u8]) -> Option<Self> {
+        let text = String::from_utf8_lossy(buffer);
+        let (head, _) = text.split_once("\r\n\r\n")?;
+        let mut lines = head.lines();
+        let mut request_line = lines.next()?.split(' ');
+        Some(Self {
+            method: request_line.next()?.to_string(),
+            path: request_line.next()?.to_string(),
+            headers: lines
+                .filter_map(|line| line.split_once(':'))
+                .map(|(name, value)| (name.to_lowercase(), value.trim().to_string()))
+                .collect(),
+        })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Response {
+    fn new(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// An error response in the format the distribution spec defines
+    fn error(status: u16, code: &str) -> Self {
+        Self::new(
+            status,
+            format!(
+                r#"{{"errors":[{{"code":"{}","message":"{}"}}]}}"#,
+                code, code
+            ),
+        )
+        .header("Content-Type", "application/json")
+    }
+}
+
+impl State {
+    fn handle(&mut self, request: &Request, base_url: &str) -> Response {
+        self.requests
+            .push(format!("{} {}", request.method, request.path));
+        let url = Url::parse(&format!("{}{}", base_url, request.path)).unwrap();
+
+        if let Some(failure) = self
+            .failures
+            .iter_mut()
+            .find(|failure| url.path().starts_with(&failure.path) && failure.remaining != Some(0))
+        {
+            if let Some(remaining) = &mut failure.remaining {
+                *remaining -= 1;
+            }
+            return Response::error(failure.status, "FAILURE");
+        }
+
+        if url.path() == "/token" {
+            return self.token(&url);
+        }
+        let Some(route) = url.path().strip_prefix("/v2/") else {
+            return Response::error(404, "NOT_FOUND");
+        };
+        let (repository, action) = if route.is_empty() {
+            (None, None)
+        } else if let Some((repository, _)) = route.split_once("/tags/list") {
+            (Some(repository), Some(Action::Tags))
+        } else if let Some((repository, reference)) = route.split_once("/manifests/") {
+            (Some(repository), Some(Action::Manifest(reference)))
+        } else if let Some((repository, digest)) = route.split_once("/blobs/") {
+            (Some(repository), Some(Action::Blob(digest)))
+        } else {
+            return Response::error(404, "NOT_FOUND");
+        };
+
+        if !self.authorized(request, repository) {
+            return match &self.auth {
+                Auth::Basic(_) => Response::error(401, "UNAUTHORIZED")
+                    .header("WWW-Authenticate", r#"Basic realm="fake""#),
+                _ => Response::error(401, "UNAUTHORIZED").header(
+                    "WWW-Authenticate",
+                    format!(r#"Bearer realm="{}/token",service="fake""#, base_url),
+                ),
+            };
+        }
+
+        let (Some(repository), Some(action)) = (repository, action) else {
+            return Response::new(200, "{}");
+        };
+        let Some((_, tags)) = self
+            .repositories
+            .iter()
+            .find(|(name, _)| name == repository)
+        else {
+            return Response::error(404, "NAME_UNKNOWN");
+        };
+        match action {
+            Action::Tags => Self::tags(repository, tags, &url, self.page_size),
+            Action::Manifest(reference) => {
+                // Manifests of a list's platforms are only referenced by digest
+                let platform_manifest = tags.iter().filter(|tag| tag.list).find_map(|tag| {
+                    PLATFORMS
+                        .iter()
+                        .map(|(_, architecture, _)| platform_digest(&tag.digest, architecture))
+                        .find(|digest| digest == reference)
+                });
+                if let Some(digest) = platform_manifest {
+                    return Self::manifest(&digest);
+                }
+                let Some(tag) = tags
+                    .iter()
+                    .find(|tag| tag.name == reference || (!tag.list && tag.digest == reference))
+                else {
+                    return Response::error(404, "MANIFEST_UNKNOWN");
+                };
+                let accepts_list = request
+                    .header("accept")
+                    .is_some_and(|accept| LIST_TYPES.iter().any(|t| accept.contains(t)));
+                if tag.list && accepts_list {
+                    let manifests: Vec<_> = PLATFORMS
+                        .iter()
+                        .map(|(os, architecture, variant)| {
+                            serde_json::json!({
+                                "mediaType": MANIFEST_TYPE,
+                                "digest": platform_digest(&tag.digest, architecture),
+                                "platform": { "os": os, "architecture": architecture, "variant": variant }
+                            })
+                        })
+                        .collect();
+                    let body = serde_json::json!({ "schemaVersion": 2, "mediaType": LIST_TYPES[0], "manifests": manifests });
+                    Response::new(200, body.to_string())
+                        .header("Content-Type", LIST_TYPES[0])
+                        .header("Docker-Content-Digest", &tag.digest)
+                } else if tag.list {
+                    Self::manifest(&platform_digest(&tag.digest, PLATFORMS[0].1))
+                } else {
+                    Self::manifest(&tag.digest)
+                }
+            }
+            // Only configs are requested, which are named after their manifest
+            Action::Blob(digest) => match digest.strip_suffix("-config") {
+                Some(manifest_digest) => Self::config(manifest_digest),
+                None => Response::error(404, "BLOB_UNKNOWN"),
+            },
+        }
+    }
+
+    /// A single platform's image manifest
+    fn manifest(digest: &str) -> Response {
+        let body = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": MANIFEST_TYPE,
+            "config": { "digest": config_digest(digest) },
+            "layers": [
+                { "digest": format!("{}-blob", BASE_LAYER), "size": LAYER_SIZES[0] },
+                { "digest": format!("{}-blob", layer_digest(digest)), "size": LAYER_SIZES[1] }
+            ]
+        });
+        Response::new(200, body.to_string())
+            .header("Content-Type", MANIFEST_TYPE)
+            .header("Docker-Content-Digest", digest)
+    }
+
+    /// The config of the image a manifest describes. Its revision label is the manifest's digest.
+    fn config(manifest_digest: &str) -> Response {
+        let body = serde_json::json!({
+            "created": CREATED,
+            "rootfs": { "type": "layers", "diff_ids": [BASE_LAYER, layer_digest(manifest_digest)] },
+            "config": { "Labels": { "org.opencontainers.image.revision": manifest_digest } }
+        });
+        Response::new(200, body.to_string()).header("Content-Type", "application/json")
+    }
+
+    fn authorized(&self, request: &Request, repository: Option<&str>) -> bool {
+        let authorization = request.header("authorization");
+        match &self.auth {
+            Auth::Anonymous => true,
+            Auth::Basic(credentials) => authorization == Some(&format!("Basic {}", credentials)),
+            Auth::Bearer => {
+                let Some(token) = authorization.and_then(|a| a.strip_prefix("Bearer ")) else {
+                    return false;
+                };
+                self.tokens.iter().any(|(issued, scope)| {
+                    issued == token
+                        && repository.is_none_or(|repository| scope.iter().any(|r| r == repository))
+                })
+            }
+        }
+    }
+
+    fn token(&mut self, url: &Url) -> Response {
+        let scope: Vec<String> = url
+            .query_pairs()
+            .filter(|(key, _)| key == "scope")
+            .filter_map(|(_, value)| {
+                value
+                    .strip_prefix("repository:")?
+                    .strip_suffix(":pull")
+                    .map(str::to_string)
+            })
+            .collect();
+        let token = format!("token-{}", self.tokens.len());
+        self.tokens.push((token.clone(), scope));
+        Response::new(200, format!(r#"{{"token":"{}","expires_in":300}}"#, token))
+            .header("Content-Type", "application/json")
+    }
+
+    fn tags(repository: &str, tags: &[Tag], url: &Url, page_size: Option<usize>) -> Response {
+        let mut names: Vec<&str> = tags.iter().map(|tag| tag.name.as_str()).collect();
+        names.sort_unstable();
+        let query = |key: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.to_string())
+        };
+        if let Some(last) = query("last") {
+            names.retain(|name| *name > last.as_str());
+        }
+        let size = query("n")
+            .and_then(|n| n.parse().ok())
+            .or(page_size)
+            .unwrap_or(names.len());
+        let next = names.len() > size;
+        names.truncate(size);
+        let body = serde_json::json!({ "name": repository, "tags": names }).to_string();
+        let mut response = Response::new(200, body).header("Content-Type", "application/json");
+        if next {
+            response = response.header(
+                "Link",
+                format!(
+                    r#"</v2/{}/tags/list?n={}&last={}>; rel="next""#,
+                    repository,
+                    size,
+                    names.last().unwrap()
+                ),
+            );
+        }
+        response
+    }
+}
+
+enum Action<'a> {
+    Tags,
+    Manifest(&'a str),
+    Blob(&'a str),
+}
@@ -0,0 +1,516 @@
+// End to end tests of checking images against a fake registry
+
+mod common;
+
+use std::collections::BTreeMap;
+
+use common::{
+    basic, config_digest, digest, layer_digest, local_image, platform_digest, Auth, FakeRegistry,
+    BASE_LAYER, CREATED, LAYER_SIZES,
+};
+use cup::{
+    config::{
+        Channel, ConfigRegex, Glob, PolicyConfig, RegistryConfig, VersionRange, VersionUpdateType,
+    },
+    get_updates,
+    registry::{check_auth, get_latest_digest, get_latest_tag, get_token},
+    structs::{
+        details::{ImageDetails, LabelChange, UpdateDetails},
+        platform::Platform,
+        update::UpdateInfo,
+    },
+    utils::request::Challenge,
+    Client, Image, Status, Version,
+};
+
+fn version(tag: &str) -> Version {
+    Version::from_tag(tag).unwrap().0
+}
+
+/// Test that paginated tag lists are followed to the end and only tags with the same format are compared
+#[tokio::test]
+async fn paginated_tags() {
+    let registry = FakeRegistry::builder()
+        .repository(
+            "library/postgres",
+            &[
+                "14",
+                "15",
+                "15.1",
+                "16",
+                "16.2",
+                "17",
+                "17-alpine",
+                "latest",
+            ],
+        )
+        .page_size(3)
+        .start()
+        .await;
+    let ctx = registry.context(RegistryConfig::default());
+    let client = Client::new(&ctx);
+
+    assert_eq!(check_auth(&registry.host, &ctx, &client).await, Ok(None));
+
+    let image = Image::from_reference(&registry.reference("library/postgres:16"));
+    let checked = get_latest_tag(&image, &version("16"), None, &ctx, &client, Vec::new()).await;
+    assert_eq!(checked.error, None);
+    assert_eq!(
+        checked.version_info.unwrap().latest_remote_tag,
+        Some(version("17"))
+    );
+    assert_eq!(
+        registry.requests()[1..],
+        [
+            "GET /v2/library/postgres/tags/list",
+            "GET /v2/library/postgres/tags/list?n=3&last=15.1",
+            "GET /v2/library/postgres/tags/list?n=3&last=17",
+        ]
+    );
+}
+
+/// Test that pre-releases are only considered for images following a channel that includes them
+#[tokio::test]
+async fn channels() {
+    let registry = FakeRegistry::builder()
+        .repository(
+            "sergi0g/cup",
+            &[
+                "1.0.0",
+                "1.1.0-alpha.1",
+                "1.1.0-beta.1",
+                "1.1.0-beta.2",
+                "1.1.0-rc.1",
+                "1.1.0-alpine",
+            ],
+        )
+        .start()
+        .await;
+    let image = Image::from_reference(&registry.reference("sergi0g/cup:1.0.0"));
+    for (channel, expected) in [
+        (None, "1.0.0"),
+        (Some(Channel::Stable), "1.0.0"),
+        (Some(Channel::PreRelease), "1.1.0-rc.1"),
+        (Some(Channel::from(String::from("beta"))), "1.1.0-beta.2"),
+    ] {
+        let mut ctx = registry.context(RegistryConfig::default());
+        if let Some(channel) = channel {
+            ctx.config
+                .images
+                .channels
+                .insert(registry.reference("sergi0g/"), channel);
+        }
+        let client = Client::new(&ctx);
+        let checked =
+            get_latest_tag(&image, &version("1.0.0"), None, &ctx, &client, Vec::new()).await;
+        assert_eq!(
+            checked.version_info.unwrap().latest_remote_tag,
+            Some(version(expected))
+        );
+    }
+}
+
+/// Test that policies matching an image limit the versions it's updated to
+#[tokio::test]
+async fn policies() {
+    let registry = FakeRegistry::builder()
+        .repository(
+            "library/postgres",
+            &["14.1", "14.2", "15.0", "16.3", "17.0", "17.1"],
+        )
+        .repository(
+            "example/app",
+            &["r12_4-slim", "r12_5-slim", "r12_6-slim", "r12_6"],
+        )
+        .start()
+        .await;
+    let image = Image::from_reference(&registry.reference("library/postgres:14.1"));
+    let policy = |image: &str| PolicyConfig {
+        image: Some(Glob::from(registry.reference(image))),
+        ..Default::default()
+    };
+    for (policy, expected) in [
+        (policy("library/postgres:*"), "17.1"),
+        (policy("library/mysql:*"), "17.1"),
+        (
+            PolicyConfig {
+                versions: Some(VersionRange::try_from(String::from(">=14,<17")).unwrap()),
+                ..policy("library/postgres:*")
+            },
+            "16.3",
+        ),
+        (
+            PolicyConfig {
+                update_types: vec![VersionUpdateType::Minor, VersionUpdateType::Patch],
+                ..policy("*/postgres:1?.*")
+            },
+            "14.2",
+        ),
+        (
+            PolicyConfig {
+                update_types: vec![VersionUpdateType::Patch],
+                ignore: vec![String::from("14.2")],
+                ..policy("library/postgres:*")
+            },
+            "14.1",
+        ),
+    ] {
+        let mut ctx = registry.context(RegistryConfig::default());
+        ctx.config.images.policies.push(policy);
+        let client = Client::new(&ctx);
+        let checked =
+            get_latest_tag(&image, &version("14.1"), None, &ctx, &client, Vec::new()).await;
+        assert_eq!(
+            checked.version_info.unwrap().latest_remote_tag,
+            Some(version(expected))
+        );
+    }
+
+    // Ignored versions are parsed like the image's tags
+    let mut image = Image::from_reference(&registry.reference("example/app:r12_4-slim"));
+    for ignore in ["r12_6-slim", "12.6"] {
+        let mut ctx = registry.context(RegistryConfig::default());
+        ctx.config.images.policies.push(PolicyConfig {
+            tag_regex: Some(
+                ConfigRegex::try_from(String::from("^r(?P<major>[0-9]+)_(?P<minor>[0-9]+)-slim$"))
+                    .unwrap(),
+            ),
+            ignore: vec![ignore.to_string()],
+            ..policy("example/app:*")
+        });
+        let client = Client::new(&ctx);
+        let (base, format_str) = ctx
+            .config
+            .images
+            .parse_tag(&image.reference, "r12_4-slim")
+            .unwrap();
+        image.version_info.as_mut().unwrap().format_str = format_str;
+        let checked = get_latest_tag(&image, &base, None, &ctx, &client, Vec::new()).await;
+        assert_eq!(
+            checked.version_info.unwrap().latest_remote_tag,
+            Some(version("12.5"))
+        );
+    }
+}
+
+/// Test that tokens are requested for the repositories being checked and that a missing token is replaced
+#[tokio::test]
+async fn token_authentication() {
+    let registry = FakeRegistry::builder()
+        .auth(Auth::Bearer)
+        .repository("sergi0g/cup", &["v3.0.0", "v3.1.0"])
+        .start()
+        .await;
+    let ctx = registry.context(RegistryConfig::default());
+    let client = Client::new(&ctx);
+
+    let auth_url = format!("{}/token?&service=fake", registry.url);
+    assert_eq!(
+        check_auth(&registry.host, &ctx, &client).await,
+        Ok(Some(Challenge::Bearer(auth_url.clone())))
+    );
+    let (token, _) = get_token(&[String::from("sergi0g/cup")], &auth_url, &None, &client)
+        .await
+        .unwrap();
+    assert_eq!(token, "token-0");
+
+    let image = Image::from_reference(&registry.reference("sergi0g/cup:v3.0.0"));
+    for authorization in [Some("Bearer token-0"), None] {
+        let checked = get_latest_tag(
+            &image,
+            &version("v3.0.0"),
+            authorization,
+            &ctx,
+            &client,
+            Vec::new(),
+        )
+        .await;
+        assert_eq!(checked.has_update(), Status::UpdateMinor);
+    }
+    // The unauthorized request led to a new token
+    assert!(registry.requests().contains(&String::from(
+        "GET /token?&service=fake&scope=repository:sergi0g/cup:pull"
+    )));
+}
+
+/// Test that manifest lists are compared by the image for the local platform, so changes to other platforms' images aren't reported as updates
+#[tokio::test]
+async fn manifest_lists() {
+    let registry = FakeRegistry::builder()
+        .repository("library/nginx", &["latest"])
+        .manifest_list("library/nginx", "latest", "sha256:index")
+        .start()
+        .await;
+    let ctx = registry.context(RegistryConfig::default());
+    let client = Client::new(&ctx);
+    let reference = registry.reference("library/nginx:latest");
+    let arm64 = Some(Platform::new("linux", "arm64", None));
+    let current_id = config_digest(&platform_digest("sha256:index", "arm64"));
+
+    let check = |local_digest: &str, image_id: Option<&str>| {
+        let image = local_image(&reference, local_digest, image_id, arm64.clone());
+        let (ctx, client) = (&ctx, &client);
+        async move { get_latest_digest(&image, None, ctx, client).await }
+    };
+
+    // The list didn't change, so it isn't downloaded
+    let checked = check("sha256:index", Some("sha256:old-config")).await;
+    assert_eq!(checked.has_update(), Status::UpToDate);
+    assert!(checked.digest_info.unwrap().platforms.is_empty());
+
+    // Only the images for other platforms changed
+    let checked = check("sha256:old", Some(&current_id)).await;
+    assert_eq!(checked.has_update(), Status::UpToDate);
+    let digest_info = checked.digest_info.unwrap();
+    assert_eq!(digest_info.platform_updated, Some(false));
+    assert_eq!(digest_info.platforms.len(), 2);
+
+    let update = check("sha256:old", Some("sha256:old-config"))
+        .await
+        .to_update();
+    assert_eq!(update.status, Status::UpdateAvailable);
+    let UpdateInfo::Digest(info) = update.result.info else {
+        panic!("Expected digest update info");
+    };
+    assert_eq!(info.remote_digest.as_deref(), Some("sha256:index"));
+    assert_eq!(info.platform.as_deref(), Some("linux/arm64/v8"));
+    assert_eq!(info.platform_updated, Some(true));
+    assert_eq!(
+        info.platforms
+            .iter()
+            .map(|platform| (platform.platform.as_str(), platform.digest.as_str()))
+            .collect::<Vec<_>>(),
+        [
+            ("linux/amd64", "sha256:index-amd64"),
+            ("linux/arm64/v8", "sha256:index-arm64")
+        ]
+    );
+
+    // Without the local image's ID there's no telling, so any change is an update
+    let checked = check("sha256:old", None).await;
+    assert_eq!(checked.has_update(), Status::UpdateAvailable);
+}
+
+/// Test that only manifest GETs are charged to a registry's rate limit budget and that images which would need more than is left are deferred
+#[tokio::test]
+async fn rate_limit_budget() {
+    let registry = FakeRegistry::builder()
+        .repository("library/nginx", &["latest"])
+        .manifest_list("library/nginx", "latest", "sha256:index")
+        .start()
+        .await;
+    let ctx = registry.context(RegistryConfig::default());
+    let client = Client::new(&ctx);
+    client.set_budget(&registry.host, 1);
+    let reference = registry.reference("library/nginx:latest");
+    let check = |local_digest: &str| {
+        let image = local_image(
+            &reference,
+            local_digest,
+            Some("sha256:old-config"),
+            Some(Platform::new("linux", "arm64", None)),
+        );
+        let (ctx, client) = (&ctx, &client);
+        async move { get_latest_digest(&image, None, ctx, client).await }
+    };
+
+    // HEAD requests are free
+    for _ in 0..3 {
+        assert_eq!(check("sha256:index").await.has_update(), Status::UpToDate);
+    }
+
+    // Finding out whether the image for our platform changed takes 2 GETs, but only one is left
+    assert_eq!(check("sha256:old").await.has_update(), Status::Deferred);
+    let gets = registry
+        .requests()
+        .into_iter()
+        .filter(|request| request.starts_with("GET /v2/library/nginx/manifests/"))
+        .count();
+    assert_eq!(gets, 1);
+}
+
+/// Test that digest updates describe what changed, reusing the platform's manifest of manifest lists
+#[tokio::test]
+async fn update_details() {
+    let registry = FakeRegistry::builder()
+        .repository("library/redis", &["latest"])
+        .repository("library/nginx", &["latest"])
+        .manifest_list("library/nginx", "latest", "sha256:index")
+        .start()
+        .await;
+    let mut ctx = registry.context(RegistryConfig::default());
+    ctx.config.details = true;
+    let client = Client::new(&ctx);
+    let revision = "org.opencontainers.image.revision";
+
+    let mut image = local_image(
+        &registry.reference("library/redis:latest"),
+        "sha256:old",
+        None,
+        None,
+    );
+    image.digest_info.as_mut().unwrap().local_details = Some(ImageDetails {
+        created: Some(String::from("2024-12-01T00:00:00Z")),
+        layers: vec![BASE_LAYER.to_string(), layer_digest("sha256:old")],
+        layer_sizes: Vec::new(),
+        labels: BTreeMap::from([(revision.to_string(), String::from("sha256:old"))]),
+    });
+    let update = get_latest_digest(&image, None, &ctx, &client)
+        .await
+        .to_update();
+    let UpdateInfo::Digest(info) = update.result.info else {
+        panic!("Expected digest update info");
+    };
+    let remote_digest = digest("library/redis", "latest");
+    assert_eq!(
+        info.details,
+        Some(UpdateDetails {
+            local_created: Some(String::from("2024-12-01T00:00:00Z")),
+            remote_created: Some(CREATED.to_string()),
+            local_layers: Some(2),
+            remote_layers: 2,
+            new_layers: Some(1),
+            size: LAYER_SIZES.iter().sum(),
+            download_size: Some(LAYER_SIZES[1]),
+            labels: vec![LabelChange {
+                name: revision.to_string(),
+                old: Some(String::from("sha256:old")),
+                new: Some(remote_digest.clone()),
+            }],
+        })
+    );
+    // The manifest and config are fetched by digest, so they're the ones of the update
+    assert!(registry.requests().ends_with(&[
+        format!("GET /v2/library/redis/manifests/{}", remote_digest),
+        format!(
+            "GET /v2/library/redis/blobs/{}",
+            config_digest(&remote_digest)
+        ),
+    ]));
+
+    let image = local_image(
+        &registry.reference("library/nginx:latest"),
+        "sha256:old",
+        Some("sha256:old-config"),
+        Some(Platform::new("linux", "arm64", None)),
+    );
+    let checked = get_latest_digest(&image, None, &ctx, &client).await;
+    let details = checked.digest_info.unwrap().remote_details.unwrap();
+    let manifest = platform_digest("sha256:index", "arm64");
+    assert_eq!(
+        details.layers,
+        [BASE_LAYER.to_string(), layer_digest(&manifest)]
+    );
+    assert_eq!(
+        registry
+            .requests()
+            .iter()
+            .filter(|request| request.contains(&manifest))
+            .count(),
+        2 // The manifest and its config
+    );
+
+    // Up to date images aren't described
+    let image = local_image(
+        &registry.reference("library/redis:latest"),
+        &remote_digest,
+        None,
+        None,
+    );
+    let requests = registry.requests().len();
+    let checked = get_latest_digest(&image, None, &ctx, &client).await;
+    assert_eq!(checked.digest_info.unwrap().remote_details, None);
+    assert_eq!(registry.requests().len(), requests + 1);
+}
+
+/// Test checking images in a registry requiring basic authentication
+#[tokio::test]
+async fn basic_authentication() {
+    let credentials = basic("cup", "secret");
+    let registry = FakeRegistry::builder()
+        .auth(Auth::Basic(credentials.clone()))
+        .repository("library/alpine", &["3.19", "3.20"])
+        .start()
+        .await;
+    let references = Some(vec![registry.reference("library/alpine:3.19")]);
+
+    let ctx = registry.context(RegistryConfig {
+        authentication: Some(credentials),
+        ..Default::default()
+    });
+    let updates = get_updates(&references, false, &ctx).await.unwrap();
+    assert_eq!(updates[0].status, Status::UpdateMinor);
+
+    let ctx = registry.context(RegistryConfig {
+        authentication: Some(basic("cup", "wrong")),
+        ..Default::default()
+    });
+    let updates = get_updates(&references, false, &ctx).await.unwrap();
+    assert_eq!(
+        updates[0].result.error_code.as_deref(),
+        Some("unauthorized")
+    );
+}
+
+/// Test that failing requests make the image's status unknown with the reason, without affecting other images, and that transient failures are retried
+#[tokio::test]
+async fn failures() {
+    let registry = FakeRegistry::builder()
+        .repository("working/app", &["1.0", "1.1"])
+        .repository("forbidden/app", &["1.0"])
+        .repository("down/app", &["1.0"])
+        .repository("limited/app", &["1.0", "2.0"])
+        .repository("empty/app", &["latest"])
+        .repository("invalid/app", &["1.0"])
+        .fail("/v2/forbidden/", 403)
+        .fail("/v2/invalid/", 400)
+        .fail("/v2/down/", 503)
+        .fail_times("/v2/limited/", 429, 1)
+        .start()
+        .await;
+    let ctx = registry.context(RegistryConfig::default());
+    let images = [
+        "working/app:1.0",
+        "missing/app:1.0",
+        "forbidden/app:1.0",
+        "down/app:1.0",
+        "limited/app:1.0",
+        "empty/app:1.0",
+        "invalid/app:1.0",
+        "working/app:latest",
+    ];
+    let references = Some(
+        images
+            .iter()
+            .map(|image| registry.reference(image))
+            .collect(),
+    );
+
+    let updates = get_updates(&references, false, &ctx).await.unwrap();
+    let result = |image: &str| {
+        let update = updates
+            .iter()
+            .find(|update| update.reference == registry.reference(image))
+            .unwrap();
+        (update.status.clone(), update.result.error_code.as_deref())
+    };
+    assert_eq!(result("working/app:1.0"), (Status::UpdateMinor, None));
+    assert_eq!(result("limited/app:1.0"), (Status::UpdateMajor, None));
+    for (image, code) in [
+        ("missing/app:1.0", "not_found"),
+        ("forbidden/app:1.0", "forbidden"),
+        ("down/app:1.0", "registry_unavailable"),
+        ("empty/app:1.0", "no_version_tags"),
+        ("invalid/app:1.0", "unexpected_status"),
+        ("working/app:latest", "unsupported_tag"),
+    ] {
+        let (status, error_code) = result(image);
+        assert!(matches!(status, Status::Unknown(_)), "{}", image);
+        assert_eq!(error_code, Some(code), "{}", image);
+    }
+    // Images which can't be checked aren't requested
+    assert!(!registry
+        .requests()
+        .iter()
+        .any(|request| request.contains("working/app/manifests/latest")));
+}
@@ -16,7 +16,9 @@ const STATUSES = [
   "Minor update",
   "Patch update",
   "Digest update",
+  "Acknowledged",
   "Up to date",
+  "Deferred",
   "Unknown",
 ];
 
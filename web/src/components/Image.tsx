@@ -6,13 +6,15 @@ import {
   DialogTitle,
 } from "@headlessui/react";
 import { WithTooltip } from "./ui/Tooltip";
-import type { Image } from "../types";
+import type { Image, UpdateDetails } from "../types";
 import { theme } from "../theme";
 import { CodeBlock } from "./CodeBlock";
 import {
+  BellOff,
   Box,
   CircleArrowUp,
   CircleCheck,
+  Clock,
   HelpCircle,
   Timer,
   TriangleAlert,
@@ -146,6 +148,21 @@ export default function Image({ data }: { data: Image }) {
                     {data.result.error}
                   </div>
                 )}
+                {data.acknowledgement?.until &&
+                  data.acknowledgement.until > Date.now() && (
+                    <div className="flex items-center gap-3">
+                      <BellOff className="size-6 shrink-0 text-gray-500" />
+                      <span>
+                        Snoozed until{" "}
+                        <b>
+                          {new Date(
+                            data.acknowledgement.until,
+                          ).toLocaleString()}
+                        </b>
+                      </span>
+                    </div>
+                  )}
+                {data.result.has_update && <Acknowledge data={data} />}
                 {data.result.has_update && (
                   <div className="mt-4 flex flex-col gap-1">
                     Pull command
@@ -154,6 +171,46 @@ export default function Image({ data }: { data: Image }) {
                     </CodeBlock>
                   </div>
                 )}
+                {data.result.info?.type == "version" &&
+                  data.result.info.releases &&
+                  data.result.info.releases.length > 0 && (
+                    <div className="flex flex-col gap-1">
+                      Releases
+                      <ul className="flex flex-col gap-1">
+                        {data.result.info.releases.map((release) => (
+                          <li
+                            key={release.tag}
+                            className="flex items-center gap-2"
+                          >
+                            <a
+                              href={release.url}
+                              target="_blank"
+                              rel="noopener noreferrer"
+                              className="hover:underline"
+                            >
+                              {release.title}
+                            </a>
+                            {release.breaking && (
+                              <span className="flex items-center gap-1 text-red-500">
+                                <TriangleAlert className="size-4 shrink-0" />
+                                Breaking
+                              </span>
+                            )}
+                          </li>
+                        ))}
+                      </ul>
+                      {data.result.info.releases_url && (
+                        <a
+                          href={data.result.info.releases_url}
+                          target="_blank"
+                          rel="noopener noreferrer"
+                          className={`w-fit text-${theme}-500 hover:underline`}
+                        >
+                          All releases
+                        </a>
+                      )}
+                    </div>
+                  )}
                 <div className="flex flex-col gap-1">
                   {data.result.info?.type == "digest" && (
                     <>
@@ -171,6 +228,25 @@ export default function Image({ data }: { data: Image }) {
                           </CodeBlock>
                         </div>
                       )}
+                      {data.result.info.platform && (
+                        <div className="flex flex-col gap-1">
+                          Remote digest for {data.result.info.platform}
+                          <CodeBlock enableCopy>
+                            {data.result.info.platforms?.find(
+                              (platform) =>
+                                platform.platform == data.result.info?.platform,
+                            )?.digest ?? "Not available"}
+                          </CodeBlock>
+                        </div>
+                      )}
+                      {data.result.info.details && (
+                        <div className="flex flex-col gap-1">
+                          What changed
+                          <CodeBlock>
+                            {formatDetails(data.result.info.details)}
+                          </CodeBlock>
+                        </div>
+                      )}
                     </>
                   )}
                 </div>
@@ -183,6 +259,122 @@ export default function Image({ data }: { data: Image }) {
   );
 }
 
+function Acknowledge({ data }: { data: Image }) {
+  const [disabled, setDisabled] = useState(false);
+  const [until, setUntil] = useState("");
+  const send = (endpoint: string, body: object) => {
+    setDisabled(true);
+    const request = new XMLHttpRequest();
+    request.onload = () => {
+      if (request.status === 200) {
+        window.location.reload();
+      } else {
+        // The token is asked for again if it's missing or wrong
+        if (request.status === 401) {
+          const token = window.prompt("Enter the token set in `apply.token`");
+          if (token) {
+            localStorage.setItem("token", token);
+            send(endpoint, body);
+            return;
+          }
+        }
+        setDisabled(false);
+      }
+    };
+    request.open(
+      "POST",
+      process.env.NODE_ENV === "production"
+        ? `./api/v3/${endpoint}`
+        : `http://${window.location.hostname}:8000/api/v3/${endpoint}`,
+    );
+    request.setRequestHeader("Content-Type", "application/json");
+    request.setRequestHeader(
+      "Authorization",
+      `Bearer ${localStorage.getItem("token") ?? ""}`,
+    );
+    request.send(
+      JSON.stringify({
+        reference: data.reference,
+        server: data.server,
+        ...body,
+      }),
+    );
+  };
+  const buttonClass = `rounded-md border border-${theme}-300 px-3 py-1 transition-colors duration-200 hover:text-black disabled:opacity-50 dark:border-${theme}-700 dark:hover:text-white`;
+  return (
+    <div className="mt-4 flex flex-wrap items-center gap-2">
+      {data.acknowledgement ? (
+        <button
+          className={buttonClass}
+          disabled={disabled}
+          onClick={() => send("unacknowledge", {})}
+        >
+          Unacknowledge
+        </button>
+      ) : (
+        <>
+          <button
+            className={buttonClass}
+            disabled={disabled}
+            onClick={() => send("acknowledge", {})}
+          >
+            Acknowledge
+          </button>
+          <input
+            type="date"
+            value={until}
+            onChange={(event) => setUntil(event.target.value)}
+            className={`rounded-md border border-${theme}-300 bg-transparent px-3 py-1 dark:border-${theme}-700`}
+          />
+          <button
+            className={buttonClass}
+            disabled={disabled || !until}
+            onClick={() => send("snooze", { until })}
+          >
+            Snooze
+          </button>
+        </>
+      )}
+    </div>
+  );
+}
+
+function formatDetails(details: UpdateDetails): string {
+  const formatSize = (bytes: number) =>
+    bytes < 1000
+      ? `${bytes} B`
+      : bytes < 1000 ** 2
+        ? `${(bytes / 1000).toFixed(1)} kB`
+        : bytes < 1000 ** 3
+          ? `${(bytes / 1000 ** 2).toFixed(1)} MB`
+          : `${(bytes / 1000 ** 3).toFixed(1)} GB`;
+  const lines: string[] = [];
+  if (details.remote_created) {
+    lines.push(
+      `Created: ${details.local_created ? `${details.local_created} → ` : ""}${details.remote_created}`,
+    );
+  }
+  if (
+    details.local_layers !== null &&
+    details.new_layers !== null &&
+    details.download_size !== null
+  ) {
+    lines.push(
+      `Layers: ${details.local_layers} → ${details.remote_layers} (${details.new_layers} new, ${formatSize(details.download_size)} to download)`,
+    );
+  } else {
+    lines.push(
+      `Layers: ${details.remote_layers} (${formatSize(details.size)})`,
+    );
+  }
+  for (const label of details.labels) {
+    lines.push(
+      `${label.name}: ${label.old ?? "(none)"} → ${label.new ?? "(none)"}`,
+    );
+  }
+  return lines.join("\n");
+}
+
 function getInfo(data: Image): {
   color: string;
   icon: typeof HelpCircle;
@@ -196,6 +388,18 @@ function getInfo(data: Image): {
         icon: HelpCircle,
         description,
       };
+    case "Deferred":
+      return {
+        color: "text-gray-500",
+        icon: Clock,
+        description,
+      };
+    case "Acknowledged":
+      return {
+        color: "text-gray-500",
+        icon: BellOff,
+        description,
+      };
     case "Up to date":
       return {
         color: "text-green-500",
@@ -50,7 +50,7 @@ export default function Statistic({
 
 function getUpdatesAvailableIcon(metrics: Data["metrics"]) {
   const filteredMetrics = Object.entries(metrics).filter(
-    ([key]) => !metricsToShow.includes(key),
+    ([key]) => !metricsToShow.includes(key) && key !== "acknowledged",
   );
   const maxMetric = filteredMetrics.reduce((max, current) => {
     if (Number(current[1]) > Number(max[1])) {
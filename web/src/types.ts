@@ -7,12 +7,39 @@ export interface Data {
     minor_updates: number;
     patch_updates: number;
     other_updates: number;
+    acknowledged?: number;
     unknown: number;
   };
   images: Image[];
+  projects?: Project[];
+  rate_limits?: Record<string, RateLimit>;
   last_updated: string;
 }
 
+export interface RateLimit {
+  limit: number;
+  remaining: number;
+}
+
+export interface Project {
+  server: string | null;
+  name: string;
+  config_files: string[];
+  services: Record<string, string>;
+}
+
+interface ComposeService {
+  project: string;
+  service: string;
+  config_files: string[];
+}
+
+interface Workload {
+  namespace: string;
+  kind: string;
+  name: string;
+}
+
 export interface Image {
   reference: string;
   parts: {
@@ -25,10 +52,21 @@ export interface Image {
     has_update: boolean | null;
     info: VersionInfo | DigestInfo | null;
     error: string | null;
+    error_code?: string | null;
+    deferred?: boolean;
   };
   time: number;
   server: string | null;
   in_use: boolean | null;
+  compose?: ComposeService[];
+  workloads?: Workload[];
+  acknowledgement?: Acknowledgement | null;
+  first_seen?: string | null;
+}
+
+interface Acknowledgement {
+  version: string | null;
+  until: number | null;
 }
 
 interface VersionInfo {
@@ -37,12 +75,47 @@ interface VersionInfo {
   new_tag: string;
   current_version: string;
   new_version: string;
+  releases?: Release[];
+  releases_url?: string | null;
+}
+
+interface Release {
+  tag: string;
+  title: string;
+  url: string;
+  breaking: boolean;
 }
 
 interface DigestInfo {
   type: "digest";
   local_digests: string[];
   remote_digest: string;
+  platform?: string | null;
+  platform_updated?: boolean | null;
+  platforms?: PlatformDigest[];
+  details?: UpdateDetails | null;
+}
+
+export interface UpdateDetails {
+  local_created: string | null;
+  remote_created: string | null;
+  local_layers: number | null;
+  remote_layers: number;
+  new_layers: number | null;
+  size: number;
+  download_size: number | null;
+  labels: LabelChange[];
+}
+
+interface LabelChange {
+  name: string;
+  old: string | null;
+  new: string | null;
+}
+
+interface PlatformDigest {
+  platform: string;
+  digest: string;
 }
 
 export interface Filters {
@@ -53,7 +126,9 @@ export interface Filters {
     | "Minor update"
     | "Patch update"
     | "Digest update"
+    | "Acknowledged"
     | "Up to date"
+    | "Deferred"
     | "Unknown"
   )[];
 }
@@ -7,12 +7,14 @@ export function cn(...inputs: ClassValue[]) {
 }
 
 export function getDescription(image: Image) {
+  if (image.result.deferred) return "Deferred";
   switch (image.result.has_update) {
     case null:
       return "Unknown";
     case false:
       return "Up to date";
     case true:
+      if (image.acknowledgement) return "Acknowledged";
       if (image.result.info?.type === "version") {
         switch (image.result.info.version_update_type) {
           case "major":